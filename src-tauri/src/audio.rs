@@ -1,182 +1,33 @@
 use anyhow::{Result, anyhow};
-use cpal::{StreamConfig, SampleRate};
+use cpal::{FromSample, Sample, SampleFormat, SampleRate, SizedSample, StreamConfig};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use hound::{WavWriter, WavSpec};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::io::BufWriter;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::io::{BufWriter, Seek, Write};
 use std::fs::File;
 use tauri::{AppHandle, Emitter};
 use tokio::sync::mpsc;
 use tokio::time::{interval, Duration};
 use std::collections::HashMap;
 use once_cell::sync::Lazy;
+use serde::Serialize;
+use crate::meter::{InputMeter, InputWatch, Levels};
+use crate::resample::{Resampler, remap_channels};
+use crate::settings::RecordingFormat;
 
-// Simple recorder that doesn't store the stream to avoid Send issues
-pub struct AudioRecorder {
-    session_id: String,
-    output_path: PathBuf,
-    is_recording: Arc<AtomicBool>,
-}
-
-impl AudioRecorder {
-    pub fn new(session_id: String, session_dir: PathBuf) -> Result<Self> {
-        let output_path = session_dir.join("audio.wav");
-        
-        Ok(AudioRecorder {
-            session_id,
-            output_path,
-            is_recording: Arc::new(AtomicBool::new(false)),
-        })
-    }
-
-    pub async fn start_recording(&self, app_handle: AppHandle) -> Result<()> {
-        if self.is_recording.load(Ordering::Relaxed) {
-            return Err(anyhow!("Already recording"));
+/// Iterate over a WAV file's samples as f32 in -1.0..=1.0, whatever format it was written in.
+pub fn wav_samples_f32<R: std::io::Read>(
+    reader: &mut hound::WavReader<R>,
+) -> Box<dyn Iterator<Item = hound::Result<f32>> + '_> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => Box::new(reader.samples::<f32>()),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+            Box::new(reader.samples::<i32>().map(move |s| s.map(|s| s as f32 * scale)))
         }
-
-        println!("🎙️ Starting audio recording for session: {}", self.session_id);
-
-        // Get the default input device
-        let host = cpal::default_host();
-        let device = host.default_input_device()
-            .ok_or_else(|| anyhow!("No default input device available"))?;
-
-        println!("🎙️ Using audio device: {}", device.name().unwrap_or_default());
-
-        // Get supported configurations and pick the best one
-        let mut supported_configs = device.supported_input_configs()
-            .map_err(|e| anyhow!("Failed to get supported configs: {}", e))?;
-        
-        let supported_config = supported_configs
-            .find(|config| {
-                // Prefer configs that support mono and reasonable sample rates
-                config.channels() >= 1 && 
-                config.min_sample_rate().0 <= 44100 && 
-                config.max_sample_rate().0 >= 16000
-            })
-            .ok_or_else(|| anyhow!("No suitable audio configuration found"))?;
-
-        println!("🎙️ Using config: channels={}, sample_rate_range={:?}-{:?}", 
-                supported_config.channels(),
-                supported_config.min_sample_rate(),
-                supported_config.max_sample_rate());
-
-            // Use the device's preferred sample rate for best compatibility
-    let sample_rate = supported_config.max_sample_rate().0;
-    
-    println!("🎙️ Selected sample rate: {}Hz (device supports {}-{}Hz)", 
-             sample_rate,
-             supported_config.min_sample_rate().0,
-             supported_config.max_sample_rate().0);
-
-        let config = StreamConfig {
-            channels: 1.min(supported_config.channels()), // Mono if possible, otherwise use minimum
-            sample_rate: SampleRate(sample_rate),
-            buffer_size: cpal::BufferSize::Default,
-        };
-
-        println!("🎙️ Final config: channels={}, sample_rate={}", config.channels, config.sample_rate.0);
-
-        // Create WAV writer - always mono since we convert multi-channel to mono
-        let spec = WavSpec {
-            channels: 1, // Always mono output
-            sample_rate: config.sample_rate.0,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
-
-        let writer = Arc::new(Mutex::new(Some(WavWriter::create(&self.output_path, spec)?)));
-
-        // Set up level monitoring channel
-        let (level_tx, mut level_rx) = mpsc::unbounded_channel::<f32>();
-
-        // Spawn task to emit audio level events
-        let app_handle_clone = app_handle.clone();
-        let session_id_clone = self.session_id.clone();
-        tokio::spawn(async move {
-            let mut interval = interval(Duration::from_millis(100)); // 10Hz updates
-            while let Ok(level) = level_rx.try_recv() {
-                interval.tick().await;
-                let _ = app_handle_clone.emit("polka://audio-level", serde_json::json!({
-                    "session_id": session_id_clone,
-                    "level": level
-                }));
-            }
-        });
-
-        // Create the audio stream
-        let writer_clone = Arc::clone(&writer);
-        let is_recording_clone = Arc::clone(&self.is_recording);
-        let channels = config.channels;
-
-        let stream = device.build_input_stream(
-            &config,
-            move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                if !is_recording_clone.load(Ordering::Relaxed) {
-                    return;
-                }
-
-                // Calculate RMS level for VU meter (use all channels for level calculation)
-                let rms = calculate_rms(data);
-                let _ = level_tx.send(rms);
-
-                // Write audio data to WAV file
-                if let Ok(mut writer_guard) = writer_clone.lock() {
-                    if let Some(ref mut writer) = *writer_guard {
-                        if channels == 1 {
-                            // Mono: write samples directly
-                            for &sample in data {
-                                let sample_i16 = (sample * i16::MAX as f32) as i16;
-                                let _ = writer.write_sample(sample_i16);
-                            }
-                        } else {
-                            // Multi-channel: convert to mono by averaging channels
-                            for chunk in data.chunks(channels as usize) {
-                                let mono_sample: f32 = chunk.iter().sum::<f32>() / chunk.len() as f32;
-                                let sample_i16 = (mono_sample * i16::MAX as f32) as i16;
-                                let _ = writer.write_sample(sample_i16);
-                            }
-                        }
-                    }
-                }
-            },
-            move |err| {
-                eprintln!("🎙️ Audio stream error: {}", err);
-            },
-            None
-        )?;
-
-        // Start the stream and keep it alive by leaking it (simple approach)
-        stream.play()?;
-        std::mem::forget(stream); // Keep stream alive
-        self.is_recording.store(true, Ordering::Relaxed);
-
-        println!("🎙️ Audio recording started successfully");
-        Ok(())
-    }
-
-    pub async fn stop_recording(&self) -> Result<()> {
-        if !self.is_recording.load(Ordering::Relaxed) {
-            return Err(anyhow!("Not currently recording"));
-        }
-
-        println!("🎙️ Stopping audio recording for session: {}", self.session_id);
-
-        self.is_recording.store(false, Ordering::Relaxed);
-
-        // Note: In this simplified implementation, we leak the stream to avoid Send issues
-        // The stream will be cleaned up when the process exits
-        // In a production app, you'd want a more sophisticated approach
-
-        println!("🎙️ Audio recording stopped successfully");
-        println!("🎙️ Audio file should be saved to: {:?}", self.output_path);
-        Ok(())
-    }
-
-    pub fn is_recording(&self) -> bool {
-        self.is_recording.load(Ordering::Relaxed)
     }
 }
 
@@ -235,6 +86,15 @@ static AUDIO_SEGMENTS: Lazy<Mutex<HashMap<String, Vec<PathBuf>>>> =
 static SEGMENT_COUNTERS: Lazy<Mutex<HashMap<String, usize>>> = 
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Format every segment of a session's recording is written in
+static RECORDING_SPECS: Lazy<Mutex<HashMap<String, WavSpec>>> = 
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Frames in the session's recording so far, counting earlier recordings and
+// leaving out pauses
+static RECORDED_FRAMES: Lazy<Mutex<HashMap<String, Arc<AtomicU64>>>> = 
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
 pub fn is_recording(session_id: &str) -> bool {
     let sessions = RECORDING_SESSIONS.lock().unwrap();
     if let Some(flag) = sessions.get(session_id) {
@@ -244,6 +104,14 @@ pub fn is_recording(session_id: &str) -> bool {
     }
 }
 
+/// Length of the session's recording in milliseconds, exact to the frame,
+/// or `None` if it isn't recording.
+pub fn recorded_duration_ms(session_id: &str) -> Option<u64> {
+    let sample_rate = RECORDING_SPECS.lock().unwrap().get(session_id)?.sample_rate;
+    let frames = RECORDED_FRAMES.lock().unwrap().get(session_id)?.load(Ordering::Relaxed);
+    Some(frames * 1000 / sample_rate.max(1) as u64)
+}
+
 pub fn is_paused(session_id: &str) -> bool {
     let paused = PAUSED_SESSIONS.lock().unwrap();
     if let Some(flag) = paused.get(session_id) {
@@ -253,7 +121,89 @@ pub fn is_paused(session_id: &str) -> bool {
     }
 }
 
-pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_handle: AppHandle) -> Result<()> {
+/// A microphone as shown in the device picker.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    /// Most channels any of the device's configurations offers.
+    pub channels: u16,
+    /// Common sample rates the device can capture at, ascending.
+    pub sample_rates: Vec<u32>,
+}
+
+// Rates worth offering when a device reports a continuous range
+const COMMON_SAMPLE_RATES: &[u32] = &[8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000];
+
+pub fn list_input_devices() -> Result<Vec<InputDeviceInfo>> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+
+    let devices = host.input_devices()
+        .map_err(|e| anyhow!("Failed to list input devices: {}", e))?;
+
+    let mut result = Vec::new();
+    for device in devices {
+        let Ok(name) = device.name() else { continue };
+        // Devices can vanish between listing and querying them
+        let Ok(configs) = device.supported_input_configs() else { continue };
+
+        let mut channels = 0;
+        let mut sample_rates = Vec::new();
+        for config in configs {
+            channels = channels.max(config.channels());
+            let (min, max) = (config.min_sample_rate().0, config.max_sample_rate().0);
+            sample_rates.extend(COMMON_SAMPLE_RATES.iter().copied().filter(|rate| (min..=max).contains(rate)));
+            // Fixed-rate devices may use a rate that isn't in the list
+            sample_rates.push(min);
+            sample_rates.push(max);
+        }
+        sample_rates.sort_unstable();
+        sample_rates.dedup();
+
+        result.push(InputDeviceInfo {
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+            channels,
+            sample_rates,
+        });
+    }
+
+    Ok(result)
+}
+
+// The named input device, or the system default when no name is given
+fn open_input_device(host: &cpal::Host, device_name: Option<&str>) -> Result<cpal::Device> {
+    let Some(device_name) = device_name else {
+        return host.default_input_device()
+            .ok_or_else(|| anyhow!("No default input device available. Please check microphone permissions in System Preferences > Security & Privacy > Privacy > Microphone"));
+    };
+
+    let devices = host.input_devices()
+        .map_err(|e| anyhow!("Failed to list input devices: {}", e))?;
+    for device in devices {
+        if device.name().map(|name| name == device_name).unwrap_or(false) {
+            return Ok(device);
+        }
+    }
+
+    Err(anyhow!("Input device \"{}\" is not available. It may have been unplugged; reconnect it or choose another microphone in Settings.", device_name))
+}
+
+/// Start capturing into a new segment file from `device_name` (the system
+/// default if `None`).
+///
+/// Audio is saved in `format`, converted from whatever the device delivers.
+/// A session that already has an `audio.wav` keeps that file's format so
+/// the new audio can be appended to it. The speech engine gets the same
+/// audio as mono at [`crate::speech::SPEECH_SAMPLE_RATE`].
+pub fn start_recording_simple(
+    session_id: String,
+    session_dir: PathBuf,
+    device_name: Option<String>,
+    format: RecordingFormat,
+    app_handle: AppHandle,
+) -> Result<()> {
     // Check if already recording
     if is_recording(&session_id) {
         return Err(anyhow!("Already recording"));
@@ -273,50 +223,27 @@ pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_hand
     
     println!("🎙️ Starting audio recording for session: {}", session_id);
 
-    // Get the default input device
+    // Get the chosen input device
     let host = cpal::default_host();
     println!("🎙️ Audio host: {}", host.id().name());
     
-    let device = host.default_input_device()
-        .ok_or_else(|| anyhow!("No default input device available. Please check microphone permissions in System Preferences > Security & Privacy > Privacy > Microphone"))?;
+    let device = open_input_device(&host, device_name.as_deref())?;
 
     let device_name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
     println!("🎙️ Using audio device: {}", device_name);
 
-    // Get supported configurations and pick the best one
-    let mut supported_configs = device.supported_input_configs()
-        .map_err(|e| anyhow!("Failed to get supported configs: {}", e))?;
-    
-    let supported_config = supported_configs
-        .find(|config| {
-            // Prefer configs that support mono and reasonable sample rates
-            config.channels() >= 1 && 
-            config.min_sample_rate().0 <= 44100 && 
-            config.max_sample_rate().0 >= 16000
-        })
-        .ok_or_else(|| anyhow!("No suitable audio configuration found"))?;
-
-    // Use a sample rate that the device supports, prefer 16kHz if available
-    let sample_rate = if supported_config.min_sample_rate().0 <= 16000 && 
-                         supported_config.max_sample_rate().0 >= 16000 {
-        16000
+    let spec = recording_spec(&session_dir, &format);
+    // New audio is appended, so the recording's length starts from what is already there
+    let main_audio_path = session_dir.join("audio.wav");
+    let existing_frames = if main_audio_path.exists() {
+        crate::codec::audio_info(&main_audio_path).map(|info| info.frames).unwrap_or(0)
     } else {
-        supported_config.min_sample_rate().0
-    };
-
-    let config = StreamConfig {
-        channels: 1.min(supported_config.channels()),
-        sample_rate: SampleRate(sample_rate),
-        buffer_size: cpal::BufferSize::Default,
-    };
-
-    // Create WAV writer - always mono since we convert multi-channel to mono
-    let spec = WavSpec {
-        channels: 1,
-        sample_rate: config.sample_rate.0,
-        bits_per_sample: 16,
-        sample_format: hound::SampleFormat::Int,
+        0
     };
+    let recorded_frames = Arc::new(AtomicU64::new(existing_frames));
+    let (config, sample_format) = choose_input_config(&device, spec.sample_rate, spec.channels)?;
+    log::info!("Capturing {}Hz x{} {:?}, saving {}Hz x{} {}-bit",
+            config.sample_rate.0, config.channels, sample_format, spec.sample_rate, spec.channels, spec.bits_per_sample);
 
     // Create WAV writer with BufWriter for better performance - write to segment file
     let file = File::create(&segment_path)?;
@@ -327,6 +254,38 @@ pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_hand
     // Set up recording flag and pause state
     let recording_flag = Arc::new(AtomicBool::new(true));
     let pause_flag = Arc::new(AtomicBool::new(false));
+
+    // Set up level monitoring channel
+    let (level_tx, mut level_rx) = mpsc::unbounded_channel::<(f32, Levels)>();
+
+    // Looks out for a muted or overdriven microphone in what the device delivers
+    let mut watch = InputWatch::new(config.sample_rate.0);
+
+    let resamplers = Arc::new(Mutex::new(CaptureResamplers::new(config.sample_rate.0, spec)));
+
+    // Open the device on the audio thread, which keeps the stream until the recording stops
+    let capture = Capture {
+        session_id: session_id.clone(),
+        device,
+        device_name,
+        config,
+        sample_format,
+        spec,
+        writer: Arc::clone(&writer),
+        recording_flag: Arc::clone(&recording_flag),
+        pause_flag: Arc::clone(&pause_flag),
+        recorded_frames: Arc::clone(&recorded_frames),
+        write_failing: Arc::new(AtomicBool::new(false)),
+        resamplers,
+        level_tx,
+        app_handle: app_handle.clone(),
+    };
+    if let Err(e) = send_stream_command(|reply| StreamCommand::Open(Box::new(capture), reply)) {
+        drop(writer);
+        let _ = std::fs::remove_file(&segment_path);
+        return Err(e);
+    }
+
     {
         let mut sessions = RECORDING_SESSIONS.lock().unwrap();
         sessions.insert(session_id.clone(), Arc::clone(&recording_flag));
@@ -342,11 +301,15 @@ pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_hand
         let mut segments = AUDIO_SEGMENTS.lock().unwrap();
         let session_segments = segments.entry(session_id.clone()).or_insert_with(Vec::new);
         session_segments.push(segment_path.clone());
+        
+        // Segments written after a resume use the same format
+        let mut specs = RECORDING_SPECS.lock().unwrap();
+        specs.insert(session_id.clone(), spec);
+        
+        let mut frames = RECORDED_FRAMES.lock().unwrap();
+        frames.insert(session_id.clone(), Arc::clone(&recorded_frames));
     }
 
-    // Set up level monitoring channel
-    let (level_tx, mut level_rx) = mpsc::unbounded_channel::<f32>();
-
     // Spawn task to emit audio level events with better timing
     let app_handle_clone = app_handle.clone();
     let session_id_clone = session_id.clone();
@@ -361,8 +324,10 @@ pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_hand
             
             // Collect multiple level readings if available
             let mut current_levels = Vec::new();
-            while let Ok(level) = level_rx.try_recv() {
+            let mut levels = Levels::default();
+            while let Ok((level, block)) = level_rx.try_recv() {
                 current_levels.push(level);
+                levels.merge(&block);
             }
             
             if !current_levels.is_empty() {
@@ -395,9 +360,25 @@ pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_hand
             // Always emit the current level (even if it's the same) for consistent updates
             let _ = app_handle_clone.emit("polka://audio-level", serde_json::json!({
                 "session_id": session_id_clone,
-                "level": last_level
+                "level": last_level,
+                "rms_db": levels.rms_db(),
+                "true_peak_db": levels.true_peak_db(),
+                "clipping": levels.clipped
             }));
             
+            // Nothing arrives while paused, so only audio that was heard counts
+            if levels.frames > 0 {
+                for (warning, active) in watch.update(&levels) {
+                    log::info!("Input warning {:?} {}", warning, if active { "started" } else { "cleared" });
+                    let _ = app_handle_clone.emit("polka://input-warning", serde_json::json!({
+                        "session_id": session_id_clone,
+                        "warning": warning,
+                        "active": active,
+                        "message": warning.message()
+                    }));
+                }
+            }
+            
             // Decay the level gradually when no new audio
             if current_levels.is_empty() && last_level > 0.0 {
                 last_level *= 0.85; // Gradual decay
@@ -408,69 +389,354 @@ pub fn start_recording_simple(session_id: String, session_dir: PathBuf, app_hand
         }
     });
 
-    // Create the audio stream
-    let writer_clone = Arc::clone(&writer);
-    let recording_flag_clone2 = Arc::clone(&recording_flag);
-    let pause_flag_clone = Arc::clone(&pause_flag);
-    let channels = config.channels;
-
-    let stream = device.build_input_stream(
-        &config,
-        move |data: &[f32], _: &cpal::InputCallbackInfo| {
-            if !recording_flag_clone2.load(Ordering::Relaxed) {
-                return;
+    log::info!("Audio recording started for session: {}", session_id);
+    Ok(())
+}
+
+// Everything needed to open a session's input stream, again after every resume
+struct Capture {
+    session_id: String,
+    device: cpal::Device,
+    device_name: String,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    spec: WavSpec,
+    writer: Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>,
+    recording_flag: Arc<AtomicBool>,
+    pause_flag: Arc<AtomicBool>,
+    recorded_frames: Arc<AtomicU64>,
+    write_failing: Arc<AtomicBool>,
+    // Outside the stream, so the audio they hold back outlives it
+    resamplers: Arc<Mutex<CaptureResamplers>>,
+    level_tx: mpsc::UnboundedSender<(f32, Levels)>,
+    app_handle: AppHandle,
+}
+
+// Convert from what the device delivers to the saved format and to what speech recognition expects
+struct CaptureResamplers {
+    file: Resampler,
+    speech: Resampler,
+}
+
+impl CaptureResamplers {
+    fn new(device_rate: u32, spec: WavSpec) -> Self {
+        CaptureResamplers {
+            file: Resampler::new(device_rate, spec.sample_rate, spec.channels),
+            speech: Resampler::new(device_rate, crate::speech::SPEECH_SAMPLE_RATE, 1),
+        }
+    }
+}
+
+impl Capture {
+    fn open_stream(&self) -> Result<cpal::Stream> {
+        // Devices deliver whatever sample type they were made for
+        let stream = match self.sample_format {
+            SampleFormat::F32 => self.build_input_stream::<f32>(),
+            SampleFormat::F64 => self.build_input_stream::<f64>(),
+            SampleFormat::I8 => self.build_input_stream::<i8>(),
+            SampleFormat::I16 => self.build_input_stream::<i16>(),
+            SampleFormat::I32 => self.build_input_stream::<i32>(),
+            SampleFormat::I64 => self.build_input_stream::<i64>(),
+            SampleFormat::U8 => self.build_input_stream::<u8>(),
+            SampleFormat::U16 => self.build_input_stream::<u16>(),
+            SampleFormat::U32 => self.build_input_stream::<u32>(),
+            SampleFormat::U64 => self.build_input_stream::<u64>(),
+            format => Err(anyhow!("Unsupported input sample format {:?}", format)),
+        }?;
+        stream.play()?;
+        Ok(stream)
+    }
+
+    // Open an input stream delivering the device's sample type, converted to
+    // f32 before anything else sees it
+    fn build_input_stream<T>(&self) -> Result<cpal::Stream>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let writer_clone = Arc::clone(&self.writer);
+        let recording_flag_clone = Arc::clone(&self.recording_flag);
+        let pause_flag_clone = Arc::clone(&self.pause_flag);
+        let recorded_frames = Arc::clone(&self.recorded_frames);
+        let write_failing = Arc::clone(&self.write_failing);
+        let resamplers = Arc::clone(&self.resamplers);
+        let level_tx = self.level_tx.clone();
+        let mut meter = InputMeter::new(self.config.channels);
+        let stream_session_id = self.session_id.clone();
+        let spec = self.spec;
+        let channels = self.config.channels;
+        let error_app_handle = self.app_handle.clone();
+        let error_session_id = self.session_id.clone();
+        let error_device_name = self.device_name.clone();
+        let mut converted = Vec::new();
+
+        let stream = self.device.build_input_stream(
+            &self.config,
+            move |input: &[T], _: &cpal::InputCallbackInfo| {
+                if !recording_flag_clone.load(Ordering::Relaxed) {
+                    return;
+                }
+                converted.clear();
+                converted.extend(input.iter().map(|&sample| f32::from_sample(sample)));
+                let data = converted.as_slice();
+
+                // Calculate RMS level for VU meter (always calculate for UI feedback)
+                let rms = calculate_rms(data);
+                let _ = level_tx.send((rms, meter.measure(data)));
+
+                // Only write audio data if not paused
+                if !pause_flag_clone.load(Ordering::Relaxed) {
+                    // Multi-channel input is converted to mono by averaging channels
+                    let mono_samples: Vec<f32> = if channels == 1 {
+                        data.to_vec()
+                    } else {
+                        data.chunks(channels as usize)
+                            .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+                            .collect()
+                    };
+
+                    // Bring the audio into the saved channel layout and sample rate
+                    let Ok(mut resamplers) = resamplers.lock() else {
+                        return;
+                    };
+                    let file_samples = if spec.channels == 1 {
+                        resamplers.file.process(&mono_samples)
+                    } else {
+                        resamplers.file.process(&remap_channels(data, channels, spec.channels))
+                    };
+                    write_capture_samples(&writer_clone, &file_samples, &recorded_frames, &write_failing);
+
+                    // The speech engine hears exactly what ends up in the recording
+                    crate::speech::feed_samples(&stream_session_id, &resamplers.speech.process(&mono_samples));
+                }
+            },
+            move |err| {
+                log::error!("Audio input error on {}: {}", error_device_name, err);
+            
+                // Tell the user right away instead of silently recording nothing
+                let error = match err {
+                    cpal::StreamError::DeviceNotAvailable => format!(
+                        "Input device \"{}\" was disconnected. Stop the recording and choose another microphone.",
+                        error_device_name
+                    ),
+                    other => format!("Audio input error on \"{}\": {}", error_device_name, other),
+                };
+                let _ = error_app_handle.emit("polka://audio-device-error", serde_json::json!({
+                    "session_id": error_session_id,
+                    "device": error_device_name,
+                    "error": error
+                }));
+            },
+            None
+        )?;
+        Ok(stream)
+    }
+
+    // Write out what the resamplers still hold once the stream is gone, and
+    // start over for the next one
+    fn flush(&self) {
+        let Ok(mut resamplers) = self.resamplers.lock() else {
+            return;
+        };
+        let file_samples = resamplers.file.flush();
+        write_capture_samples(&self.writer, &file_samples, &self.recorded_frames, &self.write_failing);
+        crate::speech::feed_samples(&self.session_id, &resamplers.speech.flush());
+        *resamplers = CaptureResamplers::new(self.config.sample_rate.0, self.spec);
+    }
+}
+
+// Append converted audio to the segment being recorded. This runs for every
+// buffer, so a failure is logged when it starts rather than on each one.
+fn write_capture_samples(
+    writer: &Mutex<Option<WavWriter<BufWriter<File>>>>,
+    samples: &[f32],
+    recorded_frames: &AtomicU64,
+    write_failing: &AtomicBool,
+) {
+    if samples.is_empty() {
+        return;
+    }
+    let written = match writer.lock() {
+        Ok(mut writer_guard) => match writer_guard.as_mut() {
+            Some(writer) => write_f32_samples(writer, samples)
+                .map(|()| {
+                    let frames = samples.len() / writer.spec().channels.max(1) as usize;
+                    recorded_frames.fetch_add(frames as u64, Ordering::Relaxed);
+                })
+                .map_err(|e| e.to_string()),
+            None => Err("no segment is open".to_string()),
+        },
+        Err(_) => Err("the segment writer is poisoned".to_string()),
+    };
+    match written {
+        Ok(()) => write_failing.store(false, Ordering::Relaxed),
+        Err(e) => {
+            if !write_failing.swap(true, Ordering::Relaxed) {
+                log::error!("Failed to write captured audio: {}", e);
             }
+        }
+    }
+}
 
-            // Calculate RMS level for VU meter (always calculate for UI feedback)
-            let rms = calculate_rms(data);
-            let _ = level_tx.send(rms);
-
-            // Only write audio data if not paused
-            if !pause_flag_clone.load(Ordering::Relaxed) {
-                // Write audio data to WAV file
-                if let Ok(mut writer_guard) = writer_clone.lock() {
-                    if let Some(ref mut writer) = *writer_guard {
-                        if channels == 1 {
-                            // Mono: write samples directly
-                            for &sample in data {
-                                let sample_i16 = (sample * i16::MAX as f32) as i16;
-                                if let Err(e) = writer.write_sample(sample_i16) {
-                                    eprintln!("🎙️ Error writing sample: {}", e);
-                                }
-                            }
-                        } else {
-                            // Multi-channel: convert to mono by averaging channels
-                            for chunk in data.chunks(channels as usize) {
-                                let mono_sample: f32 = chunk.iter().sum::<f32>() / chunk.len() as f32;
-                                let sample_i16 = (mono_sample * i16::MAX as f32) as i16;
-                                if let Err(e) = writer.write_sample(sample_i16) {
-                                    eprintln!("🎙️ Error writing sample: {}", e);
-                                }
-                            }
+type Reply = std::sync::mpsc::Sender<Result<()>>;
+
+enum StreamCommand {
+    Open(Box<Capture>, Reply),
+    // Release the device while paused, keeping what is needed to open it again
+    Suspend(String, Reply),
+    Reopen(String, Reply),
+    Close(String, Reply),
+}
+
+// cpal streams can't move between threads on every platform, so one thread
+// owns them all. Dropping a stream closes the device.
+static STREAM_COMMANDS: Lazy<Mutex<std::sync::mpsc::Sender<StreamCommand>>> = Lazy::new(|| {
+    let (commands_tx, commands_rx) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("polka-audio".to_string())
+        .spawn(move || run_stream_thread(commands_rx))
+        .expect("Failed to start audio thread");
+    Mutex::new(commands_tx)
+});
+
+fn run_stream_thread(commands: std::sync::mpsc::Receiver<StreamCommand>) {
+    let mut captures: HashMap<String, (Capture, Option<cpal::Stream>)> = HashMap::new();
+    for command in commands {
+        match command {
+            StreamCommand::Open(capture, reply) => match capture.open_stream() {
+                Ok(stream) => {
+                    captures.insert(capture.session_id.clone(), (*capture, Some(stream)));
+                    let _ = reply.send(Ok(()));
+                }
+                Err(e) => {
+                    // Let go of the segment file before the caller cleans it up
+                    drop(capture);
+                    let _ = reply.send(Err(e));
+                }
+            },
+            StreamCommand::Suspend(session_id, reply) => {
+                let result = match captures.get_mut(&session_id) {
+                    Some((capture, stream)) => {
+                        // Taking the stream closes it, so its callback is done with the resamplers
+                        if stream.take().is_some() {
+                            capture.flush();
                         }
-                    } else {
-                        eprintln!("🎙️ Warning: Writer is None in audio callback");
+                        Ok(())
                     }
-                } else {
-                    eprintln!("🎙️ Warning: Could not lock writer in audio callback");
-                }
+                    None => Err(anyhow!("No audio stream for session")),
+                };
+                let _ = reply.send(result);
             }
-        },
-        move |err| {
-            eprintln!("🎙️ Audio stream error: {}", err);
-        },
-        None
-    )?;
+            StreamCommand::Reopen(session_id, reply) => {
+                let result = match captures.get_mut(&session_id) {
+                    Some((capture, stream)) if stream.is_none() => {
+                        capture.open_stream().map(|opened| *stream = Some(opened))
+                    }
+                    Some(_) => Ok(()),
+                    None => Err(anyhow!("No audio stream for session")),
+                };
+                let _ = reply.send(result);
+            }
+            StreamCommand::Close(session_id, reply) => {
+                let result = match captures.remove(&session_id) {
+                    Some((capture, mut stream)) => {
+                        // A suspended capture was flushed when it was paused
+                        if stream.take().is_some() {
+                            capture.flush();
+                        }
+                        Ok(())
+                    }
+                    None => Err(anyhow!("No audio stream for session")),
+                };
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
 
-    // Start the stream and keep it alive by leaking it
-    stream.play()?;
-    std::mem::forget(stream);
+// Run a command on the audio thread and wait until it is done
+fn send_stream_command(command: impl FnOnce(Reply) -> StreamCommand) -> Result<()> {
+    let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+    STREAM_COMMANDS.lock().unwrap().send(command(reply_tx))
+        .map_err(|_| anyhow!("Audio thread is not running"))?;
+    reply_rx.recv().map_err(|_| anyhow!("Audio thread is not running"))?
+}
 
-    println!("🎙️ Audio recording started successfully");
+// Continue in the format of an existing recording, otherwise use the configured one
+fn recording_spec(session_dir: &Path, format: &RecordingFormat) -> WavSpec {
+    let main_audio_path = session_dir.join("audio.wav");
+    if main_audio_path.exists() {
+        match hound::WavReader::open(&main_audio_path) {
+            Ok(reader) => {
+                let existing_spec = reader.spec();
+                log::info!("Continuing in existing file format: channels={}, sample_rate={}, bits={}",
+                        existing_spec.channels, existing_spec.sample_rate, existing_spec.bits_per_sample);
+                return existing_spec;
+            }
+            Err(e) => log::warn!("Could not read existing audio format, using configured format: {}", e),
+        }
+    }
+    format.wav_spec()
+}
+
+// Sample types the capture callback can convert, the ones that lose least first
+const CAPTURE_SAMPLE_FORMATS: [SampleFormat; 10] = [
+    SampleFormat::F32, SampleFormat::F64, SampleFormat::I32, SampleFormat::I64, SampleFormat::U32,
+    SampleFormat::U64, SampleFormat::I16, SampleFormat::U16, SampleFormat::I8, SampleFormat::U8,
+];
+
+// The device configuration closest to the format being saved, and the sample
+// type it delivers. Anything it can't match exactly is converted in the capture callback.
+fn choose_input_config(device: &cpal::Device, sample_rate: u32, channels: u16) -> Result<(StreamConfig, SampleFormat)> {
+    let mut configs: Vec<_> = device.supported_input_configs()
+        .map_err(|e| anyhow!("Failed to get supported configs: {}", e))?
+        .filter(|config| CAPTURE_SAMPLE_FORMATS.contains(&config.sample_format()))
+        .collect();
+    configs.sort_by_key(|config| CAPTURE_SAMPLE_FORMATS.iter().position(|&format| format == config.sample_format()));
+    
+    let supports_rate = |config: &&cpal::SupportedStreamConfigRange| {
+        config.min_sample_rate().0 <= sample_rate && config.max_sample_rate().0 >= sample_rate
+    };
+    let best = configs.iter()
+        .find(|config| supports_rate(config) && config.channels() >= channels)
+        .or_else(|| configs.iter().find(supports_rate))
+        .or_else(|| configs.iter().find(|config| config.channels() >= channels))
+        .or_else(|| configs.first())
+        .ok_or_else(|| anyhow!("No suitable audio configuration found"))?;
+    
+    let (min_rate, max_rate) = (best.min_sample_rate().0, best.max_sample_rate().0);
+    let capture_rate = sample_rate.clamp(min_rate, max_rate.max(min_rate));
+    
+    let config = StreamConfig {
+        channels: channels.min(best.channels()).max(1),
+        sample_rate: SampleRate(capture_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    Ok((config, best.sample_format()))
+}
+
+// Store -1.0..=1.0 samples in whatever sample format the file uses
+fn write_f32_samples<W: Write + Seek>(writer: &mut WavWriter<W>, samples: &[f32]) -> hound::Result<()> {
+    let spec = writer.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for &sample in samples {
+                writer.write_sample(sample)?;
+            }
+        }
+        hound::SampleFormat::Int => {
+            let max = ((1i64 << (spec.bits_per_sample - 1)) - 1) as f32;
+            for &sample in samples {
+                writer.write_sample((sample.clamp(-1.0, 1.0) * max) as i32)?;
+            }
+        }
+    }
     Ok(())
 }
 
-pub fn pause_recording_simple(session_id: &str) -> Result<()> {
+/// Pause recording, appending what was recorded to the session's `audio.wav`.
+/// Returns the length of the recording so far in milliseconds.
+pub fn pause_recording_simple(session_id: &str) -> Result<u64> {
     println!("⏸️ Pausing audio recording for session: {}", session_id);
     
     // Check if currently recording
@@ -479,14 +745,17 @@ pub fn pause_recording_simple(session_id: &str) -> Result<()> {
     }
     
     // Set pause flag
-    let paused = PAUSED_SESSIONS.lock().unwrap();
-    if let Some(pause_flag) = paused.get(session_id) {
+    let pause_flag = {
+        let paused = PAUSED_SESSIONS.lock().unwrap();
+        paused.get(session_id).cloned()
+    };
+    if let Some(pause_flag) = pause_flag {
         pause_flag.store(true, Ordering::Relaxed);
         
-        // Give a moment for any pending writes to complete
-        std::thread::sleep(std::time::Duration::from_millis(100));
+        // Release the microphone; once the stream is gone nothing writes to the segment
+        send_stream_command(|reply| StreamCommand::Suspend(session_id.to_string(), reply))?;
         
-        // Finalize current segment but keep writer reference for stream callback
+        // Finalize current segment but keep the writer slot for the next one
         let writer_arc = {
             let writers = WAV_WRITERS.lock().unwrap();
             writers.get(session_id).cloned()
@@ -499,9 +768,6 @@ pub fn pause_recording_simple(session_id: &str) -> Result<()> {
                         Ok(_) => {
                             println!("⏸️ Current segment finalized successfully");
                             
-                            // Add a small delay to ensure file is fully written
-                            std::thread::sleep(std::time::Duration::from_millis(50));
-                            
                             // Immediately append this segment to the main audio file
                             if let Err(e) = append_current_segment_to_main(session_id) {
                                 println!("⚠️ Warning: Failed to append segment to main file: {}", e);
@@ -510,12 +776,11 @@ pub fn pause_recording_simple(session_id: &str) -> Result<()> {
                         Err(e) => println!("⚠️ Warning: Failed to finalize current segment: {}", e),
                     }
                 }
-                // Keep the writer_guard but with None content - this prevents stream callback from writing
             }
         }
         
         println!("⏸️ Audio recording paused successfully");
-        Ok(())
+        Ok(recorded_duration_ms(session_id).unwrap_or(0))
     } else {
         Err(anyhow!("Session not found"))
     }
@@ -548,64 +813,21 @@ pub fn resume_recording_simple(session_id: String, session_dir: PathBuf) -> Resu
     let segment_path = session_dir.join(format!("audio_segment_{}.wav", segment_number));
     println!("▶️ Creating new segment file: {:?}", segment_path);
     
-    // Create new WAV writer for the new segment with consistent format
-    // Get the sample rate from the main audio file if it exists, otherwise use 16kHz
+    // The new segment continues in the format recording started with
     let spec = {
-        let main_audio_path = session_dir.join("audio.wav");
-        if main_audio_path.exists() {
-            // Read spec from existing main file to ensure consistency
-            match hound::WavReader::open(&main_audio_path) {
-                Ok(reader) => {
-                    let existing_spec = reader.spec();
-                    println!("▶️ Using existing main file spec: channels={}, sample_rate={}, bits={}", 
-                            existing_spec.channels, existing_spec.sample_rate, existing_spec.bits_per_sample);
-                    existing_spec
-                },
-                Err(_) => {
-                    println!("▶️ Could not read main file spec, using default");
-                    WavSpec {
-                        channels: 1,
-                        sample_rate: 16000,
-                        bits_per_sample: 16,
-                        sample_format: hound::SampleFormat::Int,
-                    }
-                }
-            }
-        } else {
-            println!("▶️ No main file exists, using default spec");
-            WavSpec {
-                channels: 1,
-                sample_rate: 16000,
-                bits_per_sample: 16,
-                sample_format: hound::SampleFormat::Int,
-            }
-        }
+        let specs = RECORDING_SPECS.lock().unwrap();
+        *specs.get(&session_id).ok_or_else(|| anyhow!("No recording format found for session"))?
     };
     
     let file = File::create(&segment_path)?;
     let buf_writer = BufWriter::new(file);
     let wav_writer = WavWriter::new(buf_writer, spec)?;
-    let writer = Arc::new(Mutex::new(Some(wav_writer)));
     
-    // Update the writer - replace the existing one or insert new one
+    // The stream callback picks the new writer up from the session's writer slot
     {
-        let mut writers = WAV_WRITERS.lock().unwrap();
-        if let Some(existing_writer_arc) = writers.get(&session_id) {
-            // Replace the content of existing writer
-            if let Ok(mut existing_guard) = existing_writer_arc.lock() {
-                let new_wav_writer = {
-                    let file = File::create(&segment_path)?;
-                    let buf_writer = BufWriter::new(file);
-                    WavWriter::new(buf_writer, spec)?
-                };
-                *existing_guard = Some(new_wav_writer);
-                println!("▶️ Replaced existing writer with new segment writer");
-            }
-        } else {
-            // Insert new writer if none exists
-            writers.insert(session_id.clone(), writer);
-            println!("▶️ Inserted new writer for session");
-        }
+        let writers = WAV_WRITERS.lock().unwrap();
+        let writer_arc = writers.get(&session_id).ok_or_else(|| anyhow!("No writer found for session"))?;
+        *writer_arc.lock().unwrap() = Some(wav_writer);
         
         // Add new segment to the list
         let mut segments = AUDIO_SEGMENTS.lock().unwrap();
@@ -614,9 +836,18 @@ pub fn resume_recording_simple(session_id: String, session_dir: PathBuf) -> Resu
     }
     
     // Clear pause flag
-    let paused = PAUSED_SESSIONS.lock().unwrap();
-    if let Some(pause_flag) = paused.get(&session_id) {
+    let pause_flag = {
+        let paused = PAUSED_SESSIONS.lock().unwrap();
+        paused.get(&session_id).cloned()
+    };
+    if let Some(pause_flag) = pause_flag {
         pause_flag.store(false, Ordering::Relaxed);
+        
+        // Open the microphone again, staying paused if it has gone away
+        if let Err(e) = send_stream_command(|reply| StreamCommand::Reopen(session_id.clone(), reply)) {
+            pause_flag.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
         println!("▶️ Audio recording resumed successfully");
         Ok(())
     } else {
@@ -624,7 +855,9 @@ pub fn resume_recording_simple(session_id: String, session_dir: PathBuf) -> Resu
     }
 }
 
-pub fn stop_recording_simple(session_id: &str) -> Result<()> {
+/// Stop recording and finish the session's `audio.wav`. Returns the length
+/// of the recording in milliseconds.
+pub fn stop_recording_simple(session_id: &str) -> Result<u64> {
     println!("🎙️ Stopping audio recording for session: {}", session_id);
     
     // Stop the recording flag first
@@ -634,19 +867,25 @@ pub fn stop_recording_simple(session_id: &str) -> Result<()> {
     };
     
     // Remove pause flag and segment counter
-    {
+    let (spec, recorded_frames) = {
         let mut paused = PAUSED_SESSIONS.lock().unwrap();
         paused.remove(session_id);
         
         let mut counters = SEGMENT_COUNTERS.lock().unwrap();
         counters.remove(session_id);
-    }
+        
+        let mut specs = RECORDING_SPECS.lock().unwrap();
+        let mut frames = RECORDED_FRAMES.lock().unwrap();
+        (specs.remove(session_id), frames.remove(session_id))
+    };
     
     if let Some(flag) = recording_flag {
         flag.store(false, Ordering::Relaxed);
         
-        // Give a moment for any pending writes to complete
-        std::thread::sleep(std::time::Duration::from_millis(100));
+        // Close the microphone; once the stream is gone nothing writes to the segment
+        if let Err(e) = send_stream_command(|reply| StreamCommand::Close(session_id.to_string(), reply)) {
+            log::warn!("Failed to close audio stream: {}", e);
+        }
         
         // Properly finalize the current segment WAV file
         let writer = {
@@ -678,8 +917,14 @@ pub fn stop_recording_simple(session_id: &str) -> Result<()> {
             // Continue anyway - main audio file should be available
         }
         
-        println!("🎙️ Audio recording stopped successfully");
-        Ok(())
+        // Read only now that the stream is closed and can't add any more
+        let duration_ms = match (spec, recorded_frames) {
+            (Some(spec), Some(frames)) => frames.load(Ordering::Relaxed) * 1000 / spec.sample_rate.max(1) as u64,
+            _ => 0,
+        };
+        
+        log::info!("Audio recording stopped for session: {} ({} ms)", session_id, duration_ms);
+        Ok(duration_ms)
     } else {
         Err(anyhow!("Not currently recording"))
     }
@@ -728,49 +973,7 @@ fn append_current_segment_to_main(session_id: &str) -> Result<()> {
     if main_audio_path.exists() {
         println!("🔗 Appending to existing main audio file");
         
-        // Read existing main file
-        let mut main_reader = hound::WavReader::open(&main_audio_path)?;
-        let spec = main_reader.spec();
-        let main_samples: Result<Vec<i16>, _> = main_reader.samples().collect();
-        let main_samples = main_samples?;
-        
-        // Read current segment
-        let mut segment_reader = hound::WavReader::open(&current_segment_path)?;
-        let segment_spec = segment_reader.spec();
-        
-        // Log specs for debugging
-        println!("🔗 Main file spec: channels={}, sample_rate={}, bits={}", 
-                spec.channels, spec.sample_rate, spec.bits_per_sample);
-        println!("🔗 Segment spec: channels={}, sample_rate={}, bits={}", 
-                segment_spec.channels, segment_spec.sample_rate, segment_spec.bits_per_sample);
-        
-        if segment_spec != spec {
-            println!("⚠️ Warning: Segment spec mismatch with main file, but continuing");
-        }
-        
-        let segment_samples: Result<Vec<i16>, _> = segment_reader.samples().collect();
-        let segment_samples = segment_samples?;
-        
-        println!("🔗 Main file samples: {}, Segment samples: {}", main_samples.len(), segment_samples.len());
-        
-        // Create temporary file for the combined audio
-        let temp_path = main_audio_path.with_extension("temp.wav");
-        let mut writer = hound::WavWriter::create(&temp_path, spec)?;
-        
-        // Write main audio samples first
-        for sample in main_samples {
-            writer.write_sample(sample)?;
-        }
-        
-        // Then append segment samples
-        for sample in segment_samples {
-            writer.write_sample(sample)?;
-        }
-        
-        writer.finalize()?;
-        
-        // Replace main file with combined file
-        std::fs::rename(&temp_path, &main_audio_path)?;
+        append_segment_to_file(&main_audio_path, &current_segment_path)?;
         
         println!("🔗 Successfully appended segment to main audio file");
     } else {
@@ -811,28 +1014,7 @@ fn combine_audio_segments(session_id: &str) -> Result<()> {
             println!("🔗 Final segment became the main audio file");
         } else {
             // Append the final segment to main file
-            let mut main_reader = hound::WavReader::open(&main_audio_path)?;
-            let spec = main_reader.spec();
-            let main_samples: Result<Vec<i16>, _> = main_reader.samples().collect();
-            let main_samples = main_samples?;
-            
-            let mut segment_reader = hound::WavReader::open(last_segment)?;
-            let segment_samples: Result<Vec<i16>, _> = segment_reader.samples().collect();
-            let segment_samples = segment_samples?;
-            
-            let temp_path = main_audio_path.with_extension("temp.wav");
-            let mut writer = hound::WavWriter::create(&temp_path, spec)?;
-            
-            // Write all samples
-            for sample in main_samples {
-                writer.write_sample(sample)?;
-            }
-            for sample in segment_samples {
-                writer.write_sample(sample)?;
-            }
-            
-            writer.finalize()?;
-            std::fs::rename(&temp_path, &main_audio_path)?;
+            append_segment_to_file(&main_audio_path, last_segment)?;
             
             println!("🔗 Final segment appended to main audio file");
         }
@@ -850,3 +1032,129 @@ fn combine_audio_segments(session_id: &str) -> Result<()> {
     println!("🔗 Audio finalization completed for session: {}", session_id);
     Ok(())
 }
+
+/// Stitch segments left behind by a recording that never stopped (the app
+/// crashed or was killed) into the session's `audio.wav`. Their headers are
+/// repaired first, since the writer never got to finalize them. Must not run
+/// while the session is recording. Returns whether anything was recovered.
+pub fn recover_segments(session_dir: &Path) -> Result<bool> {
+    let mut segments: Vec<(usize, PathBuf)> = Vec::new();
+    for entry in std::fs::read_dir(session_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.starts_with("audio_segment_") && name.ends_with(".converted.wav") {
+            // Half-written conversion, its segment is still there
+            std::fs::remove_file(&path)?;
+        } else if let Some(number) = name.strip_prefix("audio_segment_")
+            .and_then(|rest| rest.strip_suffix(".wav"))
+            .and_then(|number| number.parse().ok()) {
+            segments.push((number, path));
+        }
+    }
+
+    // An append that patched the header but didn't get to delete its segment
+    // is cut back, so the segment goes in once
+    let main_audio_path = session_dir.join("audio.wav");
+    if crate::wav::undo_unfinished_append(&main_audio_path)? {
+        log::warn!("Undid an append to {:?} that didn't finish", main_audio_path);
+    }
+
+    if segments.is_empty() {
+        return Ok(false);
+    }
+    segments.sort();
+    log::warn!("Recovering {} interrupted segment(s) in {:?}", segments.len(), session_dir);
+
+    // An append cut short leaves audio past the end the header states, and
+    // that audio is still in its segment
+    if main_audio_path.exists() && crate::wav::trim_to_header(&main_audio_path)? {
+        log::warn!("Cut an unfinished append from {:?}", main_audio_path);
+    }
+
+    let mut recovered = false;
+    for (_, segment_path) in &segments {
+        match crate::wav::repair_header(segment_path) {
+            Ok(0) => log::info!("Segment {:?} holds no audio", segment_path),
+            Ok(frames) => {
+                log::info!("Repaired {:?}: {} frames", segment_path, frames);
+                if main_audio_path.exists() {
+                    append_segment_to_file(&main_audio_path, segment_path)?;
+                } else {
+                    std::fs::rename(segment_path, &main_audio_path)?;
+                }
+                recovered = true;
+            }
+            // The header never reached the disk, so there's no audio either
+            Err(e) => log::warn!("Discarding unreadable segment {:?}: {}", segment_path, e),
+        }
+        if segment_path.exists() {
+            std::fs::remove_file(segment_path)?;
+        }
+    }
+    Ok(recovered)
+}
+
+// Append a segment to the main file in place and delete it. A segment in a
+// different format (e.g. recorded before the format setting changed) is
+// converted first.
+fn append_segment_to_file(main_audio_path: &Path, segment_path: &Path) -> Result<()> {
+    // Until the segment is gone, recovery must not append it a second time
+    crate::wav::mark_append(main_audio_path, segment_path)?;
+    append_segment_data(main_audio_path, segment_path)?;
+    std::fs::remove_file(segment_path)?;
+    crate::durable::sync_parent(segment_path)?;
+    crate::wav::finish_append(main_audio_path)
+}
+
+fn append_segment_data(main_audio_path: &Path, segment_path: &Path) -> Result<()> {
+    let spec = hound::WavReader::open(main_audio_path)?.spec();
+    let mut segment_reader = hound::WavReader::open(segment_path)?;
+    let segment_spec = segment_reader.spec();
+    
+    log::debug!("Main file spec: channels={}, sample_rate={}, bits={}",
+            spec.channels, spec.sample_rate, spec.bits_per_sample);
+    log::debug!("Segment spec: channels={}, sample_rate={}, bits={}",
+            segment_spec.channels, segment_spec.sample_rate, segment_spec.bits_per_sample);
+    
+    if segment_spec == spec {
+        return crate::wav::append_data(main_audio_path, segment_path);
+    }
+    
+    log::info!("Segment format differs from main file, converting");
+    let converted_path = segment_path.with_extension("converted.wav");
+    {
+        let mut writer = hound::WavWriter::create(&converted_path, spec)?;
+        convert_samples(&mut segment_reader, &mut writer)?;
+        writer.finalize()?;
+    }
+    let result = crate::wav::append_data(main_audio_path, &converted_path);
+    let _ = std::fs::remove_file(&converted_path);
+    result
+}
+
+// Copy samples into a file of another sample rate, channel layout or sample format
+fn convert_samples<R: std::io::Read, W: Write + Seek>(reader: &mut hound::WavReader<R>, writer: &mut WavWriter<W>) -> Result<()> {
+    let from = reader.spec();
+    let to = writer.spec();
+    let mut resampler = Resampler::new(from.sample_rate, to.sample_rate, to.channels);
+    
+    let chunk_len = from.sample_rate as usize * from.channels as usize;
+    let mut chunk = Vec::with_capacity(chunk_len);
+    let mut samples = wav_samples_f32(reader);
+    loop {
+        chunk.clear();
+        for sample in samples.by_ref().take(chunk_len) {
+            chunk.push(sample?);
+        }
+        if chunk.is_empty() {
+            break;
+        }
+        
+        let remapped = remap_channels(&chunk, from.channels, to.channels);
+        write_f32_samples(writer, &resampler.process(&remapped))?;
+    }
+    write_f32_samples(writer, &resampler.flush())?;
+    Ok(())
+}
@@ -0,0 +1,355 @@
+use anyhow::{Result, anyhow};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use crate::flac::{FlacSamples, FlacWriter};
+use crate::settings::StorageFormat;
+
+// Where a session's recording can be, the WAV first since it is the one
+// being recorded into
+const STORAGE_FORMATS: [StorageFormat; 3] = [StorageFormat::Wav, StorageFormat::Flac, StorageFormat::Opus];
+// Seeking this far ahead or less decodes through rather than looks the target up
+const SKIP_AHEAD_SECONDS: u64 = 1;
+
+impl StorageFormat {
+    /// Name of a session's recording in this format.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            StorageFormat::Wav => "audio.wav",
+            StorageFormat::Flac => "audio.flac",
+            StorageFormat::Opus => "audio.opus",
+        }
+    }
+
+    fn of_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("wav") => Ok(StorageFormat::Wav),
+            Some("flac") => Ok(StorageFormat::Flac),
+            Some("opus") => Ok(StorageFormat::Opus),
+            _ => Err(anyhow!("Unsupported audio file {:?}", path)),
+        }
+    }
+}
+
+/// The session's recording, in whichever format it is stored.
+pub fn find_session_audio(session_dir: &Path) -> Option<PathBuf> {
+    STORAGE_FORMATS.iter()
+        .map(|format| session_dir.join(format.file_name()))
+        .find(|path| path.exists())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Length in samples per channel.
+    pub frames: u64,
+    /// Sample size of a lossless recording; Opus has none.
+    pub bits_per_sample: Option<u16>,
+}
+
+impl AudioInfo {
+    pub fn duration_ms(&self) -> u64 {
+        self.frames * 1000 / self.sample_rate.max(1) as u64
+    }
+}
+
+/// Format and length of a recording, read from its headers.
+pub fn audio_info(path: &Path) -> Result<AudioInfo> {
+    match StorageFormat::of_path(path)? {
+        StorageFormat::Wav => Ok(wav_info(&hound::WavReader::open(path)?)),
+        StorageFormat::Flac => {
+            let reader = claxon::FlacReader::open(path)
+                .map_err(|e| anyhow!("Failed to read FLAC file {:?}: {}", path, e))?;
+            flac_info(path, &reader.streaminfo())
+        }
+        #[cfg(feature = "opus")]
+        StorageFormat::Opus => {
+            let (sample_rate, channels, frames) = crate::opus::read_info(path)?;
+            Ok(AudioInfo { sample_rate, channels, frames, bits_per_sample: None })
+        }
+        #[cfg(not(feature = "opus"))]
+        StorageFormat::Opus => Err(no_opus_support()),
+    }
+}
+
+fn wav_info<R: std::io::Read>(reader: &hound::WavReader<R>) -> AudioInfo {
+    let spec = reader.spec();
+    AudioInfo {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        frames: reader.duration() as u64,
+        bits_per_sample: Some(spec.bits_per_sample),
+    }
+}
+
+fn flac_info(path: &Path, info: &claxon::metadata::StreamInfo) -> Result<AudioInfo> {
+    Ok(AudioInfo {
+        sample_rate: info.sample_rate,
+        channels: info.channels as u16,
+        frames: info.samples.ok_or_else(|| anyhow!("FLAC file {:?} doesn't state its length", path))?,
+        bits_per_sample: Some(info.bits_per_sample as u16),
+    })
+}
+
+#[cfg(not(feature = "opus"))]
+fn no_opus_support() -> anyhow::Error {
+    anyhow!("This build has no Opus support, rebuild with the `opus` feature")
+}
+
+/// A recording's samples as interleaved f32 in -1.0..=1.0, whatever format
+/// it is stored in.
+pub struct AudioReader {
+    info: AudioInfo,
+    samples: Box<dyn Samples>,
+    // Index of the next sample, counting every channel
+    position: u64,
+}
+
+// A decoder that can continue from any frame of the recording
+trait Samples: Iterator<Item = Result<f32>> {
+    fn seek(&mut self, frame: u64) -> Result<()>;
+}
+
+impl Samples for FlacSamples {
+    fn seek(&mut self, frame: u64) -> Result<()> {
+        FlacSamples::seek(self, frame)
+    }
+}
+
+#[cfg(feature = "opus")]
+impl Samples for crate::opus::OpusSamples {
+    fn seek(&mut self, frame: u64) -> Result<()> {
+        crate::opus::OpusSamples::seek(self, frame)
+    }
+}
+
+// WAV samples, read straight from the file
+struct WavSamples {
+    reader: hound::WavReader<BufReader<File>>,
+    // Scale of integer samples; float ones are used as they are
+    scale: Option<f32>,
+}
+
+impl Iterator for WavSamples {
+    type Item = Result<f32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.scale {
+            Some(scale) => self.reader.samples::<i32>().next().map(|s| Ok(s? as f32 * scale)),
+            None => self.reader.samples::<f32>().next().map(|s| Ok(s?)),
+        }
+    }
+}
+
+impl Samples for WavSamples {
+    fn seek(&mut self, frame: u64) -> Result<()> {
+        Ok(self.reader.seek(frame as u32)?)
+    }
+}
+
+impl AudioReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let (info, samples): (AudioInfo, Box<dyn Samples>) = match StorageFormat::of_path(path)? {
+            StorageFormat::Wav => {
+                let reader = hound::WavReader::open(path)?;
+                let spec = reader.spec();
+                let scale = match spec.sample_format {
+                    hound::SampleFormat::Float => None,
+                    hound::SampleFormat::Int => Some(1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32),
+                };
+                (wav_info(&reader), Box::new(WavSamples { reader, scale }))
+            }
+            StorageFormat::Flac => {
+                let (stream_info, samples) = FlacSamples::open(path)?;
+                (flac_info(path, &stream_info)?, Box::new(samples))
+            }
+            #[cfg(feature = "opus")]
+            StorageFormat::Opus => (audio_info(path)?, Box::new(crate::opus::OpusSamples::open(path)?)),
+            #[cfg(not(feature = "opus"))]
+            StorageFormat::Opus => return Err(no_opus_support()),
+        };
+        Ok(AudioReader { info, samples, position: 0 })
+    }
+
+    /// Open a recording to read from `frame` on.
+    pub fn open_at(path: &Path, frame: u64) -> Result<Self> {
+        let mut reader = Self::open(path)?;
+        reader.seek(frame)?;
+        Ok(reader)
+    }
+
+    /// Continue reading from `frame`. A short jump ahead decodes on to it,
+    /// anything else looks it up in the file, which for compressed
+    /// recordings takes a few small reads however long they are.
+    pub fn seek(&mut self, frame: u64) -> Result<()> {
+        let channels = self.info.channels as u64;
+        let target = frame.min(self.info.frames) * channels;
+        let skip_limit = SKIP_AHEAD_SECONDS * self.info.sample_rate as u64 * channels;
+        match target.checked_sub(self.position) {
+            Some(ahead) if ahead <= skip_limit => {
+                for _ in 0..ahead {
+                    if self.next().transpose()?.is_none() {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                self.samples.seek(target / channels)?;
+                self.position = target;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn info(&self) -> AudioInfo {
+        self.info
+    }
+}
+
+impl Iterator for AudioReader {
+    type Item = Result<f32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.samples.next();
+        if let Some(Ok(_)) = sample {
+            self.position += 1;
+        }
+        sample
+    }
+}
+
+/// Store a finished recording in `format`, replacing the WAV it was recorded
+/// into and any older copy in another format. Returns the recording's path.
+pub fn store_recording(wav_path: &Path, format: StorageFormat) -> Result<PathBuf> {
+    let target = wav_path.with_file_name(format.file_name());
+
+    if format != StorageFormat::Wav {
+        println!("🗜️ Encoding {:?} as {:?}", wav_path, format);
+        // Encode next to the target and rename, so a crash never leaves a half-written recording
+        let temp_path = target.with_extension("tmp");
+        if let Err(e) = encode(wav_path, &temp_path, format) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        crate::durable::replace(&temp_path, &target)?;
+    }
+
+    for other in STORAGE_FORMATS.iter().filter(|&&other| other != format) {
+        let path = wav_path.with_file_name(other.file_name());
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(target)
+}
+
+fn encode(wav_path: &Path, output_path: &Path, format: StorageFormat) -> Result<()> {
+    let mut reader = hound::WavReader::open(wav_path)?;
+    let spec = reader.spec();
+
+    // One second at a time keeps memory flat for long recordings
+    let chunk_len = spec.sample_rate as usize * spec.channels as usize;
+    match format {
+        StorageFormat::Wav => Err(anyhow!("WAV recordings need no encoding")),
+        StorageFormat::Flac => {
+            // FLAC only stores integers, float recordings keep 24 bits
+            let bits = match spec.sample_format {
+                hound::SampleFormat::Int => spec.bits_per_sample,
+                hound::SampleFormat::Float => 24,
+            };
+            let mut writer = FlacWriter::create(output_path, spec.sample_rate, spec.channels, bits)?;
+            let mut chunk = Vec::with_capacity(chunk_len);
+            match spec.sample_format {
+                hound::SampleFormat::Int => {
+                    let mut samples = reader.samples::<i32>();
+                    loop {
+                        chunk.clear();
+                        for sample in samples.by_ref().take(chunk_len) {
+                            chunk.push(sample?);
+                        }
+                        if chunk.is_empty() {
+                            break;
+                        }
+                        writer.write_samples(&chunk)?;
+                    }
+                }
+                hound::SampleFormat::Float => {
+                    let max = ((1 << 23) - 1) as f32;
+                    let mut samples = reader.samples::<f32>();
+                    loop {
+                        chunk.clear();
+                        for sample in samples.by_ref().take(chunk_len) {
+                            chunk.push((sample?.clamp(-1.0, 1.0) * max) as i32);
+                        }
+                        if chunk.is_empty() {
+                            break;
+                        }
+                        writer.write_samples(&chunk)?;
+                    }
+                }
+            }
+            writer.finalize()?;
+            Ok(())
+        }
+        #[cfg(feature = "opus")]
+        StorageFormat::Opus => {
+            let mut writer = crate::opus::OpusWriter::create(output_path, spec.sample_rate, spec.channels)?;
+            let mut chunk = Vec::with_capacity(chunk_len);
+            let mut samples = crate::audio::wav_samples_f32(&mut reader);
+            loop {
+                chunk.clear();
+                for sample in samples.by_ref().take(chunk_len) {
+                    chunk.push(sample?);
+                }
+                if chunk.is_empty() {
+                    break;
+                }
+                writer.write_samples(&chunk)?;
+            }
+            writer.finalize()?;
+            Ok(())
+        }
+        #[cfg(not(feature = "opus"))]
+        StorageFormat::Opus => Err(no_opus_support()),
+    }
+}
+
+/// Decode a compressed recording back to `audio.wav` so that more audio can
+/// be recorded into the session. The compressed file stays until the
+/// recording is stored again. Does nothing if there is a WAV already.
+///
+/// FLAC comes back bit for bit. Opus is lossy, so an Opus recording is
+/// only ever decoded once: draft sessions are kept as FLAC until completed.
+pub fn restore_wav(session_dir: &Path) -> Result<()> {
+    let wav_path = session_dir.join(StorageFormat::Wav.file_name());
+    let Some(path) = find_session_audio(session_dir) else {
+        return Ok(());
+    };
+    if path == wav_path {
+        return Ok(());
+    }
+
+    println!("🗜️ Decoding {:?} to continue recording", path);
+    let mut reader = AudioReader::open(&path)?;
+    let info = reader.info();
+    // Lossless files come back bit for bit, Opus as 16-bit
+    let bits_per_sample = info.bits_per_sample.unwrap_or(16);
+    let spec = hound::WavSpec {
+        channels: info.channels,
+        sample_rate: info.sample_rate,
+        bits_per_sample,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let temp_path = wav_path.with_extension("wav.tmp");
+    let mut writer = hound::WavWriter::create(&temp_path, spec)?;
+    let scale = (1i64 << (bits_per_sample - 1)) as f32;
+    let max = scale as i64 - 1;
+    for sample in reader.by_ref() {
+        writer.write_sample(((sample? * scale).round() as i64).clamp(-max - 1, max) as i32)?;
+    }
+    writer.finalize()?;
+    crate::durable::replace(&temp_path, &wav_path)?;
+    Ok(())
+}
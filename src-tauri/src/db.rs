@@ -1,12 +1,24 @@
 use anyhow::Result;
-use rusqlite::Connection;
-use std::path::PathBuf;
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::str::FromStr;
-use crate::models::{Session, SessionStatus};
+use std::time::Duration;
+use crate::models::{Course, CourseStats, SearchHit, SearchSource, Session, SessionFilter, SessionStatus, Tag, TranscriptLine};
 
+// Connections open at most; a handful covers every command running at once
+const POOL_SIZE: u32 = 8;
+// How long a write waits for another one to finish before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handle to `polka.db`. Cheap to clone; every call checks out its own pooled
+/// connection, and in WAL mode reads go ahead while a write is in progress.
+#[derive(Clone)]
 pub struct Database {
-    conn: Connection,
+    pool: Pool<SqliteConnectionManager>,
 }
 
 impl Database {
@@ -14,79 +26,30 @@ impl Database {
         let data_dir = get_data_dir()?;
         fs::create_dir_all(&data_dir)?;
         
-        let db_path = data_dir.join("polka.db");
-        let conn = Connection::open(&db_path)?;
+        Self::open(&data_dir.join("polka.db"))
+    }
+    
+    /// Open the database at `db_path`, migrating it to the current schema.
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let manager = SqliteConnectionManager::file(db_path).with_init(|conn| {
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; PRAGMA foreign_keys = ON;")
+        });
+        let pool = Pool::builder().max_size(POOL_SIZE).build(manager)?;
         
-        let db = Database { conn };
-        db.init_db()?;
+        crate::migrations::migrate(&mut *pool.get()?)?;
         
-        Ok(db)
+        Ok(Database { pool })
     }
     
-    fn init_db(&self) -> Result<()> {
-        // First create the table with the original constraint if it doesn't exist
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                title TEXT NOT NULL,
-                course TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                duration_ms INTEGER DEFAULT 0,
-                status TEXT NOT NULL CHECK (status IN ('draft', 'complete', 'archived')),
-                notes_path TEXT,
-                audio_path TEXT,
-                transcript_path TEXT
-            )",
-            [],
-        )?;
-
-        // Migration: Add archived status to existing tables
-        // Check if we need to migrate by trying to insert an archived status
-        let needs_migration = self.conn.execute(
-            "INSERT INTO sessions (id, title, course, created_at, status) VALUES ('__migration_test__', 'test', 'test', 0, 'archived')",
-            [],
-        ).is_err();
-
-        if needs_migration {
-            // Create new table with updated constraint
-            self.conn.execute(
-                "CREATE TABLE sessions_new (
-                    id TEXT PRIMARY KEY,
-                    title TEXT NOT NULL,
-                    course TEXT NOT NULL,
-                    created_at INTEGER NOT NULL,
-                    duration_ms INTEGER DEFAULT 0,
-                    status TEXT NOT NULL CHECK (status IN ('draft', 'recording', 'complete', 'archived')),
-                    notes_path TEXT,
-                    audio_path TEXT,
-                    transcript_path TEXT
-                )",
-                [],
-            )?;
-
-            // Copy data from old table
-            self.conn.execute(
-                "INSERT INTO sessions_new SELECT * FROM sessions",
-                [],
-            )?;
-
-            // Drop old table
-            self.conn.execute("DROP TABLE sessions", [])?;
-
-            // Rename new table
-            self.conn.execute("ALTER TABLE sessions_new RENAME TO sessions", [])?;
-        } else {
-            // Clean up test record
-            self.conn.execute("DELETE FROM sessions WHERE id = '__migration_test__'", [])?;
-        }
-        
-        Ok(())
+    fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        Ok(self.pool.get()?)
     }
     
     pub fn insert_session(&self, session: &Session) -> Result<()> {
-        self.conn.execute(
-            "INSERT OR REPLACE INTO sessions (id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO sessions (id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path, recovered, course_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             (
                 &session.id,
                 &session.title,
@@ -97,6 +60,8 @@ impl Database {
                 &session.notes_path,
                 &session.audio_path,
                 &session.transcript_path,
+                session.recovered,
+                &session.course_id,
             ),
         )?;
         
@@ -104,12 +69,31 @@ impl Database {
     }
     
     pub fn list_sessions(&self) -> Result<Vec<Session>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path
-             FROM sessions ORDER BY created_at DESC"
-        )?;
+        self.list_sessions_matching(&SessionFilter::default())
+    }
+    
+    /// Sessions passing `filter`, newest first.
+    pub fn list_sessions_matching(&self, filter: &SessionFilter) -> Result<Vec<Session>> {
+        let mut tag_ids = filter.tags.clone();
+        tag_ids.sort();
+        tag_ids.dedup();
         
-        let session_iter = stmt.query_map([], |row| {
+        let mut sql = "SELECT id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path, recovered, course_id
+             FROM sessions".to_string();
+        if !tag_ids.is_empty() {
+            let required = if filter.any_tag { 1 } else { tag_ids.len() };
+            sql.push_str(&format!(
+                " WHERE id IN (SELECT session_id FROM session_tags WHERE tag_id IN ({}) GROUP BY session_id HAVING COUNT(*) >= {})",
+                vec!["?"; tag_ids.len()].join(", "),
+                required,
+            ));
+        }
+        sql.push_str(" ORDER BY created_at DESC");
+        
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&sql)?;
+        
+        let session_iter = stmt.query_map(rusqlite::params_from_iter(&tag_ids), |row| {
             let status_str: String = row.get(5)?;
             let status = SessionStatus::from_str(&status_str)
                 .unwrap_or(SessionStatus::Draft);
@@ -124,6 +108,9 @@ impl Database {
                 notes_path: row.get(6)?,
                 audio_path: row.get(7)?,
                 transcript_path: row.get(8)?,
+                recovered: row.get(9)?,
+                course_id: row.get(10)?,
+                tags: Vec::new(),
             })
         })?;
         
@@ -132,11 +119,16 @@ impl Database {
             sessions.push(session?);
         }
         
+        let mut tags = tags_by_session(&conn, None)?;
+        for session in &mut sessions {
+            session.tags = tags.remove(&session.id).unwrap_or_default();
+        }
+        
         Ok(sessions)
     }
     
     pub fn update_session_status(&self, id: &str, status: &SessionStatus) -> Result<()> {
-        self.conn.execute(
+        self.conn()?.execute(
             "UPDATE sessions SET status = ?1 WHERE id = ?2",
             (status.as_str(), id),
         )?;
@@ -144,9 +136,37 @@ impl Database {
         Ok(())
     }
     
+    pub fn update_session_audio_path(&self, id: &str, audio_path: &str) -> Result<()> {
+        self.conn()?.execute(
+            "UPDATE sessions SET audio_path = ?1 WHERE id = ?2",
+            (audio_path, id),
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn update_session_duration(&self, id: &str, duration_ms: i64) -> Result<()> {
+        self.conn()?.execute(
+            "UPDATE sessions SET duration_ms = ?1 WHERE id = ?2",
+            (duration_ms, id),
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn set_session_recovered(&self, id: &str, recovered: bool) -> Result<()> {
+        self.conn()?.execute(
+            "UPDATE sessions SET recovered = ?1 WHERE id = ?2",
+            (recovered, id),
+        )?;
+        
+        Ok(())
+    }
+    
     pub fn get_session(&self, id: &str) -> Result<Option<Session>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path, recovered, course_id
              FROM sessions WHERE id = ?1"
         )?;
         
@@ -165,20 +185,439 @@ impl Database {
                 notes_path: row.get(6)?,
                 audio_path: row.get(7)?,
                 transcript_path: row.get(8)?,
+                recovered: row.get(9)?,
+                course_id: row.get(10)?,
+                tags: Vec::new(),
             })
         })?;
         
-        Ok(session_iter.next().transpose()?)
+        let Some(mut session) = session_iter.next().transpose()? else {
+            return Ok(None);
+        };
+        session.tags = tags_by_session(&conn, Some(id))?.remove(id).unwrap_or_default();
+        
+        Ok(Some(session))
     }
     
     pub fn delete_session(&self, id: &str) -> Result<bool> {
-        let rows_affected = self.conn.execute(
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM search_documents WHERE session_id = ?1", [id])?;
+        tx.execute("DELETE FROM session_tags WHERE session_id = ?1", [id])?;
+        let rows_affected = tx.execute(
             "DELETE FROM sessions WHERE id = ?1",
             [id],
         )?;
+        tx.commit()?;
         
         Ok(rows_affected > 0)
     }
+    
+    /// File a session under `course`, or under no course at all.
+    pub fn set_session_course(&self, id: &str, course: Option<&Course>) -> Result<()> {
+        self.conn()?.execute(
+            "UPDATE sessions SET course_id = ?1, course = ?2 WHERE id = ?3",
+            (course.map(|c| &c.id), course.map_or("", |c| c.name.as_str()), id),
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn insert_course(&self, course: &Course) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO courses (id, name, code, term, color, instructor, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                &course.id,
+                &course.name,
+                &course.code,
+                &course.term,
+                &course.color,
+                &course.instructor,
+                course.created_at,
+            ),
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn list_courses(&self) -> Result<Vec<Course>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, code, term, color, instructor, created_at
+             FROM courses ORDER BY name COLLATE NOCASE"
+        )?;
+        
+        let courses = stmt.query_map([], course_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(courses)
+    }
+    
+    pub fn get_course(&self, id: &str) -> Result<Option<Course>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, code, term, color, instructor, created_at
+             FROM courses WHERE id = ?1"
+        )?;
+        
+        let mut course_iter = stmt.query_map([id], course_from_row)?;
+        Ok(course_iter.next().transpose()?)
+    }
+    
+    /// The course called `name`, ignoring case.
+    pub fn find_course_by_name(&self, name: &str) -> Result<Option<Course>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, code, term, color, instructor, created_at
+             FROM courses WHERE name = ?1 COLLATE NOCASE"
+        )?;
+        
+        let mut course_iter = stmt.query_map([name.trim()], course_from_row)?;
+        Ok(course_iter.next().transpose()?)
+    }
+    
+    /// Save changes to a course, renaming it on its sessions too.
+    pub fn update_course(&self, course: &Course) -> Result<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let rows_affected = tx.execute(
+            "UPDATE courses SET name = ?1, code = ?2, term = ?3, color = ?4, instructor = ?5 WHERE id = ?6",
+            (&course.name, &course.code, &course.term, &course.color, &course.instructor, &course.id),
+        )?;
+        tx.execute("UPDATE sessions SET course = ?1 WHERE course_id = ?2", (&course.name, &course.id))?;
+        tx.commit()?;
+        
+        Ok(rows_affected > 0)
+    }
+    
+    /// Delete a course. Its sessions are kept, filed under no course.
+    pub fn delete_course(&self, id: &str) -> Result<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("UPDATE sessions SET course_id = NULL, course = '' WHERE course_id = ?1", [id])?;
+        let rows_affected = tx.execute("DELETE FROM courses WHERE id = ?1", [id])?;
+        tx.commit()?;
+        
+        Ok(rows_affected > 0)
+    }
+    
+    /// Number of sessions and time recorded for every course, including those
+    /// with nothing in them yet.
+    pub fn course_stats(&self) -> Result<Vec<CourseStats>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT c.id, COUNT(s.id), COALESCE(SUM(s.duration_ms), 0)
+             FROM courses c LEFT JOIN sessions s ON s.course_id = c.id
+             GROUP BY c.id
+             ORDER BY c.name COLLATE NOCASE"
+        )?;
+        
+        let stats = stmt.query_map([], |row| {
+            Ok(CourseStats {
+                course_id: row.get(0)?,
+                session_count: row.get(1)?,
+                total_duration_ms: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+        Ok(stats)
+    }
+    
+    pub fn list_tags(&self) -> Result<Vec<Tag>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, created_at FROM tags ORDER BY name COLLATE NOCASE")?;
+        
+        let tags = stmt.query_map([], tag_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(tags)
+    }
+    
+    pub fn get_tag(&self, id: &str) -> Result<Option<Tag>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, created_at FROM tags WHERE id = ?1")?;
+        
+        let mut tag_iter = stmt.query_map([id], tag_from_row)?;
+        Ok(tag_iter.next().transpose()?)
+    }
+    
+    /// The tag called `name`, ignoring case.
+    pub fn find_tag_by_name(&self, name: &str) -> Result<Option<Tag>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, created_at FROM tags WHERE name = ?1 COLLATE NOCASE")?;
+        
+        let mut tag_iter = stmt.query_map([name], tag_from_row)?;
+        Ok(tag_iter.next().transpose()?)
+    }
+    
+    pub fn insert_tag(&self, tag: &Tag) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO tags (id, name, created_at) VALUES (?1, ?2, ?3)",
+            (&tag.id, &tag.name, tag.created_at),
+        )?;
+        
+        Ok(())
+    }
+    
+    /// Label a session with a tag. Adding a tag it already has does nothing.
+    pub fn add_session_tag(&self, session_id: &str, tag_id: &str) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT OR IGNORE INTO session_tags (session_id, tag_id) VALUES (?1, ?2)",
+            (session_id, tag_id),
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn remove_session_tag(&self, session_id: &str, tag_id: &str) -> Result<bool> {
+        let rows_affected = self.conn()?.execute(
+            "DELETE FROM session_tags WHERE session_id = ?1 AND tag_id = ?2",
+            (session_id, tag_id),
+        )?;
+        
+        Ok(rows_affected > 0)
+    }
+    
+    /// Rename a tag. Renaming onto a tag that already exists merges the two.
+    /// Returns the tag its sessions carry now, or `None` if there was no such tag.
+    pub fn rename_tag(&self, id: &str, name: &str) -> Result<Option<Tag>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        
+        if !tx.prepare("SELECT 1 FROM tags WHERE id = ?1")?.exists([id])? {
+            return Ok(None);
+        }
+        let existing = tx.query_row(
+            "SELECT id, name, created_at FROM tags WHERE name = ?1 COLLATE NOCASE AND id != ?2",
+            (name, id),
+            tag_from_row,
+        ).optional()?;
+        let renamed = match existing {
+            Some(into) => {
+                merge_tags_in(&tx, id, &into.id)?;
+                Some(into)
+            }
+            None => {
+                tx.execute("UPDATE tags SET name = ?1 WHERE id = ?2", (name, id))?;
+                tx.query_row("SELECT id, name, created_at FROM tags WHERE id = ?1", [id], tag_from_row).optional()?
+            }
+        };
+        tx.commit()?;
+        
+        Ok(renamed)
+    }
+    
+    /// Move every session carrying `from` over to `into`, then delete `from`.
+    pub fn merge_tags(&self, from: &str, into: &str) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        merge_tags_in(&tx, from, into)?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Delete a tag, taking it off every session that carries it.
+    pub fn delete_tag(&self, id: &str) -> Result<bool> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM session_tags WHERE tag_id = ?1", [id])?;
+        let rows_affected = tx.execute("DELETE FROM tags WHERE id = ?1", [id])?;
+        tx.commit()?;
+        
+        Ok(rows_affected > 0)
+    }
+    
+    /// Make a line just added to a session's transcript searchable.
+    pub fn index_transcript_line(&self, session_id: &str, line: &TranscriptLine) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT INTO search_documents (session_id, kind, t_ms, text) VALUES (?1, 'transcript', ?2, ?3)",
+            (session_id, line.t_ms as i64, &line.text),
+        )?;
+        
+        Ok(())
+    }
+    
+    /// Replace what is searchable of a session's transcript, after it was rewritten.
+    pub fn index_transcript(&self, session_id: &str, lines: &[TranscriptLine]) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM search_documents WHERE session_id = ?1 AND kind = 'transcript'", [session_id])?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO search_documents (session_id, kind, t_ms, text) VALUES (?1, 'transcript', ?2, ?3)"
+            )?;
+            for line in lines {
+                insert.execute((session_id, line.t_ms as i64, &line.text))?;
+            }
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Replace what is searchable of a session's notes.
+    pub fn index_notes(&self, session_id: &str, markdown: &str) -> Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM search_documents WHERE session_id = ?1 AND kind = 'notes'", [session_id])?;
+        if !markdown.trim().is_empty() {
+            tx.execute(
+                "INSERT INTO search_documents (session_id, kind, text) VALUES (?1, 'notes', ?2)",
+                (session_id, markdown),
+            )?;
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Sessions with nothing in the search index yet, such as those from
+    /// before there was one.
+    pub fn list_unindexed_sessions(&self) -> Result<Vec<Session>> {
+        let indexed: std::collections::HashSet<String> = {
+            let conn = self.conn()?;
+            let mut stmt = conn.prepare("SELECT DISTINCT session_id FROM search_documents")?;
+            let ids = stmt.query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            ids
+        };
+        
+        Ok(self.list_sessions()?
+            .into_iter()
+            .filter(|session| !indexed.contains(&session.id))
+            .collect())
+    }
+    
+    /// Search every session's transcript and notes, best matches first.
+    /// Each word of `query` matches words starting with it.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let Some(match_expression) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT d.session_id, d.kind, d.t_ms,
+                    snippet(search_index, 0, char(57344), char(57345), '…', 16)
+             FROM search_index
+             JOIN search_documents d ON d.id = search_index.rowid
+             WHERE search_index MATCH ?1
+             ORDER BY rank
+             LIMIT ?2"
+        )?;
+        
+        let hit_iter = stmt.query_map((&match_expression, limit as i64), |row| {
+            let kind: String = row.get(1)?;
+            let source = if kind == SearchSource::Notes.as_str() { SearchSource::Notes } else { SearchSource::Transcript };
+            let t_ms: Option<i64> = row.get(2)?;
+            let (snippet, highlights) = split_highlights(&row.get::<_, String>(3)?);
+            
+            Ok(SearchHit {
+                session_id: row.get(0)?,
+                source,
+                snippet,
+                highlights,
+                t_ms: t_ms.map(|t_ms| t_ms as u64),
+            })
+        })?;
+        
+        let mut hits = Vec::new();
+        for hit in hit_iter {
+            hits.push(hit?);
+        }
+        
+        Ok(hits)
+    }
+}
+
+fn tag_from_row(row: &rusqlite::Row) -> rusqlite::Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+    })
+}
+
+// Tags of one session, or of all of them, by session id
+fn tags_by_session(conn: &rusqlite::Connection, session_id: Option<&str>) -> Result<HashMap<String, Vec<Tag>>> {
+    let mut stmt = conn.prepare(
+        "SELECT st.session_id, t.id, t.name, t.created_at
+         FROM session_tags st JOIN tags t ON t.id = st.tag_id
+         WHERE ?1 IS NULL OR st.session_id = ?1
+         ORDER BY t.name COLLATE NOCASE"
+    )?;
+    
+    let mut tags: HashMap<String, Vec<Tag>> = HashMap::new();
+    let rows = stmt.query_map([session_id], |row| {
+        Ok((row.get::<_, String>(0)?, Tag { id: row.get(1)?, name: row.get(2)?, created_at: row.get(3)? }))
+    })?;
+    for row in rows {
+        let (session_id, tag) = row?;
+        tags.entry(session_id).or_default().push(tag);
+    }
+    
+    Ok(tags)
+}
+
+fn merge_tags_in(tx: &rusqlite::Transaction, from: &str, into: &str) -> rusqlite::Result<()> {
+    if from == into {
+        return Ok(());
+    }
+    tx.execute(
+        "INSERT OR IGNORE INTO session_tags (session_id, tag_id) SELECT session_id, ?2 FROM session_tags WHERE tag_id = ?1",
+        (from, into),
+    )?;
+    tx.execute("DELETE FROM session_tags WHERE tag_id = ?1", [from])?;
+    tx.execute("DELETE FROM tags WHERE id = ?1", [from])?;
+    Ok(())
+}
+
+fn course_from_row(row: &rusqlite::Row) -> rusqlite::Result<Course> {
+    Ok(Course {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        code: row.get(2)?,
+        term: row.get(3)?,
+        color: row.get(4)?,
+        instructor: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+// Quote every word so what users type is never read as FTS5 syntax, and let
+// each match as a prefix so results show up while still typing
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+// Strip the private-use marks `snippet()` puts around matches, noting where they were
+fn split_highlights(marked: &str) -> (String, Vec<[usize; 2]>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut offset = 0;
+    let mut start = None;
+    
+    for c in marked.chars() {
+        match c {
+            '\u{E000}' => start = Some(offset),
+            '\u{E001}' => {
+                if let Some(start) = start.take() {
+                    highlights.push([start, offset]);
+                }
+            }
+            _ => {
+                snippet.push(c);
+                offset += c.len_utf16();
+            }
+        }
+    }
+    
+    (snippet, highlights)
 }
 
 fn get_data_dir() -> Result<PathBuf> {
@@ -201,6 +640,24 @@ pub fn create_session_folder(session_id: &str) -> Result<PathBuf> {
     Ok(session_dir)
 }
 
+/// Every session folder on disk with the session id it belongs to.
+pub fn list_session_folders() -> Result<Vec<(String, PathBuf)>> {
+    let sessions_dir = get_sessions_dir()?;
+    if !sessions_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut folders = Vec::new();
+    for entry in fs::read_dir(&sessions_dir)? {
+        let path = entry?.path();
+        if let (true, Some(id)) = (path.is_dir(), path.file_name().and_then(|n| n.to_str())) {
+            folders.push((id.to_string(), path.clone()));
+        }
+    }
+    
+    Ok(folders)
+}
+
 pub fn delete_session_folder(session_id: &str) -> Result<()> {
     let sessions_dir = get_sessions_dir()?;
     let session_dir = sessions_dir.join(session_id);
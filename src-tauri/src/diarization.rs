@@ -0,0 +1,383 @@
+use anyhow::{Result, anyhow};
+use rustfft::{FftPlanner, num_complex::Complex};
+use std::collections::HashMap;
+use std::path::Path;
+use crate::codec::AudioReader;
+use crate::models::TranscriptLine;
+
+// Analysis frames, the usual speech front-end sizes
+const FRAME_MS: u32 = 25;
+const HOP_MS: u32 = 10;
+const MEL_BANDS: usize = 26;
+const CEPSTRA: usize = 12;
+const MAX_MEL_HZ: f32 = 8000.0;
+
+// Frames quieter than this (about -40 dBFS) say nothing about the speaker
+const VOICED_RMS: f32 = 0.01;
+// A line needs at least this many voiced frames (0.3s) to get its own embedding
+const MIN_VOICED_FRAMES: usize = 30;
+// Used for lines without an end time
+const DEFAULT_LINE_MS: u64 = 5000;
+
+/// Average distance between two lines' mean cepstra, in units of the
+/// session's frame-level spread, below which they are taken to be the same
+/// speaker. Line means of one voice sit well inside it; different voices
+/// usually land around 0.5 and above.
+pub const DISTANCE_THRESHOLD: f64 = 0.3;
+
+/// Label every line with "Speaker 1", "Speaker 2", ... based on the voice
+/// heard in `audio_path` between the line's start and end.
+///
+/// Each line gets an embedding from the mean MFCCs of its voiced frames, and
+/// the embeddings are grouped with average-linkage clustering. Without
+/// `num_speakers` the number of speakers is found with
+/// [`DISTANCE_THRESHOLD`]. Lines too short to tell take the speaker of the
+/// line before them. Returns the number of speakers found.
+pub fn diarize_lines(
+    audio_path: &Path,
+    lines: &mut [TranscriptLine],
+    num_speakers: Option<usize>,
+) -> Result<usize> {
+    if lines.is_empty() {
+        return Ok(0);
+    }
+    if num_speakers == Some(0) {
+        return Err(anyhow!("Number of speakers must be at least 1"));
+    }
+
+    let spans = line_spans(lines);
+    let embeddings = line_embeddings(audio_path, &spans)?;
+
+    let voiced: Vec<usize> = (0..lines.len()).filter(|&i| embeddings[i].is_some()).collect();
+    if voiced.is_empty() {
+        return Err(anyhow!("No speech found in the recording"));
+    }
+
+    let points: Vec<Vec<f64>> = voiced.iter()
+        .map(|&i| embeddings[i].clone().unwrap_or_default())
+        .collect();
+    let clusters = cluster_average_linkage(&points, DISTANCE_THRESHOLD, num_speakers);
+
+    let mut line_clusters: Vec<Option<usize>> = vec![None; lines.len()];
+    for (point, &line_index) in voiced.iter().enumerate() {
+        line_clusters[line_index] = Some(clusters[point]);
+    }
+
+    // Too-short lines belong to whoever spoke before them (or after, at the very start)
+    let first_voiced = line_clusters.iter().flatten().copied().next();
+    let mut previous = first_voiced;
+    for cluster in line_clusters.iter_mut() {
+        match cluster {
+            Some(c) => previous = Some(*c),
+            None => *cluster = previous,
+        }
+    }
+
+    // Number speakers in order of first appearance
+    let mut labels: HashMap<usize, usize> = HashMap::new();
+    for (line, cluster) in lines.iter_mut().zip(line_clusters) {
+        let cluster = cluster.unwrap_or_default();
+        let next_label = labels.len() + 1;
+        let label = *labels.entry(cluster).or_insert(next_label);
+        line.speaker = format!("Speaker {}", label);
+    }
+
+    Ok(labels.len())
+}
+
+// Where each line's voice is in the audio, as (start_ms, end_ms)
+fn line_spans(lines: &[TranscriptLine]) -> Vec<(u64, u64)> {
+    lines.iter().enumerate()
+        .map(|(i, line)| {
+            let end = line.end_ms
+                .or_else(|| lines.get(i + 1).map(|next| next.t_ms))
+                .filter(|&end| end > line.t_ms)
+                .unwrap_or(line.t_ms + DEFAULT_LINE_MS);
+            (line.t_ms, end)
+        })
+        .collect()
+}
+
+// One pass over the audio computing MFCCs frame by frame and averaging them
+// per line. Each dimension is scaled by its spread over all voiced frames of
+// the session so that no coefficient dominates the distance.
+fn line_embeddings(audio_path: &Path, spans: &[(u64, u64)]) -> Result<Vec<Option<Vec<f64>>>> {
+    let mut samples = AudioReader::open(audio_path)
+        .map_err(|e| anyhow!("Failed to open audio file {:?}: {}", audio_path, e))?;
+    let info = samples.info();
+    let channels = info.channels.max(1) as usize;
+    let sample_rate = info.sample_rate.max(1);
+
+    let frame_len = (sample_rate * FRAME_MS / 1000) as usize;
+    let hop_len = (sample_rate * HOP_MS / 1000) as usize;
+    let mut extractor = MfccExtractor::new(sample_rate, frame_len);
+
+    let mut sums = vec![[0f64; CEPSTRA]; spans.len()];
+    let mut counts = vec![0usize; spans.len()];
+    let mut global_sum = [0f64; CEPSTRA];
+    let mut global_sum_sq = [0f64; CEPSTRA];
+    let mut global_count = 0usize;
+
+    // Lines are in time order, so the first line that can still contain a frame only moves forward
+    let mut first_line = 0;
+    let mut frame_start = 0u64;
+    let mut window: Vec<f32> = Vec::with_capacity(frame_len + hop_len);
+    let mut frame = Vec::with_capacity(channels);
+
+    loop {
+        frame.clear();
+        for sample in samples.by_ref().take(channels) {
+            frame.push(sample?);
+        }
+        if frame.is_empty() {
+            break;
+        }
+        window.push(frame.iter().sum::<f32>() / frame.len() as f32);
+        if window.len() < frame_len {
+            continue;
+        }
+
+        let center_ms = (frame_start + frame_len as u64 / 2) * 1000 / sample_rate as u64;
+        while first_line < spans.len() && spans[first_line].1 <= center_ms {
+            first_line += 1;
+        }
+        if first_line == spans.len() {
+            break;
+        }
+
+        if let Some(cepstra) = extractor.voiced_cepstra(&window[..frame_len]) {
+            for d in 0..CEPSTRA {
+                global_sum[d] += cepstra[d];
+                global_sum_sq[d] += cepstra[d] * cepstra[d];
+            }
+            global_count += 1;
+
+            // Overlapping lines all hear the frame
+            for line in first_line..spans.len() {
+                let (start, end) = spans[line];
+                if start > center_ms {
+                    break;
+                }
+                if center_ms < end {
+                    for d in 0..CEPSTRA {
+                        sums[line][d] += cepstra[d];
+                    }
+                    counts[line] += 1;
+                }
+            }
+        }
+
+        window.drain(..hop_len);
+        frame_start += hop_len as u64;
+    }
+
+    let mut scale = [1f64; CEPSTRA];
+    if global_count > 1 {
+        for d in 0..CEPSTRA {
+            let mean = global_sum[d] / global_count as f64;
+            let variance = global_sum_sq[d] / global_count as f64 - mean * mean;
+            scale[d] = 1.0 / variance.max(1e-9).sqrt();
+        }
+    }
+
+    Ok(sums.iter().zip(&counts)
+        .map(|(sum, &count)| {
+            (count >= MIN_VOICED_FRAMES).then(|| {
+                (0..CEPSTRA).map(|d| sum[d] / count as f64 * scale[d]).collect()
+            })
+        })
+        .collect())
+}
+
+/// Mel-frequency cepstral coefficients for single analysis frames.
+struct MfccExtractor {
+    fft: std::sync::Arc<dyn rustfft::Fft<f32>>,
+    window: Vec<f32>,
+    filters: Vec<Vec<(usize, f32)>>,
+    buffer: Vec<Complex<f32>>,
+    log_energies: Vec<f64>,
+}
+
+impl MfccExtractor {
+    fn new(sample_rate: u32, frame_len: usize) -> Self {
+        let fft_len = frame_len.next_power_of_two();
+        let fft = FftPlanner::new().plan_fft_forward(fft_len);
+
+        let window = (0..frame_len)
+            .map(|n| 0.54 - 0.46 * (2.0 * std::f32::consts::PI * n as f32 / (frame_len - 1).max(1) as f32).cos())
+            .collect();
+
+        MfccExtractor {
+            fft,
+            window,
+            filters: mel_filterbank(sample_rate, fft_len),
+            buffer: vec![Complex::default(); fft_len],
+            log_energies: vec![0.0; MEL_BANDS],
+        }
+    }
+
+    // None for frames too quiet to carry a voice
+    fn voiced_cepstra(&mut self, frame: &[f32]) -> Option<[f64; CEPSTRA]> {
+        let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt();
+        if rms < VOICED_RMS {
+            return None;
+        }
+
+        for (i, slot) in self.buffer.iter_mut().enumerate() {
+            let sample = frame.get(i).map_or(0.0, |s| s * self.window[i]);
+            *slot = Complex::new(sample, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        for (band, filter) in self.filters.iter().enumerate() {
+            let energy: f32 = filter.iter().map(|&(bin, weight)| self.buffer[bin].norm_sqr() * weight).sum();
+            self.log_energies[band] = (energy as f64 + 1e-10).ln();
+        }
+
+        // DCT-II of the log energies, skipping c0 (overall loudness)
+        let mut cepstra = [0f64; CEPSTRA];
+        for (k, coefficient) in cepstra.iter_mut().enumerate() {
+            let k = k + 1;
+            *coefficient = self.log_energies.iter().enumerate()
+                .map(|(m, e)| e * (std::f64::consts::PI * k as f64 * (m as f64 + 0.5) / MEL_BANDS as f64).cos())
+                .sum();
+        }
+        Some(cepstra)
+    }
+}
+
+// Triangular filters evenly spaced on the mel scale, as (fft bin, weight) lists
+fn mel_filterbank(sample_rate: u32, fft_len: usize) -> Vec<Vec<(usize, f32)>> {
+    let hz_to_mel = |hz: f32| 2595.0 * (1.0 + hz / 700.0).log10();
+    let mel_to_hz = |mel: f32| 700.0 * (10f32.powf(mel / 2595.0) - 1.0);
+
+    let max_hz = MAX_MEL_HZ.min(sample_rate as f32 / 2.0);
+    let min_mel = hz_to_mel(60.0);
+    let max_mel = hz_to_mel(max_hz);
+    let bin_hz = sample_rate as f32 / fft_len as f32;
+
+    let edges: Vec<f32> = (0..MEL_BANDS + 2)
+        .map(|i| mel_to_hz(min_mel + (max_mel - min_mel) * i as f32 / (MEL_BANDS + 1) as f32))
+        .collect();
+
+    (0..MEL_BANDS)
+        .map(|band| {
+            let (low, center, high) = (edges[band], edges[band + 1], edges[band + 2]);
+            (0..=fft_len / 2)
+                .filter_map(|bin| {
+                    let hz = bin as f32 * bin_hz;
+                    let weight = if hz > low && hz <= center {
+                        (hz - low) / (center - low)
+                    } else if hz > center && hz < high {
+                        (high - hz) / (high - center)
+                    } else {
+                        0.0
+                    };
+                    (weight > 0.0).then_some((bin, weight))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Group points with average-linkage agglomerative clustering.
+///
+/// Clusters are merged while their average distance (root mean square over
+/// the dimensions) stays within `threshold`, or, when `num_clusters` is
+/// given, until exactly that many are left. Returns a cluster index per
+/// point; indices are only meaningful for comparing points.
+pub fn cluster_average_linkage(points: &[Vec<f64>], threshold: f64, num_clusters: Option<usize>) -> Vec<usize> {
+    let n = points.len();
+    if n <= 1 {
+        return vec![0; n];
+    }
+
+    let mut dist = vec![0f64; n * n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let d = rms_distance(&points[i], &points[j]);
+            dist[i * n + j] = d;
+            dist[j * n + i] = d;
+        }
+    }
+
+    // Nearest-neighbour chain: builds the whole dendrogram in O(n²).
+    // A merged cluster lives on under the index of its first member.
+    let mut size = vec![1usize; n];
+    let mut active = vec![true; n];
+    let mut merges: Vec<(usize, usize, f64)> = Vec::with_capacity(n - 1);
+    let mut chain: Vec<usize> = Vec::new();
+    let mut remaining = n;
+
+    while remaining > 1 {
+        if chain.is_empty() {
+            chain.extend((0..n).find(|&i| active[i]));
+        }
+        let a = chain[chain.len() - 1];
+        let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+
+        // Prefer the previous chain link on ties so the chain always ends
+        let mut nearest = previous;
+        let mut nearest_dist = previous.map_or(f64::INFINITY, |p| dist[a * n + p]);
+        for j in 0..n {
+            if active[j] && j != a && dist[a * n + j] < nearest_dist {
+                nearest = Some(j);
+                nearest_dist = dist[a * n + j];
+            }
+        }
+        let Some(b) = nearest else { break };
+
+        if Some(b) != previous {
+            chain.push(b);
+            continue;
+        }
+
+        chain.truncate(chain.len() - 2);
+        merges.push((a, b, nearest_dist));
+        for k in 0..n {
+            if active[k] && k != a && k != b {
+                let merged = (size[a] as f64 * dist[a * n + k] + size[b] as f64 * dist[b * n + k])
+                    / (size[a] + size[b]) as f64;
+                dist[a * n + k] = merged;
+                dist[k * n + a] = merged;
+            }
+        }
+        size[a] += size[b];
+        active[b] = false;
+        remaining -= 1;
+    }
+
+    // Average linkage never merges below an earlier merge, so cutting the
+    // dendrogram is applying the merges in order of distance
+    merges.sort_by(|x, y| x.2.total_cmp(&y.2));
+
+    let mut parent: Vec<usize> = (0..n).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut clusters = n;
+    for (a, b, d) in merges {
+        let done = match num_clusters {
+            Some(target) => clusters <= target,
+            None => d > threshold,
+        };
+        if done {
+            break;
+        }
+        let (root_a, root_b) = (root(&mut parent, a), root(&mut parent, b));
+        parent[root_b] = root_a;
+        clusters -= 1;
+    }
+
+    (0..n).map(|i| root(&mut parent, i)).collect()
+}
+
+fn rms_distance(a: &[f64], b: &[f64]) -> f64 {
+    let sum_sq: f64 = a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum();
+    (sum_sq / a.len().max(1) as f64).sqrt()
+}
@@ -0,0 +1,27 @@
+use anyhow::Result;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Move a fully written `temp_path` over `path`, so that even after a power
+/// loss the file is either what it was or the complete new version.
+///
+/// Without syncing first the rename can reach the disk before the data does,
+/// leaving an empty or half-written file in place of the old one.
+pub fn replace(temp_path: &Path, path: &Path) -> Result<()> {
+    File::open(temp_path)?.sync_all()?;
+    fs::rename(temp_path, path)?;
+    sync_parent(path)
+}
+
+/// Make files created, renamed or removed next to `path` survive a power loss.
+pub fn sync_parent(path: &Path) -> Result<()> {
+    // Windows can't open a directory as a file, and doesn't need it for renames
+    #[cfg(unix)]
+    {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
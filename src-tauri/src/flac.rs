@@ -0,0 +1,633 @@
+use anyhow::{Result, anyhow};
+use std::fs::File;
+use claxon::frame::FrameReader;
+use claxon::input::BufferedReader;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Samples per channel in one frame, the size the reference encoder uses
+const BLOCK_SIZE: usize = 4096;
+// Highest fixed predictor order FLAC defines
+const MAX_FIXED_ORDER: usize = 4;
+// Residuals are split into at most 2^MAX_PARTITION_ORDER Rice partitions
+const MAX_PARTITION_ORDER: u32 = 6;
+// Rice parameters above this need the escape code, which isn't worth it
+const MAX_RICE_PARAMETER: u32 = 14;
+// Seeking stops bisecting and decodes on once it is this close
+const LINEAR_SEEK_BYTES: u64 = 64 * 1024;
+// Bytes read at a time while looking for a frame header
+const SCAN_BYTES: usize = 16 * 1024;
+// Longest a frame header can be, with a 7 byte sample number and the CRC
+const MAX_FRAME_HEADER: usize = 16;
+
+/// Lossless FLAC encoder for integer PCM.
+///
+/// Every block is coded with the best of FLAC's fixed polynomial predictors
+/// (or verbatim/constant where that is smaller) and partitioned Rice coding.
+/// That is a little larger than what `flac -8` produces, but fast and simple.
+/// The stream info is patched in by [`FlacWriter::finalize`], so the output
+/// must be seekable.
+pub struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    channels: usize,
+    bits_per_sample: u32,
+    sample_rate: u32,
+    // Interleaved samples waiting for a full block
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_frames: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl FlacWriter<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16, bits_per_sample: u16) -> Result<Self> {
+        FlacWriter::new(BufWriter::new(File::create(path)?), sample_rate, channels, bits_per_sample)
+    }
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32, channels: u16, bits_per_sample: u16) -> Result<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(anyhow!("FLAC supports 1 to 8 channels, not {}", channels));
+        }
+        if sample_size_code(bits_per_sample as u32).is_none() {
+            return Err(anyhow!("Can't store {} bits per sample as FLAC", bits_per_sample));
+        }
+        if sample_rate == 0 || sample_rate >= 1 << 20 {
+            return Err(anyhow!("Invalid sample rate for FLAC: {}", sample_rate));
+        }
+
+        // Placeholder stream info, rewritten once the totals are known
+        writer.write_all(b"fLaC")?;
+        writer.write_all(&[0; 38])?;
+
+        Ok(FlacWriter {
+            writer,
+            channels: channels as usize,
+            bits_per_sample: bits_per_sample as u32,
+            sample_rate,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_frames: 0,
+            min_frame_size: u32::MAX,
+            max_frame_size: 0,
+        })
+    }
+
+    /// Add interleaved samples, which must fit in the stream's bits per sample.
+    pub fn write_samples(&mut self, samples: &[i32]) -> Result<()> {
+        let block_len = BLOCK_SIZE * self.channels;
+        for chunk in samples.chunks(block_len) {
+            let take = (block_len - self.pending.len()).min(chunk.len());
+            self.pending.extend_from_slice(&chunk[..take]);
+            if self.pending.len() == block_len {
+                self.write_frame()?;
+            }
+            self.pending.extend_from_slice(&chunk[take..]);
+        }
+        Ok(())
+    }
+
+    /// Write the last partial block and the stream info.
+    pub fn finalize(mut self) -> Result<W> {
+        self.pending.truncate(self.pending.len() / self.channels * self.channels);
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+
+        let info = self.stream_info();
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&info)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    // STREAMINFO as the only, and so last, metadata block
+    fn stream_info(&self) -> Vec<u8> {
+        let block_size = if self.total_frames < BLOCK_SIZE as u64 { self.total_frames.max(16) } else { BLOCK_SIZE as u64 };
+        let mut bits = BitWriter::new();
+        bits.write(1, 1);
+        bits.write(0, 7);
+        bits.write(34, 24);
+        bits.write(block_size, 16);
+        bits.write(block_size, 16);
+        bits.write(if self.max_frame_size == 0 { 0 } else { self.min_frame_size as u64 }, 24);
+        bits.write(self.max_frame_size as u64, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.channels as u64 - 1, 3);
+        bits.write(self.bits_per_sample as u64 - 1, 5);
+        bits.write(self.total_frames, 36);
+        // No MD5 signature, which the format allows
+        bits.write(0, 64);
+        bits.write(0, 64);
+        bits.into_bytes()
+    }
+
+    fn write_frame(&mut self) -> Result<()> {
+        let block_size = self.pending.len() / self.channels;
+        let mut bits = BitWriter::new();
+
+        // Header: fixed block size stream, block size and frame number spelled out
+        bits.write(0b1111_1111_1111_1000, 16);
+        bits.write(0b0111, 4);
+        bits.write(0b0000, 4);
+        bits.write(self.channels as u64 - 1, 4);
+        bits.write(sample_size_code(self.bits_per_sample).unwrap_or(0), 3);
+        bits.write(0, 1);
+        bits.write_utf8(self.frame_number);
+        bits.write(block_size as u64 - 1, 16);
+        let crc = crc8(bits.bytes());
+        bits.write(crc as u64, 8);
+
+        let mut channel = Vec::with_capacity(block_size);
+        for index in 0..self.channels {
+            channel.clear();
+            channel.extend(self.pending.iter().skip(index).step_by(self.channels).map(|&s| s as i64));
+            write_subframe(&mut bits, &channel, self.bits_per_sample);
+        }
+
+        bits.align();
+        let crc = crc16(bits.bytes());
+        bits.write(crc as u64, 16);
+
+        let frame = bits.into_bytes();
+        self.writer.write_all(&frame)?;
+        self.min_frame_size = self.min_frame_size.min(frame.len() as u32);
+        self.max_frame_size = self.max_frame_size.max(frame.len() as u32);
+        self.frame_number += 1;
+        self.total_frames += block_size as u64;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+// Frame header code for a sample size. The code for "see stream info" would
+// cover any size, but not every decoder supports it.
+fn sample_size_code(bits_per_sample: u32) -> Option<u64> {
+    match bits_per_sample {
+        8 => Some(0b001),
+        12 => Some(0b010),
+        16 => Some(0b100),
+        20 => Some(0b101),
+        24 => Some(0b110),
+        _ => None,
+    }
+}
+
+fn write_subframe(bits: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    // Digital silence and other flat stretches cost one sample
+    if samples.iter().all(|&s| s == samples[0]) {
+        write_subframe_header(bits, 0b000000);
+        bits.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    // The predictor with the smallest residual almost always codes smallest too
+    let order = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .min_by_key(|&order| fixed_residual(samples, order).map(|r| r.unsigned_abs()).sum::<u64>())
+        .unwrap_or(0);
+    let residual: Vec<u64> = fixed_residual(samples, order).map(fold).collect();
+    let (partition_order, parameters, residual_bits) = best_partitioning(&residual, samples.len(), order);
+
+    let fixed_bits = 8 + order as u64 * bits_per_sample as u64 + 6 + residual_bits;
+    let verbatim_bits = 8 + samples.len() as u64 * bits_per_sample as u64;
+    if fixed_bits >= verbatim_bits {
+        write_subframe_header(bits, 0b000001);
+        for &sample in samples {
+            bits.write_signed(sample, bits_per_sample);
+        }
+        return;
+    }
+
+    write_subframe_header(bits, 0b001000 | order as u64);
+    for &sample in &samples[..order] {
+        bits.write_signed(sample, bits_per_sample);
+    }
+
+    // Rice coding with 4 bit parameters, then the partitions
+    bits.write(0b00, 2);
+    bits.write(partition_order as u64, 4);
+    let mut start = 0;
+    for (partition, &parameter) in parameters.iter().enumerate() {
+        let len = partition_len(samples.len(), partition_order, partition, order);
+        bits.write(parameter as u64, 4);
+        for &value in &residual[start..start + len] {
+            bits.write_rice(value, parameter);
+        }
+        start += len;
+    }
+}
+
+// Padding bit, subframe type, no wasted bits
+fn write_subframe_header(bits: &mut BitWriter, subframe_type: u64) {
+    bits.write(0, 1);
+    bits.write(subframe_type, 6);
+    bits.write(0, 1);
+}
+
+// Prediction error of the fixed polynomial predictor of `order`, from sample `order` on
+fn fixed_residual(samples: &[i64], order: usize) -> impl Iterator<Item = i64> + '_ {
+    (order..samples.len()).map(move |i| {
+        let s = |back: usize| samples[i - back];
+        match order {
+            0 => s(0),
+            1 => s(0) - s(1),
+            2 => s(0) - 2 * s(1) + s(2),
+            3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+            _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+        }
+    })
+}
+
+// Signed to unsigned so that small magnitudes of either sign stay small
+fn fold(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// Residuals in a partition; the first one is short by the predictor's warm-up samples
+fn partition_len(block_size: usize, partition_order: u32, partition: usize, order: usize) -> usize {
+    let len = block_size >> partition_order;
+    if partition == 0 { len - order } else { len }
+}
+
+// Partition order and per-partition Rice parameters that code the residual in
+// the fewest bits, and that number of bits
+fn best_partitioning(residual: &[u64], block_size: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        if !block_size.is_multiple_of(partitions) || block_size >> partition_order <= order {
+            break;
+        }
+
+        let mut parameters = Vec::with_capacity(partitions);
+        let mut total_bits = 0;
+        let mut start = 0;
+        for partition in 0..partitions {
+            let len = partition_len(block_size, partition_order, partition, order);
+            let (parameter, bits) = best_rice_parameter(&residual[start..start + len]);
+            parameters.push(parameter);
+            total_bits += 4 + bits;
+            start += len;
+        }
+
+        if best.as_ref().is_none_or(|(_, _, best_bits)| total_bits < *best_bits) {
+            best = Some((partition_order, parameters, total_bits));
+        }
+    }
+
+    best.unwrap_or((0, vec![0], 0))
+}
+
+fn best_rice_parameter(values: &[u64]) -> (u32, u64) {
+    let rice_bits = |parameter: u32| -> u64 {
+        values.iter().map(|&v| (v >> parameter) + 1 + parameter as u64).sum()
+    };
+
+    // The optimum is close to log2 of the mean, check around it
+    let mean = values.iter().sum::<u64>() / values.len().max(1) as u64;
+    let guess = (64 - mean.leading_zeros()).min(MAX_RICE_PARAMETER);
+    (guess.saturating_sub(1)..=(guess + 1).min(MAX_RICE_PARAMETER))
+        .map(|parameter| (parameter, rice_bits(parameter)))
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter { bytes: Vec::new(), accumulator: 0, pending_bits: 0 }
+    }
+
+    // Lowest `count` bits of `value`, most significant first; count is at most 64
+    fn write(&mut self, value: u64, count: u32) {
+        if count > 32 {
+            self.write(value >> 32, count - 32);
+            self.write(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        let value = if count == 64 { value } else { value & ((1u64 << count) - 1) };
+        self.accumulator = (self.accumulator << count) | value;
+        self.pending_bits += count;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.accumulator >> self.pending_bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, count: u32) {
+        self.write(value as u64, count);
+    }
+
+    fn write_rice(&mut self, value: u64, parameter: u32) {
+        let mut quotient = value >> parameter;
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        self.write(1, quotient as u32 + 1);
+        self.write(value, parameter);
+    }
+
+    // FLAC's UTF-8 style variable length number, as used for the frame number
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        let significant_bits = 64 - value.leading_zeros();
+        // Each continuation byte holds 6 bits, the first byte 6 - continuation bytes
+        let continuation_bytes = (1..=6).find(|&n| significant_bits <= 6 - n + 6 * n).unwrap_or(6);
+        let prefix = !(0xFFu64 >> (continuation_bytes + 1)) & 0xFF;
+        self.write(prefix | (value >> (6 * continuation_bytes)), 8);
+        for i in (0..continuation_bytes).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+
+    // Complete bytes written so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Samples of a FLAC file as f32 in -1.0..=1.0, decoded a block at a time.
+pub struct FlacSamples {
+    path: PathBuf,
+    info: claxon::metadata::StreamInfo,
+    frames: FrameReader<BufferedReader<File>>,
+    // Where the first frame starts, after the metadata blocks
+    audio_offset: u64,
+    file_len: u64,
+    channels: usize,
+    scale: f32,
+    block: Vec<i32>,
+    position: usize,
+    done: bool,
+    decoded_blocks: u64,
+}
+
+impl FlacSamples {
+    pub fn open(path: &Path) -> Result<(claxon::metadata::StreamInfo, Self)> {
+        let info = claxon::FlacReader::open(path)
+            .map_err(|e| anyhow!("Failed to read FLAC file {:?}: {}", path, e))?
+            .streaminfo();
+        let mut file = File::open(path)?;
+        let audio_offset = skip_metadata(&mut file)?;
+        let file_len = file.metadata()?.len();
+
+        let samples = FlacSamples {
+            path: path.to_path_buf(),
+            info,
+            frames: FrameReader::new(BufferedReader::new(file)),
+            audio_offset,
+            file_len,
+            channels: info.channels as usize,
+            scale: 1.0 / (1i64 << (info.bits_per_sample - 1)) as f32,
+            block: Vec::new(),
+            position: 0,
+            done: false,
+            decoded_blocks: 0,
+        };
+        Ok((info, samples))
+    }
+
+    /// Continue from `frame`. The frame it is in is found by bisecting the
+    /// file on frame headers, so a jump costs a few small reads and decoding
+    /// a block or two, wherever it lands.
+    pub fn seek(&mut self, frame: u64) -> Result<()> {
+        let (offset, mut block_start) = self.find_frame_before(frame)?;
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        self.frames = FrameReader::new(BufferedReader::new(file));
+        self.block.clear();
+        self.position = 0;
+        self.done = false;
+
+        while self.read_block()? {
+            let frames = (self.block.len() / self.channels) as u64;
+            if block_start + frames > frame {
+                self.position = (frame - block_start) as usize * self.channels;
+                break;
+            }
+            block_start += frames;
+        }
+        Ok(())
+    }
+
+    /// How many blocks have been decoded since the file was opened, which is
+    /// what reading and seeking cost.
+    pub fn decoded_blocks(&self) -> u64 {
+        self.decoded_blocks
+    }
+
+    // Byte offset and first sample of a frame starting at or before `target`,
+    // close enough that decoding on from there is cheap
+    fn find_frame_before(&self, target: u64) -> Result<(u64, u64)> {
+        let mut file = File::open(&self.path)?;
+        let (mut low, mut low_sample) = (self.audio_offset, 0);
+        let mut high = self.file_len;
+        while high - low > LINEAR_SEEK_BYTES {
+            let middle = low + (high - low) / 2;
+            match find_frame_header(&mut file, middle, high, &self.info)? {
+                Some((offset, sample)) if sample <= target => (low, low_sample) = (offset, sample),
+                _ => high = middle,
+            }
+        }
+        Ok((low, low_sample))
+    }
+
+    // Decode the next block into `block`; false at the end of the stream
+    fn read_block(&mut self) -> Result<bool> {
+        let buffer = std::mem::take(&mut self.block);
+        match self.frames.read_next_or_eof(buffer) {
+            Ok(Some(block)) => {
+                // Blocks hold one channel after the other, interleave them
+                let frames = block.duration() as usize;
+                self.block = (0..frames * self.channels)
+                    .map(|i| block.sample((i % self.channels) as u32, (i / self.channels) as u32))
+                    .collect();
+                self.position = 0;
+                self.decoded_blocks += 1;
+                Ok(true)
+            }
+            Ok(None) => {
+                self.done = true;
+                Ok(false)
+            }
+            Err(e) => {
+                self.done = true;
+                Err(anyhow!("Failed to decode FLAC: {}", e))
+            }
+        }
+    }
+}
+
+impl Iterator for FlacSamples {
+    type Item = Result<f32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position >= self.block.len() {
+            if self.done {
+                return None;
+            }
+            match self.read_block() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let sample = self.block[self.position];
+        self.position += 1;
+        Some(Ok(sample as f32 * self.scale))
+    }
+}
+
+// Move past "fLaC" and the metadata blocks; returns where the first frame starts
+fn skip_metadata(file: &mut File) -> Result<u64> {
+    let mut marker = [0u8; 4];
+    file.read_exact(&mut marker)?;
+    if &marker != b"fLaC" {
+        return Err(anyhow!("Not a FLAC file"));
+    }
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        let offset = file.seek(SeekFrom::Current(len as i64))?;
+        if header[0] & 0x80 != 0 {
+            return Ok(offset);
+        }
+    }
+}
+
+// Offset and first sample of the first frame whose header starts between
+// `from` and `to`, if there is one
+fn find_frame_header(file: &mut File, from: u64, to: u64, info: &claxon::metadata::StreamInfo) -> Result<Option<(u64, u64)>> {
+    let mut start = from;
+    let mut window = Vec::with_capacity(SCAN_BYTES + MAX_FRAME_HEADER);
+    while start < to {
+        file.seek(SeekFrom::Start(start))?;
+        window.clear();
+        // A header starting near the end of the window runs on past it
+        (&mut *file).take((SCAN_BYTES + MAX_FRAME_HEADER) as u64).read_to_end(&mut window)?;
+        let candidates = window.len().min(SCAN_BYTES).min((to - start) as usize);
+        for i in 0..candidates {
+            if let Some(sample) = parse_frame_header(&window[i..], info) {
+                return Ok(Some((start + i as u64, sample)));
+            }
+        }
+        if window.len() < SCAN_BYTES + MAX_FRAME_HEADER {
+            break;
+        }
+        start += SCAN_BYTES as u64;
+    }
+    Ok(None)
+}
+
+// First sample of the frame whose header `bytes` starts with. The sync code
+// can also turn up inside audio data, so everything a header can be checked
+// against is.
+fn parse_frame_header(bytes: &[u8], info: &claxon::metadata::StreamInfo) -> Option<u64> {
+    if bytes.len() < 5 || bytes[0] != 0xFF || bytes[1] & 0xFE != 0xF8 {
+        return None;
+    }
+    let variable_block_size = bytes[1] & 1 == 1;
+    let block_size_code = bytes[2] >> 4;
+    let rate_code = bytes[2] & 0x0F;
+    if block_size_code == 0 || rate_code == 0x0F || bytes[3] & 1 != 0 {
+        return None;
+    }
+    let channels = match bytes[3] >> 4 {
+        code @ 0..=7 => code as u32 + 1,
+        8..=10 => 2,
+        _ => return None,
+    };
+    let bits_per_sample = match (bytes[3] >> 1) & 0x07 {
+        0 => info.bits_per_sample,
+        1 => 8,
+        2 => 12,
+        4 => 16,
+        5 => 20,
+        6 => 24,
+        _ => return None,
+    };
+    if channels != info.channels || bits_per_sample != info.bits_per_sample {
+        return None;
+    }
+
+    let (number, number_len) = read_utf8(&bytes[4..])?;
+    let len = 4 + number_len
+        + match block_size_code { 6 => 1, 7 => 2, _ => 0 }
+        + match rate_code { 12 => 1, 13 | 14 => 2, _ => 0 };
+    if bytes.len() <= len || crc8(&bytes[..len]) != bytes[len] {
+        return None;
+    }
+
+    let sample = if variable_block_size { number } else { number * info.max_block_size as u64 };
+    match info.samples {
+        Some(total) if sample >= total => None,
+        _ => Some(sample),
+    }
+}
+
+// The frame or sample number as written by `BitWriter::write_utf8`, and its length in bytes
+fn read_utf8(bytes: &[u8]) -> Option<(u64, usize)> {
+    let first = *bytes.first()?;
+    let len = first.leading_ones() as usize;
+    if len == 0 {
+        return Some((first as u64, 1));
+    }
+    if len == 1 || len > 7 || bytes.len() < len {
+        return None;
+    }
+    let mut value = (first & (0x7F >> len)) as u64;
+    for &byte in &bytes[1..len] {
+        if byte & 0xC0 != 0x80 {
+            return None;
+        }
+        value = (value << 6) | (byte & 0x3F) as u64;
+    }
+    Some((value, len))
+}
@@ -1,26 +1,41 @@
 pub mod db;
+pub mod migrations;
 pub mod models;
 pub mod audio;
 pub mod speech;
+pub mod transcript;
+pub mod diarization;
+pub mod vad;
+pub mod settings;
+pub mod resample;
+pub mod codec;
+pub mod flac;
+pub mod wav;
+pub mod durable;
+pub mod timestretch;
+pub mod playback;
+pub mod waveform;
+pub mod meter;
+#[cfg(feature = "opus")]
+pub mod opus;
+#[cfg(feature = "whisper")]
+pub mod whisper;
 
-use crate::db::{Database, create_session_folder, delete_session_folder};
-use crate::models::{Session, SessionStatus, TranscriptLine};
+use crate::db::{Database, create_session_folder, delete_session_folder, list_session_folders};
+use crate::models::{Course, CourseInput, CourseStats, SearchHit, Session, SessionFilter, SessionStatus, Tag, TranscriptLine, TranscriptWord};
 use crate::audio::{start_recording_simple, stop_recording_simple, pause_recording_simple, resume_recording_simple};
-use crate::speech::{start_speech_processing, stop_speech_processing};
-use std::process::{Child, Command};
+use crate::speech::{start_speech_processing, stop_speech_processing, start_session_transcription, cancel_session_transcription};
+use crate::vad::{VadConfig, SilenceSpan};
+use crate::settings::AppSettings;
 use anyhow::Result;
-use std::sync::Mutex;
 use std::str::FromStr;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 use tauri::State;
 use nanoid::nanoid;
 
 // Database state
 pub struct AppState {
-    db: Mutex<Database>,
-    audio_process: Mutex<Option<Child>>,
+    db: Database,
 }
 
 // Helper function to get session directory
@@ -35,6 +50,19 @@ fn get_session_file_path(session_id: &str, filename: &str) -> Result<PathBuf, St
     Ok(session_dir.join(filename))
 }
 
+// Helper function to find a session's recording, whichever format it is stored in
+fn get_session_audio_path(session_id: &str) -> Result<PathBuf, String> {
+    let session_dir = get_session_dir(session_id)?;
+    codec::find_session_audio(&session_dir)
+        .ok_or_else(|| "No audio recording found for this session".to_string())
+}
+
+// Decoding, encoding and analysing a long recording takes seconds, so commands
+// do it on a blocking thread instead of holding up the async runtime
+async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(work).await.map_err(|e| e.to_string())
+}
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -49,18 +77,29 @@ fn test_backend() -> String {
 }
 
 #[tauri::command]
-async fn cmd_list_sessions(state: State<'_, AppState>) -> Result<Vec<Session>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.list_sessions().map_err(|e| e.to_string())
+async fn cmd_list_sessions(
+    filter: Option<SessionFilter>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Session>, String> {
+    let db = &state.db;
+    db.list_sessions_matching(&filter.unwrap_or_default()).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn cmd_create_session(
     title: String,
     course: String,
+    course_id: Option<String>,
     state: State<'_, AppState>,
 ) -> Result<Session, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let db = &state.db;
+    
+    let course = match course_id {
+        Some(course_id) => Some(db.get_course(&course_id)
+            .map_err(|e| e.to_string())?
+            .ok_or("Course not found")?),
+        None => find_or_create_course(db, &course)?,
+    };
     
     let session_id = nanoid!();
     
@@ -70,13 +109,16 @@ async fn cmd_create_session(
     let session = Session {
         id: session_id,
         title,
-        course,
+        course: course.as_ref().map(|c| c.name.clone()).unwrap_or_default(),
+        course_id: course.map(|c| c.id),
         created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
         duration_ms: 0,
         status: SessionStatus::Draft,
         notes_path: Some("notes.md".to_string()),
         audio_path: Some("audio.wav".to_string()),
         transcript_path: Some("transcript.jsonl".to_string()),
+        recovered: false,
+        tags: Vec::new(),
     };
     
     db.insert_session(&session).map_err(|e| e.to_string())?;
@@ -89,12 +131,278 @@ async fn cmd_update_session_status(
     status: String,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let db = &state.db;
     
     let status_enum = SessionStatus::from_str(&status)
         .map_err(|e| e.to_string())?;
     
-    db.update_session_status(&id, &status_enum).map_err(|e| e.to_string())
+    db.update_session_status(&id, &status_enum).map_err(|e| e.to_string())?;
+    
+    // A session that is no longer a draft is stored the way it was held back from
+    let storage_format = session_storage_format(&status_enum);
+    let session_dir = get_session_dir(&id)?;
+    let audio_path = codec::find_session_audio(&session_dir);
+    let restore = !matches!(status_enum, SessionStatus::Draft)
+        && !crate::audio::is_recording(&id)
+        && audio_path.is_some_and(|path| !path.ends_with(storage_format.file_name()));
+    if restore {
+        playback::stop_session(&id);
+        let stored = run_blocking(move || {
+            codec::restore_wav(&session_dir)?;
+            codec::store_recording(&session_dir.join("audio.wav"), storage_format)
+        }).await?;
+        match stored {
+            Ok(audio_path) => {
+                let file_name = audio_path.file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("audio.wav");
+                db.update_session_audio_path(&id, file_name).map_err(|e| e.to_string())?;
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to store recording as {:?}: {}", storage_format, e);
+                // The lossless recording is kept as it is
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+// Every decode and re-encode of Opus loses a little more, and recording into a session
+// unpacks it, so Opus waits until the session is no longer a draft and FLAC stands in
+fn session_storage_format(status: &SessionStatus) -> settings::StorageFormat {
+    match (status, AppSettings::load().unwrap_or_default().storage_format) {
+        (SessionStatus::Draft, settings::StorageFormat::Opus) => settings::StorageFormat::Flac,
+        (_, storage_format) => storage_format,
+    }
+}
+
+// Sessions created with a course typed by name join the course of that name,
+// whatever its case, so only a genuinely new name makes a new course
+fn find_or_create_course(db: &Database, name: &str) -> Result<Option<Course>, String> {
+    if name.trim().is_empty() {
+        return Ok(None);
+    }
+    if let Some(course) = db.find_course_by_name(name).map_err(|e| e.to_string())? {
+        return Ok(Some(course));
+    }
+    
+    let input = CourseInput { name: name.to_string(), ..CourseInput::default() }.normalized()?;
+    let course = Course {
+        id: nanoid!(),
+        name: input.name,
+        code: None,
+        term: None,
+        color: None,
+        instructor: None,
+        created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+    };
+    db.insert_course(&course).map_err(|e| e.to_string())?;
+    println!("📚 Created course: {}", course.name);
+    Ok(Some(course))
+}
+
+// Move a session to another course, or out of any
+#[tauri::command]
+async fn cmd_set_session_course(
+    id: String,
+    course_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let db = &state.db;
+    
+    let course = match course_id {
+        Some(course_id) => Some(db.get_course(&course_id)
+            .map_err(|e| e.to_string())?
+            .ok_or("Course not found")?),
+        None => None,
+    };
+    db.set_session_course(&id, course.as_ref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_list_courses(state: State<'_, AppState>) -> Result<Vec<Course>, String> {
+    state.db.list_courses().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_create_course(
+    course: CourseInput,
+    state: State<'_, AppState>,
+) -> Result<Course, String> {
+    let db = &state.db;
+    let input = course.normalized()?;
+    
+    if let Some(existing) = db.find_course_by_name(&input.name).map_err(|e| e.to_string())? {
+        return Err(format!("A course named {} already exists", existing.name));
+    }
+    
+    let course = Course {
+        id: nanoid!(),
+        name: input.name,
+        code: input.code,
+        term: input.term,
+        color: input.color,
+        instructor: input.instructor,
+        created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+    };
+    db.insert_course(&course).map_err(|e| e.to_string())?;
+    println!("📚 Created course: {}", course.name);
+    Ok(course)
+}
+
+#[tauri::command]
+async fn cmd_update_course(
+    id: String,
+    course: CourseInput,
+    state: State<'_, AppState>,
+) -> Result<Course, String> {
+    let db = &state.db;
+    let input = course.normalized()?;
+    
+    let existing = db.get_course(&id)
+        .map_err(|e| e.to_string())?
+        .ok_or("Course not found")?;
+    if let Some(other) = db.find_course_by_name(&input.name).map_err(|e| e.to_string())? {
+        if other.id != id {
+            return Err(format!("A course named {} already exists", other.name));
+        }
+    }
+    
+    let course = Course {
+        name: input.name,
+        code: input.code,
+        term: input.term,
+        color: input.color,
+        instructor: input.instructor,
+        ..existing
+    };
+    db.update_course(&course).map_err(|e| e.to_string())?;
+    Ok(course)
+}
+
+// Sessions of a deleted course are kept, filed under no course
+#[tauri::command]
+async fn cmd_delete_course(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    println!("🗑️ cmd_delete_course called for course: {}", id);
+    
+    if !state.db.delete_course(&id).map_err(|e| e.to_string())? {
+        return Err("Course not found".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn cmd_list_course_stats(state: State<'_, AppState>) -> Result<Vec<CourseStats>, String> {
+    state.db.course_stats().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_list_tags(state: State<'_, AppState>) -> Result<Vec<Tag>, String> {
+    state.db.list_tags().map_err(|e| e.to_string())
+}
+
+// Tags a session by name, creating the tag the first time it is used
+#[tauri::command]
+async fn cmd_add_session_tag(
+    id: String,
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<Tag, String> {
+    let db = &state.db;
+    let name = Tag::normalize_name(&name)?;
+    
+    if db.get_session(&id).map_err(|e| e.to_string())?.is_none() {
+        return Err("Session not found".to_string());
+    }
+    
+    let tag = match db.find_tag_by_name(&name).map_err(|e| e.to_string())? {
+        Some(tag) => tag,
+        None => {
+            let tag = Tag {
+                id: nanoid!(),
+                name,
+                created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+            };
+            db.insert_tag(&tag).map_err(|e| e.to_string())?;
+            println!("🏷️ Created tag: {}", tag.name);
+            tag
+        }
+    };
+    
+    db.add_session_tag(&id, &tag.id).map_err(|e| e.to_string())?;
+    Ok(tag)
+}
+
+#[tauri::command]
+async fn cmd_remove_session_tag(
+    id: String,
+    tag_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let removed = state.db.remove_session_tag(&id, &tag_id).map_err(|e| e.to_string())?;
+    if !removed {
+        return Err("Tag not on session".to_string());
+    }
+    Ok(())
+}
+
+// Renaming a tag onto one that already exists merges the two; the tag its
+// sessions end up with is returned
+#[tauri::command]
+async fn cmd_rename_tag(
+    id: String,
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<Tag, String> {
+    let name = Tag::normalize_name(&name)?;
+    
+    state.db.rename_tag(&id, &name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Tag not found".to_string())
+}
+
+#[tauri::command]
+async fn cmd_merge_tags(
+    from_id: String,
+    into_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let db = &state.db;
+    println!("🏷️ cmd_merge_tags called: {} -> {}", from_id, into_id);
+    
+    for id in [&from_id, &into_id] {
+        if db.get_tag(id).map_err(|e| e.to_string())?.is_none() {
+            return Err("Tag not found".to_string());
+        }
+    }
+    db.merge_tags(&from_id, &into_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_delete_tag(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    println!("🗑️ cmd_delete_tag called for tag: {}", id);
+    
+    if !state.db.delete_tag(&id).map_err(|e| e.to_string())? {
+        return Err("Tag not found".to_string());
+    }
+    Ok(())
+}
+
+// Clears the notice shown for a recording that was recovered after a crash
+#[tauri::command]
+async fn cmd_dismiss_recovery(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let db = &state.db;
+    db.set_session_recovered(&id, false).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -104,10 +412,7 @@ async fn cmd_delete_session(
 ) -> Result<(), String> {
     println!("🗑️ cmd_delete_session called for session: {}", id);
     
-    let db = state.db.lock().map_err(|e| {
-        println!("❌ Failed to lock database: {}", e);
-        e.to_string()
-    })?;
+    let db = &state.db;
     
     // First, delete from database
     let deleted = db.delete_session(&id).map_err(|e| {
@@ -121,6 +426,7 @@ async fn cmd_delete_session(
     }
     
     // Then, delete session folder and all its files
+    playback::stop_session(&id);
     delete_session_folder(&id).map_err(|e| {
         println!("❌ Failed to delete session folder: {}", e);
         // Don't fail the entire operation if folder deletion fails
@@ -133,19 +439,20 @@ async fn cmd_delete_session(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn cmd_append_transcript_line(
     id: String,
     t_ms: u64,
     speaker: String,
     text: String,
+    end_ms: Option<u64>,
+    confidence: Option<f32>,
+    words: Option<Vec<TranscriptWord>>,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
     println!("🔧 cmd_append_transcript_line called with id: {}, t_ms: {}, speaker: {}, text: {}", id, t_ms, speaker, text);
     
-    let db = state.db.lock().map_err(|e| {
-        println!("❌ Failed to lock database: {}", e);
-        e.to_string()
-    })?;
+    let db = &state.db;
     
     // Get session to ensure it exists and get transcript path
     let session = db.get_session(&id).map_err(|e| {
@@ -170,52 +477,30 @@ async fn cmd_append_transcript_line(
     
     println!("📁 Full file path: {:?}", full_path);
     
-    // Ensure session directory exists
-    if let Some(parent) = full_path.parent() {
-        println!("📁 Creating directory: {:?}", parent);
-        std::fs::create_dir_all(parent)
-            .map_err(|e| {
-                println!("❌ Failed to create session directory: {}", e);
-                format!("Failed to create session directory: {}", e)
-            })?;
-    }
-    
     // Create transcript line
     let transcript_line = TranscriptLine {
         t_ms,
         speaker,
         text,
+        end_ms,
+        confidence,
+        words: words.unwrap_or_default(),
     };
     
     println!("📝 Created transcript line: {:?}", transcript_line);
     
-    // Serialize to JSON and append to file
-    let json_line = serde_json::to_string(&transcript_line)
-        .map_err(|e| {
-            println!("❌ Failed to serialize transcript line: {}", e);
-            format!("Failed to serialize transcript line: {}", e)
-        })?;
-    
-    println!("📝 Serialized JSON: {}", json_line);
-    
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&full_path)
-        .map_err(|e| {
-            println!("❌ Failed to open transcript file {:?}: {}", full_path, e);
-            format!("Failed to open transcript file: {}", e)
-        })?;
-    
-    println!("📝 Opened file for writing");
-    
-    writeln!(file, "{}", json_line)
+    transcript::append_line(&full_path, &transcript_line)
         .map_err(|e| {
             println!("❌ Failed to write to transcript file: {}", e);
             format!("Failed to write to transcript file: {}", e)
         })?;
     
     println!("✅ Successfully wrote transcript line to file");
+    
+    // The line is saved either way; a missing index entry only affects search
+    if let Err(e) = db.index_transcript_line(&id, &transcript_line) {
+        eprintln!("❌ Failed to index transcript line: {}", e);
+    }
     Ok(())
 }
 
@@ -226,10 +511,7 @@ async fn cmd_read_transcript(
 ) -> Result<Vec<TranscriptLine>, String> {
     println!("🔧 cmd_read_transcript called with id: {}", id);
     
-    let db = state.db.lock().map_err(|e| {
-        println!("❌ Failed to lock database: {}", e);
-        e.to_string()
-    })?;
+    let db = &state.db;
     
     // Get session to ensure it exists and get transcript path
     let session = db.get_session(&id).map_err(|e| e.to_string())?
@@ -240,27 +522,7 @@ async fn cmd_read_transcript(
     
     let full_path = get_session_file_path(&id, &transcript_path)?;
     
-    // Check if file exists
-    if !full_path.exists() {
-        return Ok(Vec::new());
-    }
-    
-    let file = File::open(full_path)
-        .map_err(|e| format!("Failed to open transcript file: {}", e))?;
-    
-    let reader = BufReader::new(file);
-    let mut transcript_lines = Vec::new();
-    
-    for line in reader.lines() {
-        let line = line.map_err(|e| format!("Failed to read line: {}", e))?;
-        if !line.trim().is_empty() {
-            let transcript_line: TranscriptLine = serde_json::from_str(&line)
-                .map_err(|e| format!("Failed to parse transcript line: {}", e))?;
-            transcript_lines.push(transcript_line);
-        }
-    }
-    
-    Ok(transcript_lines)
+    transcript::read_lines(&full_path).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -269,7 +531,7 @@ async fn cmd_write_notes(
     markdown: String,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let db = &state.db;
     
     // Get session to ensure it exists and get notes path
     let session = db.get_session(&id).map_err(|e| e.to_string())?
@@ -287,9 +549,13 @@ async fn cmd_write_notes(
     }
     
     // Write markdown content to file
-    std::fs::write(full_path, markdown)
+    std::fs::write(full_path, &markdown)
         .map_err(|e| format!("Failed to write notes file: {}", e))?;
     
+    if let Err(e) = db.index_notes(&id, &markdown) {
+        eprintln!("❌ Failed to index notes: {}", e);
+    }
+    
     Ok(())
 }
 
@@ -298,7 +564,7 @@ async fn cmd_read_notes(
     id: String,
     state: State<'_, AppState>,
 ) -> Result<String, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let db = &state.db;
     
     // Get session to ensure it exists and get notes path
     let session = db.get_session(&id).map_err(|e| e.to_string())?
@@ -319,20 +585,37 @@ async fn cmd_read_notes(
         .map_err(|e| format!("Failed to read notes file: {}", e))
 }
 
+// Find sessions by what was said in them or written in their notes
+#[tauri::command]
+async fn cmd_search(
+    query: String,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchHit>, String> {
+    println!("🔍 cmd_search called with query: {}", query);
+    
+    state.db.search(&query, limit.unwrap_or(50)).map_err(|e| format!("Search failed: {}", e))
+}
+
 #[tauri::command]
 async fn cmd_start_recording(
     id: String,
+    device: Option<String>,
     app_handle: tauri::AppHandle,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
     println!("🎙️ cmd_start_recording called for session: {}", id);
     
-    // Scope the database lock to avoid Send issues
-    let session_dir = {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
+    // Batch transcription rewrites the transcript the live recognizer would append to
+    if speech::is_transcribing(&id) {
+        return Err("Cannot record while the session is being transcribed. Please wait for transcription to finish or cancel it first.".to_string());
+    }
+    
+    let (session_dir, transcript_path) = {
+        let db = &state.db;
         
-        // Get session to ensure it exists
-        let _session = db.get_session(&id).map_err(|e| e.to_string())?
+        // Get session to ensure it exists and get transcript path
+        let session = db.get_session(&id).map_err(|e| e.to_string())?
             .ok_or("Session not found")?;
         
         // Get session directory and ensure it exists
@@ -342,16 +625,34 @@ async fn cmd_start_recording(
         std::fs::create_dir_all(&session_dir)
             .map_err(|e| format!("Failed to create session directory: {}", e))?;
         
-        session_dir
+        let transcript_path = session.transcript_path
+            .unwrap_or_else(|| "transcript.jsonl".to_string()); // Default if missing
+        
+        (session_dir.clone(), session_dir.join(transcript_path))
     };
     
+    let vad_config = VadConfig::load(&session_dir).map_err(|e| e.to_string())?;
+    
+    let settings = AppSettings::load().map_err(|e| e.to_string())?;
+    
+    // A device picked for this recording wins over the saved default
+    let device = device.or(settings.input_device);
+    
+    // The recording is about to change under the player
+    playback::stop_session(&id);
+    
+    // New audio is appended to the WAV, so a compressed recording has to be unpacked first
+    let restore_dir = session_dir.clone();
+    run_blocking(move || codec::restore_wav(&restore_dir))
+        .await?
+        .map_err(|e| format!("Failed to prepare existing recording: {}", e))?;
+    
     // Start recording
-    start_recording_simple(id.clone(), session_dir.clone(), app_handle.clone())
+    start_recording_simple(id.clone(), session_dir, device, settings.recording_format, app_handle.clone())
         .map_err(|e| format!("Failed to start recording: {}", e))?;
     
-    // Start speech processing
-    let audio_path = session_dir.join("audio.wav");
-    if let Err(e) = start_speech_processing(id.clone(), audio_path, app_handle).await {
+    // Start speech processing on the captured samples; final lines are saved by the backend
+    if let Err(e) = start_speech_processing(id.clone(), transcript_path, vad_config, state.db.clone(), app_handle) {
         eprintln!("❌ Failed to start speech processing: {}", e);
         // Continue anyway - audio recording will still work
     }
@@ -360,6 +661,25 @@ async fn cmd_start_recording(
     Ok(())
 }
 
+#[tauri::command]
+async fn cmd_list_input_devices() -> Result<Vec<crate::audio::InputDeviceInfo>, String> {
+    println!("🎙️ cmd_list_input_devices called");
+    
+    crate::audio::list_input_devices().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_get_settings() -> Result<AppSettings, String> {
+    AppSettings::load().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_save_settings(settings: AppSettings) -> Result<(), String> {
+    println!("🔧 cmd_save_settings called: {:?}", settings);
+    
+    settings.save().map_err(|e| format!("Failed to save settings: {}", e))
+}
+
 #[tauri::command]
 async fn cmd_pause_recording(
     id: String,
@@ -367,9 +687,8 @@ async fn cmd_pause_recording(
 ) -> Result<(), String> {
     println!("⏸️ cmd_pause_recording called for session: {}", id);
     
-    // Scope the database lock to avoid Send issues
     {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let db = &state.db;
         
         // Get session to ensure it exists
         let _session = db.get_session(&id).map_err(|e| e.to_string())?
@@ -377,9 +696,15 @@ async fn cmd_pause_recording(
     };
     
     // Pause recording
-    pause_recording_simple(&id)
+    let duration_ms = pause_recording_simple(&id)
         .map_err(|e| format!("Failed to pause recording: {}", e))?;
     
+    // Keep the library's duration current in case the recording is never stopped
+    {
+        let db = &state.db;
+        db.update_session_duration(&id, duration_ms as i64).map_err(|e| e.to_string())?;
+    }
+    
     println!("⏸️ Recording paused successfully for session: {}", id);
     Ok(())
 }
@@ -391,9 +716,8 @@ async fn cmd_resume_recording(
 ) -> Result<(), String> {
     println!("▶️ cmd_resume_recording called for session: {}", id);
     
-    // Scope the database lock to avoid Send issues
     let session_dir = {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let db = &state.db;
         
         // Get session to ensure it exists
         let _session = db.get_session(&id).map_err(|e| e.to_string())?
@@ -422,7 +746,7 @@ async fn cmd_get_recording_state(
     id: String,
     state: State<'_, AppState>,
 ) -> Result<serde_json::Value, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let db = &state.db;
     
     // Get session to ensure it exists
     let _session = db.get_session(&id).map_err(|e| e.to_string())?
@@ -430,10 +754,12 @@ async fn cmd_get_recording_state(
     
     let is_recording = crate::audio::is_recording(&id);
     let is_paused = crate::audio::is_paused(&id);
+    let duration_ms = crate::audio::recorded_duration_ms(&id);
     
     Ok(serde_json::json!({
         "is_recording": is_recording,
-        "is_paused": is_paused
+        "is_paused": is_paused,
+        "duration_ms": duration_ms
     }))
 }
 
@@ -444,12 +770,11 @@ async fn cmd_stop_recording(
 ) -> Result<(), String> {
     println!("🎙️ cmd_stop_recording called for session: {}", id);
     
-    // Scope the database lock to avoid Send issues
-    {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
+    let (session_dir, transcript_path, status) = {
+        let db = &state.db;
         
         // Get session to ensure it exists
-        let _session = db.get_session(&id).map_err(|e| e.to_string())?
+        let session = db.get_session(&id).map_err(|e| e.to_string())?
             .ok_or("Session not found")?;
         
         // Get session directory and ensure it exists
@@ -458,10 +783,15 @@ async fn cmd_stop_recording(
         // Create the session directory if it doesn't exist (for existing sessions)
         std::fs::create_dir_all(&session_dir)
             .map_err(|e| format!("Failed to create session directory: {}", e))?;
+        
+        let transcript_path = session.transcript_path
+            .unwrap_or_else(|| "transcript.jsonl".to_string()); // Default if missing
+        
+        (session_dir.clone(), session_dir.join(transcript_path), session.status)
     };
     
     // Stop recording
-    stop_recording_simple(&id)
+    let recorded_ms = stop_recording_simple(&id)
         .map_err(|e| format!("Failed to stop recording: {}", e))?;
     
     // Stop speech processing
@@ -470,189 +800,407 @@ async fn cmd_stop_recording(
         // Continue anyway - audio recording was stopped
     }
     
+    // Shorten long silences now that the audio and transcript are complete
+    let vad_config = VadConfig::load(&session_dir).unwrap_or_default();
+    if vad_config.trim_silence {
+        let (trim_id, audio_path, db) = (id.clone(), session_dir.join("audio.wav"), state.db.clone());
+        let trimmed = run_blocking(move || {
+            trim_session_silences(&trim_id, &audio_path, &transcript_path, &vad_config, &db)
+        }).await?;
+        if let Err(e) = trimmed {
+            eprintln!("❌ Failed to trim silences: {}", e);
+            // The untrimmed recording is still intact
+        }
+    }
+    
+    // Compress the finished recording if the user asked for it
+    let storage_format = session_storage_format(&status);
+    let wav_path = session_dir.join("audio.wav");
+    if wav_path.exists() {
+        let stored = run_blocking(move || codec::store_recording(&wav_path, storage_format)).await?;
+        match stored {
+            Ok(audio_path) => {
+                let file_name = audio_path.file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("audio.wav");
+                let db = &state.db;
+                db.update_session_audio_path(&id, file_name).map_err(|e| e.to_string())?;
+            }
+            Err(e) => {
+                eprintln!("❌ Failed to store recording as {:?}: {}", storage_format, e);
+                // The WAV is kept as it is
+            }
+        }
+    }
+    
+    // Trimming may have shortened the recording, so its header has the final say
+    let duration_ms = codec::find_session_audio(&session_dir)
+        .and_then(|path| codec::audio_info(&path).ok())
+        .map(|info| info.duration_ms())
+        .unwrap_or(recorded_ms);
+    {
+        let db = &state.db;
+        db.update_session_duration(&id, duration_ms as i64).map_err(|e| e.to_string())?;
+    }
+    
     println!("🎙️ Recording stopped successfully for session: {}", id);
     Ok(())
 }
 
+// Cut long silences out of a recording and move its transcript along with the audio
+fn trim_session_silences(
+    session_id: &str,
+    audio_path: &std::path::Path,
+    transcript_path: &std::path::Path,
+    vad_config: &VadConfig,
+    db: &Database,
+) -> Result<()> {
+    let cuts = vad::trim_long_silences(audio_path, vad_config)?;
+    if cuts.is_empty() {
+        return Ok(());
+    }
+    
+    let removed_ms: u64 = cuts.iter().map(|cut| cut.removed_ms).sum();
+    println!("✂️ Trimmed {} silence(s), {}ms in total", cuts.len(), removed_ms);
+    
+    let mut lines = transcript::read_lines(transcript_path)?;
+    vad::shift_transcript(&mut lines, &cuts);
+    transcript::write_lines(transcript_path, &lines)?;
+    // Search hits point at the new timestamps
+    db.index_transcript(session_id, &lines)
+}
+
 #[tauri::command]
-async fn cmd_stop_audio(
-    state: State<'_, AppState>,
-) -> Result<(), String> {
-    println!("🔊 cmd_stop_audio called");
+async fn cmd_get_vad_config(id: String) -> Result<VadConfig, String> {
+    let session_dir = get_session_dir(&id)?;
+    VadConfig::load(&session_dir).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_set_vad_config(id: String, config: VadConfig) -> Result<(), String> {
+    println!("🔧 cmd_set_vad_config called for session: {}", id);
     
-    let mut audio_process = state.audio_process.lock().map_err(|e| e.to_string())?;
-    if let Some(mut child) = audio_process.take() {
-        match child.kill() {
-            Ok(_) => {
-                let _ = child.wait();
-                println!("🔊 Audio playback stopped");
-                Ok(())
-            }
-            Err(e) => Err(format!("Failed to stop audio: {}", e))
-        }
-    } else {
-        Err("No audio is currently playing".to_string())
-    }
+    let session_dir = get_session_dir(&id)?;
+    config.save(&session_dir).map_err(|e| format!("Failed to save VAD settings: {}", e))
 }
 
 #[tauri::command]
-async fn cmd_get_audio_duration(
-    id: String,
-    state: State<'_, AppState>,
-) -> Result<f64, String> {
-    println!("🔊 cmd_get_audio_duration called for session: {}", id);
+async fn cmd_get_silence_spans(id: String) -> Result<Vec<SilenceSpan>, String> {
+    println!("🔧 cmd_get_silence_spans called for session: {}", id);
     
-    // Scope the database lock to avoid Send issues
-    {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
-        
-        // Get session to ensure it exists
-        let _session = db.get_session(&id).map_err(|e| e.to_string())?
-            .ok_or("Session not found")?;
-    };
+    let session_dir = get_session_dir(&id)?;
+    let audio_path = get_session_audio_path(&id)?;
     
-    // Get the audio file path
-    let audio_path = get_session_file_path(&id, "audio.wav")?;
+    let vad_config = VadConfig::load(&session_dir).map_err(|e| e.to_string())?;
     
-    // Check if audio file exists
-    if !audio_path.exists() {
-        return Err("No audio recording found for this session".to_string());
-    }
+    run_blocking(move || vad::find_silences(&audio_path, &vad_config))
+        .await?
+        .map_err(|e| format!("Failed to analyse audio: {}", e))
+}
+
+// Min/max peaks for drawing a session's waveform, `buckets` wide at most
+#[tauri::command]
+async fn cmd_get_waveform(id: String, buckets: usize) -> Result<waveform::WaveformPeaks, String> {
+    println!("📈 cmd_get_waveform called for session: {} ({} buckets)", id, buckets);
     
-    // Get audio duration using afinfo (macOS built-in tool)
-    #[cfg(target_os = "macos")]
-    {
-        use std::process::Command;
-        
-        let output = Command::new("afinfo")
-            .arg(&audio_path)
-            .output();
-            
-        match output {
-            Ok(result) => {
-                let output_str = String::from_utf8_lossy(&result.stdout);
-                
-                // Parse duration from afinfo output
-                // Look for "estimated duration: X.X seconds"
-                for line in output_str.lines() {
-                    if line.contains("estimated duration:") {
-                        if let Some(duration_part) = line.split("estimated duration:").nth(1) {
-                            if let Some(duration_str) = duration_part.trim().split_whitespace().next() {
-                                if let Ok(duration) = duration_str.parse::<f64>() {
-                                    println!("🔊 Audio duration: {} seconds", duration);
-                                    return Ok(duration);
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                // Fallback: estimate from file size (rough approximation)
-                let file_size = std::fs::metadata(&audio_path)
-                    .map_err(|e| format!("Failed to get file metadata: {}", e))?
-                    .len();
-                
-                // Rough estimate: 44100 Hz * 2 bytes * 1 channel = 88200 bytes per second
-                let estimated_duration = file_size as f64 / 88200.0;
-                println!("🔊 Estimated audio duration from file size: {} seconds", estimated_duration);
-                Ok(estimated_duration)
-            }
-            Err(e) => {
-                Err(format!("Failed to get audio duration: {}", e))
-            }
-        }
-    }
+    let session_dir = get_session_dir(&id)?;
+    let audio_path = get_session_audio_path(&id)?;
     
-    #[cfg(not(target_os = "macos"))]
-    {
-        // Fallback for other platforms - estimate from file size
-        let file_size = std::fs::metadata(&audio_path)
-            .map_err(|e| format!("Failed to get file metadata: {}", e))?
-            .len();
-        
-        // Rough estimate: 44100 Hz * 2 bytes * 1 channel = 88200 bytes per second
-        let estimated_duration = file_size as f64 / 88200.0;
-        println!("🔊 Estimated audio duration from file size: {} seconds", estimated_duration);
-        Ok(estimated_duration)
-    }
+    run_blocking(move || waveform::load(&session_dir, &audio_path))
+        .await?
+        .map(|waveform| waveform.downsample(buckets))
+        .map_err(|e| format!("Failed to compute waveform: {}", e))
 }
 
 #[tauri::command]
-async fn cmd_play_audio(
+async fn cmd_transcribe_session(
     id: String,
+    app_handle: tauri::AppHandle,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
-    println!("🔊 cmd_play_audio called for session: {}", id);
+    println!("📝 cmd_transcribe_session called for session: {}", id);
     
-    // Check if currently recording
     if crate::audio::is_recording(&id) {
-        return Err("Cannot play audio while recording is in progress. Please stop or pause the recording first.".to_string());
+        return Err("Cannot transcribe while recording is in progress. Please stop the recording first.".to_string());
     }
     
-    // Scope the database lock to avoid Send issues
-    {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
+    let transcript_path = {
+        let db = &state.db;
         
-        // Get session to ensure it exists
-        let _session = db.get_session(&id).map_err(|e| e.to_string())?
+        // Get session to ensure it exists and get transcript path
+        let session = db.get_session(&id).map_err(|e| e.to_string())?
             .ok_or("Session not found")?;
+        
+        session.transcript_path
+            .unwrap_or_else(|| "transcript.jsonl".to_string()) // Default if missing
     };
     
-    // Get the audio file path
-    let audio_path = get_session_file_path(&id, "audio.wav")?;
+    let audio_path = get_session_audio_path(&id)?;
     
-    // Check if audio file exists
-    if !audio_path.exists() {
-        return Err("No audio recording found for this session".to_string());
-    }
+    let transcript_path = get_session_file_path(&id, &transcript_path)?;
+    let vad_config = VadConfig::load(&get_session_dir(&id)?).map_err(|e| e.to_string())?;
     
-    // Stop any currently playing audio
-    {
-        let mut audio_process = state.audio_process.lock().map_err(|e| e.to_string())?;
-        if let Some(mut child) = audio_process.take() {
-            let _ = child.kill();
-            let _ = child.wait();
-        }
-    }
+    // Runs in the background, progress arrives as events
+    start_session_transcription(id, audio_path, transcript_path, vad_config, state.db.clone(), app_handle)
+        .map_err(|e| format!("Failed to start transcription: {}", e))
+}
 
-    // Play the audio file using system command
-    #[cfg(target_os = "macos")]
-    {
-        let child = Command::new("afplay")
-            .arg(&audio_path)
-            .spawn()
-            .map_err(|e| format!("Failed to start audio playback: {}", e))?;
-            
-        // Store the process so we can stop it later
-        {
-            let mut audio_process = state.audio_process.lock().map_err(|e| e.to_string())?;
-            *audio_process = Some(child);
-        }
-        
-        println!("🔊 Started playing audio file: {:?}", audio_path);
-        Ok(())
+#[tauri::command]
+async fn cmd_cancel_transcription(id: String) -> Result<(), String> {
+    println!("📝 cmd_cancel_transcription called for session: {}", id);
+    
+    cancel_session_transcription(&id).map_err(|e| e.to_string())
+}
+
+// Resolve a session's transcript file for commands that rewrite it
+fn transcript_path_for_rewrite(id: &str, state: &State<'_, AppState>) -> Result<PathBuf, String> {
+    // The live recognizer and batch transcription both write the file themselves
+    if crate::audio::is_recording(id) {
+        return Err("Cannot change the transcript while recording is in progress. Please stop the recording first.".to_string());
+    }
+    if crate::speech::is_transcribing(id) {
+        return Err("Cannot change the transcript while it is being transcribed".to_string());
+    }
+
+    let transcript_path = {
+        let db = &state.db;
+
+        let session = db.get_session(id).map_err(|e| e.to_string())?
+            .ok_or("Session not found")?;
+
+        session.transcript_path
+            .unwrap_or_else(|| "transcript.jsonl".to_string()) // Default if missing
+    };
+
+    get_session_file_path(id, &transcript_path)
+}
+
+#[tauri::command]
+async fn cmd_diarize_session(
+    id: String,
+    num_speakers: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    println!("🗣️ cmd_diarize_session called for session: {} (speakers: {:?})", id, num_speakers);
+
+    let transcript_path = transcript_path_for_rewrite(&id, &state)?;
+    let audio_path = get_session_audio_path(&id)?;
+
+    run_blocking(move || -> Result<usize, String> {
+        let mut lines = transcript::read_lines(&transcript_path).map_err(|e| e.to_string())?;
+        let speakers = diarization::diarize_lines(&audio_path, &mut lines, num_speakers)
+            .map_err(|e| format!("Failed to identify speakers: {}", e))?;
+        transcript::write_lines(&transcript_path, &lines)
+            .map_err(|e| format!("Failed to write transcript: {}", e))?;
+
+        println!("🗣️ Found {} speaker(s) in session: {}", speakers, id);
+        Ok(speakers)
+    })
+    .await?
+}
+
+#[tauri::command]
+async fn cmd_rename_speaker(
+    id: String,
+    from: String,
+    to: String,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    println!("🗣️ cmd_rename_speaker called for session: {} ({} -> {})", id, from, to);
+
+    let to = to.trim();
+    if to.is_empty() {
+        return Err("Speaker name cannot be empty".to_string());
     }
+
+    let transcript_path = transcript_path_for_rewrite(&id, &state)?;
+    transcript::rename_speaker(&transcript_path, &from, to)
+        .map_err(|e| format!("Failed to rename speaker: {}", e))
+}
+
+#[tauri::command]
+async fn cmd_stop_audio() -> Result<(), String> {
+    println!("🔊 cmd_stop_audio called");
+    
+    playback::stop().map_err(|e| e.to_string())?;
+    println!("🔊 Audio playback stopped");
+    Ok(())
+}
+
+#[tauri::command]
+async fn cmd_pause_audio() -> Result<playback::PlaybackState, String> {
+    playback::pause().map_err(|e| format!("Failed to pause audio: {}", e))
+}
+
+#[tauri::command]
+async fn cmd_resume_audio() -> Result<playback::PlaybackState, String> {
+    playback::resume().map_err(|e| format!("Failed to resume audio: {}", e))
+}
+
+#[tauri::command]
+async fn cmd_seek_audio(position_ms: u64) -> Result<playback::PlaybackState, String> {
+    playback::seek(position_ms).map_err(|e| format!("Failed to seek: {}", e))
+}
+
+// Speed from 0.5x to 3x; the pitch stays the same
+#[tauri::command]
+async fn cmd_set_playback_rate(rate: f32) -> Result<playback::PlaybackState, String> {
+    playback::set_rate(rate).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn cmd_get_playback_state() -> Result<Option<playback::PlaybackState>, String> {
+    Ok(playback::state())
+}
+
+#[tauri::command]
+async fn cmd_get_audio_duration(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<f64, String> {
+    println!("🔊 cmd_get_audio_duration called for session: {}", id);
     
-    #[cfg(not(target_os = "macos"))]
     {
-        // For other platforms, we could use different commands
-        // For now, just open the file with the default application
-        use std::process::Command;
+        let db = &state.db;
         
-        let output = Command::new("open")
-            .arg(&audio_path)
-            .spawn();
-            
-        match output {
-            Ok(_) => {
-                println!("🔊 Opened audio file: {:?}", audio_path);
-                Ok(())
-            }
+        // Get session to ensure it exists
+        let _session = db.get_session(&id).map_err(|e| e.to_string())?
+            .ok_or("Session not found")?;
+    };
+    
+    // Get the audio file path, whichever format it is stored in
+    let audio_path = get_session_audio_path(&id)?;
+    
+    // Read the exact length from the file's headers
+    let info = run_blocking(move || codec::audio_info(&audio_path))
+        .await?
+        .map_err(|e| format!("Failed to get audio duration: {}", e))?;
+    
+    let duration = info.frames as f64 / info.sample_rate.max(1) as f64;
+    println!("🔊 Audio duration: {} seconds", duration);
+    Ok(duration)
+}
+
+#[tauri::command]
+async fn cmd_play_audio(
+    id: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<playback::PlaybackState, String> {
+    println!("🔊 cmd_play_audio called for session: {}", id);
+    
+    start_playback(&id, 0, app_handle, &state)
+}
+
+// Start playback at a transcript line's timestamp
+#[tauri::command]
+async fn cmd_play_from(
+    id: String,
+    t_ms: u64,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<playback::PlaybackState, String> {
+    println!("🔊 cmd_play_from called for session: {} at {}ms", id, t_ms);
+    
+    start_playback(&id, t_ms, app_handle, &state)
+}
+
+fn start_playback(
+    id: &str,
+    start_ms: u64,
+    app_handle: tauri::AppHandle,
+    state: &State<'_, AppState>,
+) -> Result<playback::PlaybackState, String> {
+    // Check if currently recording
+    if crate::audio::is_recording(id) {
+        return Err("Cannot play audio while recording is in progress. Please stop or pause the recording first.".to_string());
+    }
+    
+    let transcript_path = {
+        let db = &state.db;
+        
+        // Get session to ensure it exists and get transcript path
+        let session = db.get_session(id).map_err(|e| e.to_string())?
+            .ok_or("Session not found")?;
+        
+        session.transcript_path
+            .unwrap_or_else(|| "transcript.jsonl".to_string()) // Default if missing
+    };
+    
+    // Get the audio file path, whichever format it is stored in
+    let audio_path = get_session_audio_path(id)?;
+    
+    // Playback still works without a readable transcript, it just can't follow along
+    let transcript = get_session_file_path(id, &transcript_path)
+        .and_then(|path| transcript::read_lines(&path).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("📄 Failed to read transcript of session {}: {}", id, e);
+            Vec::new()
+        });
+    
+    // Replaces anything that is already playing; position updates arrive as events
+    let playback_state = playback::play(id, &audio_path, transcript, start_ms, app_handle)
+        .map_err(|e| format!("Failed to start audio playback: {}", e))?;
+    println!("🔊 Started playing audio file: {:?} from {}ms", audio_path, start_ms);
+    Ok(playback_state)
+}
+
+// Stitch together recordings the app didn't get to stop last time it ran.
+// Recovered audio stays a WAV until the session is recorded into and stopped again.
+fn recover_interrupted_recordings(db: &Database) -> Result<()> {
+    for (session_id, session_dir) in list_session_folders()? {
+        match audio::recover_segments(&session_dir) {
+            Ok(false) => continue,
+            Ok(true) => {}
             Err(e) => {
-                Err(format!("Failed to open audio: {}", e))
+                eprintln!("❌ Failed to recover recording of session {}: {}", session_id, e);
+                continue;
             }
         }
+
+        // One session that can't be updated shouldn't hold up the others
+        if let Err(e) = mark_recording_recovered(db, &session_id, &session_dir) {
+            eprintln!("❌ Failed to recover recording of session {}: {}", session_id, e);
+            continue;
+        }
+        println!("🩹 Recovered interrupted recording of session {}", session_id);
+    }
+    Ok(())
+}
+
+// Point a session at the WAV its segments were stitched back into
+fn mark_recording_recovered(db: &Database, session_id: &str, session_dir: &std::path::Path) -> Result<()> {
+    // Any compressed copy is older than the stitched WAV
+    let wav_path = session_dir.join(settings::StorageFormat::Wav.file_name());
+    codec::store_recording(&wav_path, settings::StorageFormat::Wav)?;
+    if db.get_session(session_id)?.is_some() {
+        let duration_ms = codec::audio_info(&wav_path)?.duration_ms();
+        db.update_session_duration(session_id, duration_ms as i64)?;
+        db.update_session_audio_path(session_id, settings::StorageFormat::Wav.file_name())?;
+        db.set_session_recovered(session_id, true)?;
+    }
+    Ok(())
+}
+
+// Fill the search index for sessions it has nothing for yet, such as those
+// from before there was one
+fn index_unindexed_sessions(db: &Database) -> Result<()> {
+    for session in db.list_unindexed_sessions()? {
+        let session_dir = get_session_dir(&session.id).map_err(anyhow::Error::msg)?;
+        
+        let transcript_path = session_dir.join(session.transcript_path.as_deref().unwrap_or("transcript.jsonl"));
+        match transcript::read_lines(&transcript_path) {
+            Ok(lines) if !lines.is_empty() => db.index_transcript(&session.id, &lines)?,
+            Ok(_) => {}
+            Err(e) => eprintln!("❌ Failed to read transcript of session {}: {}", session.id, e),
+        }
+        
+        let notes_path = session_dir.join(session.notes_path.as_deref().unwrap_or("notes.md"));
+        if let Ok(markdown) = std::fs::read_to_string(&notes_path) {
+            db.index_notes(&session.id, &markdown)?;
+        }
     }
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -666,13 +1214,26 @@ pub fn run() {
         }
     };
     
+    // Nothing is recording yet, so leftover segments belong to a recording that was cut short
+    if let Err(e) = recover_interrupted_recordings(&db) {
+        eprintln!("❌ Failed to recover interrupted recordings: {}", e);
+    }
+    
+    // Reading every old transcript can take a while, so search catches up in the background
+    let index_db = db.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = index_unindexed_sessions(&index_db) {
+            eprintln!("❌ Failed to index sessions for search: {}", e);
+        }
+    });
+    
     let app_state = AppState {
-        db: Mutex::new(db),
-        audio_process: Mutex::new(None),
+        db,
     };
 
-    tauri::Builder::default()
+    let app = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_log::Builder::new().build())
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             greet,
@@ -680,22 +1241,60 @@ pub fn run() {
             cmd_list_sessions,
             cmd_create_session,
             cmd_update_session_status,
+            cmd_set_session_course,
+            cmd_list_courses,
+            cmd_create_course,
+            cmd_update_course,
+            cmd_delete_course,
+            cmd_list_course_stats,
+            cmd_list_tags,
+            cmd_add_session_tag,
+            cmd_remove_session_tag,
+            cmd_rename_tag,
+            cmd_merge_tags,
+            cmd_delete_tag,
+            cmd_dismiss_recovery,
             cmd_delete_session,
             cmd_append_transcript_line,
             cmd_read_transcript,
             cmd_write_notes,
             cmd_read_notes,
+            cmd_search,
             cmd_start_recording,
+            cmd_list_input_devices,
+            cmd_get_settings,
+            cmd_save_settings,
             cmd_pause_recording,
             cmd_resume_recording,
             cmd_get_recording_state,
             cmd_stop_recording,
+            cmd_transcribe_session,
+            cmd_cancel_transcription,
+            cmd_diarize_session,
+            cmd_rename_speaker,
+            cmd_get_vad_config,
+            cmd_set_vad_config,
+            cmd_get_silence_spans,
+            cmd_get_waveform,
             cmd_play_audio,
+            cmd_play_from,
+            cmd_pause_audio,
+            cmd_resume_audio,
+            cmd_seek_audio,
+            cmd_set_playback_rate,
+            cmd_get_playback_state,
             cmd_stop_audio,
             cmd_get_audio_duration
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+    
+    app.run(|_app_handle, event| {
+        if let tauri::RunEvent::Exit = event {
+            // Make sure everything recognized so far reaches the transcript files
+            speech::stop_all_speech_processing();
+        }
+    });
 }
 
 #[cfg(test)]
@@ -715,6 +1314,9 @@ mod tests {
             notes_path: None,
             audio_path: None,
             transcript_path: None,
+            recovered: false,
+            course_id: None,
+            tags: Vec::new(),
         };
 
         assert_eq!(session.title, "Test Session");
@@ -736,4 +1338,1312 @@ mod tests {
         assert_eq!(SessionStatus::Draft.as_str(), "draft");
         assert_eq!(SessionStatus::Complete.as_str(), "complete");
     }
+
+    #[test]
+    fn test_mock_speech_engine_follows_fed_audio() {
+        use crate::speech::{MockSpeechEngine, SpeechEngine};
+
+        let mut engine = MockSpeechEngine::new();
+        engine.start(16000).unwrap();
+
+        // Less than a second of audio is not enough to produce any text
+        assert!(engine.feed(&vec![0.0; 8000]).unwrap().is_empty());
+
+        let segments = engine.feed(&vec![0.0; 16000 * 20]).unwrap();
+        assert!(!segments.is_empty());
+        for segment in &segments {
+            assert!(segment.start_ms <= segment.end_ms);
+            assert!(segment.end_ms <= 20_500 + 800);
+        }
+
+        // Partials grow within one utterance and each utterance is finalized once
+        let first = &segments[0];
+        assert!(!first.is_final);
+        let same_utterance: Vec<_> = segments.iter().filter(|s| s.utterance_id == first.utterance_id).collect();
+        assert!(same_utterance.windows(2).all(|w| w[1].text.starts_with(&w[0].text)));
+        assert_eq!(same_utterance.iter().filter(|s| s.is_final).count(), 1);
+
+        // Stopping mid-phrase finalizes what was heard so far
+        let cut_off = engine.feed(&vec![0.0; 16000 * 2]).unwrap();
+        let flushed = engine.flush().unwrap();
+        if let Some(last) = cut_off.last().filter(|s| !s.is_final) {
+            assert_eq!(flushed.len(), 1);
+            assert!(flushed[0].is_final);
+            assert_eq!(flushed[0].utterance_id, last.utterance_id);
+        }
+    }
+
+    #[test]
+    fn test_transcript_line_backward_compatible() {
+        // Lines written before word timings existed still parse
+        let old: TranscriptLine = serde_json::from_str(r#"{"t_ms":1500,"speaker":"Speaker","text":"Hello there"}"#).unwrap();
+        assert_eq!(old.t_ms, 1500);
+        assert_eq!(old.end_ms, None);
+        assert_eq!(old.confidence, None);
+        assert!(old.words.is_empty());
+
+        // ...and lines without the optional data serialize exactly as before
+        assert_eq!(serde_json::to_string(&old).unwrap(), r#"{"t_ms":1500,"speaker":"Speaker","text":"Hello there"}"#);
+
+        let detailed = TranscriptLine {
+            end_ms: Some(2300),
+            confidence: Some(0.9),
+            words: vec![
+                TranscriptWord { text: "Hello".to_string(), t_ms: 1500, end_ms: 1900, confidence: Some(0.95) },
+                TranscriptWord { text: "there".to_string(), t_ms: 1900, end_ms: 2300, confidence: None },
+            ],
+            ..old
+        };
+        let json = serde_json::to_string(&detailed).unwrap();
+        assert_eq!(serde_json::from_str::<TranscriptLine>(&json).unwrap(), detailed);
+    }
+
+    #[test]
+    fn test_transcript_survives_interrupted_write() {
+        let dir = std::env::temp_dir().join(format!("polka-transcript-test-{}", nanoid!()));
+        let path = dir.join("transcript.jsonl");
+        let line = |t_ms: u64, text: &str| TranscriptLine {
+            t_ms,
+            speaker: "Speaker".to_string(),
+            text: text.to_string(),
+            end_ms: None,
+            confidence: None,
+            words: Vec::new(),
+        };
+
+        transcript::append_line(&path, &line(0, "first")).unwrap();
+
+        // Simulate the process dying halfway through writing a line
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, br#"{"t_ms":1000,"speak"#).unwrap();
+        drop(file);
+
+        let lines = transcript::read_lines(&path).unwrap();
+        assert_eq!(lines, vec![line(0, "first")]);
+
+        // Recording resumes after the crash without corrupting the file
+        let mut writer = transcript::TranscriptWriter::open(&path).unwrap();
+        writer.append(&line(2000, "second")).unwrap();
+        let lines = transcript::read_lines(&path).unwrap();
+        assert_eq!(lines, vec![line(0, "first"), line(2000, "second")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diarization_separates_two_voices() {
+        let dir = std::env::temp_dir().join(format!("polka-diarization-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio_path = dir.join("audio.wav");
+
+        // Two "voices": a low buzz with a dark timbre and a higher, brighter one
+        let sample_rate = 16000u32;
+        let voice = |pitch: f32, brightness: f32, n: usize| -> f32 {
+            let t = n as f32 / sample_rate as f32;
+            (1..=20)
+                .map(|h| {
+                    let h = h as f32;
+                    (2.0 * std::f32::consts::PI * pitch * h * t).sin() * brightness.powf(h - 1.0)
+                })
+                .sum::<f32>() * 0.1
+        };
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&audio_path, spec).unwrap();
+        let turns = [(110.0, 0.5), (210.0, 0.9), (110.0, 0.5), (210.0, 0.9), (210.0, 0.9)];
+        let mut lines = Vec::new();
+        for (i, &(pitch, brightness)) in turns.iter().enumerate() {
+            for n in 0..2 * sample_rate as usize {
+                let sample = voice(pitch, brightness, n);
+                writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).unwrap();
+            }
+            lines.push(TranscriptLine {
+                t_ms: i as u64 * 2000,
+                speaker: "Speaker".to_string(),
+                text: format!("turn {}", i),
+                end_ms: Some(i as u64 * 2000 + 2000),
+                confidence: None,
+                words: Vec::new(),
+            });
+        }
+        // Too short to judge, takes the previous speaker
+        lines.push(TranscriptLine {
+            t_ms: 10_000,
+            speaker: "Speaker".to_string(),
+            text: "mm".to_string(),
+            end_ms: Some(10_100),
+            confidence: None,
+            words: Vec::new(),
+        });
+        writer.finalize().unwrap();
+
+        let speakers = diarization::diarize_lines(&audio_path, &mut lines, None).unwrap();
+        let labels: Vec<&str> = lines.iter().map(|line| line.speaker.as_str()).collect();
+        assert_eq!(speakers, 2);
+        assert_eq!(labels, ["Speaker 1", "Speaker 2", "Speaker 1", "Speaker 2", "Speaker 2", "Speaker 2"]);
+
+        // Asking for one speaker puts everyone together
+        let speakers = diarization::diarize_lines(&audio_path, &mut lines, Some(1)).unwrap();
+        assert_eq!(speakers, 1);
+        assert!(lines.iter().all(|line| line.speaker == "Speaker 1"));
+
+        // Renaming rewrites every line of that speaker, and renaming onto an existing name merges
+        let transcript_path = dir.join("transcript.jsonl");
+        diarization::diarize_lines(&audio_path, &mut lines, None).unwrap();
+        transcript::write_lines(&transcript_path, &lines).unwrap();
+        assert_eq!(transcript::rename_speaker(&transcript_path, "Speaker 1", "Professor").unwrap(), 2);
+        assert_eq!(transcript::rename_speaker(&transcript_path, "Speaker 2", "Professor").unwrap(), 4);
+        assert_eq!(transcript::rename_speaker(&transcript_path, "Nobody", "Someone").unwrap(), 0);
+        let lines = transcript::read_lines(&transcript_path).unwrap();
+        assert!(lines.iter().all(|line| line.speaker == "Professor"));
+        assert_eq!(lines[0].text, "turn 0");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_vad_gates_speech_and_trims_silence() {
+        let sample_rate = 16000u32;
+        let tone = |seconds: f32| -> Vec<f32> {
+            (0..(seconds * sample_rate as f32) as usize)
+                .map(|n| (2.0 * std::f32::consts::PI * 220.0 * n as f32 / sample_rate as f32).sin() * 0.3)
+                .collect()
+        };
+        let silence = |seconds: f32| vec![0.0f32; (seconds * sample_rate as f32) as usize];
+        // 1s quiet, 1s speech, 5s quiet, 1s speech, 1s quiet
+        let audio = [silence(1.0), tone(1.0), silence(5.0), tone(1.0), silence(1.0)].concat();
+
+        // The gate hands over speech plus padding, skips the rest and ends each utterance
+        let config = VadConfig::default();
+        let mut gate = vad::SpeechGate::new(&config, sample_rate);
+        let mut actions = Vec::new();
+        for chunk in audio.chunks(1234) {
+            actions.extend(gate.process(chunk));
+        }
+        actions.extend(gate.finish());
+
+        let mut position = 0usize;
+        let mut fed_from = Vec::new();
+        let mut feeding = false;
+        let mut utterances = 0;
+        for action in &actions {
+            match action {
+                vad::GateAction::Skip(n) => position += n,
+                vad::GateAction::Feed(samples) => {
+                    if !feeding {
+                        fed_from.push(position * 1000 / sample_rate as usize);
+                    }
+                    position += samples.len();
+                }
+                vad::GateAction::EndOfSpeech => utterances += 1,
+            }
+            feeding = matches!(action, vad::GateAction::Feed(_));
+        }
+        assert!(position <= audio.len());
+        assert_eq!(utterances, 2);
+        assert_eq!(fed_from, vec![800, 6800]);
+
+        let dir = std::env::temp_dir().join(format!("polka-vad-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio_path = dir.join("audio.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&audio_path, spec).unwrap();
+        for &sample in &audio {
+            writer.write_sample((sample * i16::MAX as f32) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let silences = vad::find_silences(&audio_path, &config).unwrap();
+        let silences: Vec<(u64, u64)> = silences.iter().map(|span| (span.start_ms, span.end_ms)).collect();
+        assert_eq!(silences, vec![(0, 1000), (2000, 7000), (8000, 9000)]);
+
+        // Per-session settings round-trip, with defaults when none are saved
+        assert_eq!(VadConfig::load(&dir).unwrap(), config);
+        let trimming = VadConfig { trim_silence: true, max_silence_ms: 1000, ..config };
+        trimming.save(&dir).unwrap();
+        assert_eq!(VadConfig::load(&dir).unwrap(), trimming);
+
+        // Only the 5s silence is longer than the limit; it shrinks to 1s
+        let cuts = vad::trim_long_silences(&audio_path, &trimming).unwrap();
+        assert_eq!(cuts, vec![vad::SilenceCut { at_ms: 2500, removed_ms: 4000 }]);
+        let reader = hound::WavReader::open(&audio_path).unwrap();
+        assert_eq!(reader.duration(), 5 * sample_rate);
+
+        let mut lines = vec![
+            TranscriptLine {
+                t_ms: 1000,
+                speaker: "Speaker".to_string(),
+                text: "first".to_string(),
+                end_ms: Some(2000),
+                confidence: None,
+                words: Vec::new(),
+            },
+            TranscriptLine {
+                t_ms: 7000,
+                speaker: "Speaker".to_string(),
+                text: "second".to_string(),
+                end_ms: Some(8000),
+                confidence: None,
+                words: vec![TranscriptWord { text: "second".to_string(), t_ms: 7000, end_ms: 8000, confidence: None }],
+            },
+        ];
+        vad::shift_transcript(&mut lines, &cuts);
+        assert_eq!((lines[0].t_ms, lines[0].end_ms), (1000, Some(2000)));
+        assert_eq!((lines[1].t_ms, lines[1].end_ms), (3000, Some(4000)));
+        assert_eq!((lines[1].words[0].t_ms, lines[1].words[0].end_ms), (3000, 4000));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_format_conversion() {
+        use crate::resample::{Resampler, remap_channels};
+
+        assert_eq!(remap_channels(&[0.2, 0.4, -1.0, 1.0], 2, 1), vec![0.3, 0.0]);
+        assert_eq!(remap_channels(&[0.5, -0.5], 1, 2), vec![0.5, 0.5, -0.5, -0.5]);
+
+        // A tone resampled in uneven chunks comes out whole, in place and without seams
+        let tone = |rate: u32, hz: f32, len: usize| -> Vec<f32> {
+            (0..len).map(|n| (2.0 * std::f32::consts::PI * hz * n as f32 / rate as f32).sin() * 0.5).collect()
+        };
+        let resample = |input: &[f32]| -> Vec<f32> {
+            let mut resampler = Resampler::new(48000, 16000, 1);
+            let mut output = Vec::new();
+            for chunk in input.chunks(1001) {
+                output.extend(resampler.process(chunk));
+            }
+            output.extend(resampler.flush());
+            output
+        };
+        let output = resample(&tone(48000, 1000.0, 48000));
+        assert_eq!(output.len(), 16000);
+        let expected = tone(16000, 1000.0, 16000);
+        for n in 100..15900 {
+            assert!((output[n] - expected[n]).abs() < 1e-3, "sample {} is off: {} vs {}", n, output[n], expected[n]);
+        }
+
+        // Frequencies the lower rate can't hold are filtered out instead of aliasing
+        let output = resample(&tone(48000, 12000.0, 48000));
+        let rms = (output[100..15900].iter().map(|s| s * s).sum::<f32>() / 15800.0).sqrt();
+        assert!(rms < 0.005, "aliased energy {}", rms);
+    }
+
+    #[test]
+    fn test_compressed_storage_round_trip() {
+        use crate::codec::{self, AudioReader};
+        use crate::settings::StorageFormat;
+
+        let dir = std::env::temp_dir().join(format!("polka-codec-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wav_path = dir.join("audio.wav");
+
+        // Speech-like tone, digital silence and full-scale noise exercise every subframe type
+        let write_wav = |spec: hound::WavSpec| -> Vec<i32> {
+            let max = (1i64 << (spec.bits_per_sample - 1)) - 1;
+            let mut seed = 12345u32;
+            let frames = spec.sample_rate as usize * 3 + 1234;
+            let samples: Vec<i32> = (0..frames * spec.channels as usize)
+                .map(|i| {
+                    let frame = i / spec.channels as usize;
+                    let t = frame as f64 / spec.sample_rate as f64;
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    match frame * 3 / frames {
+                        0 => ((2.0 * std::f64::consts::PI * 440.0 * t).sin() * 0.4 * max as f64) as i32,
+                        1 => 0,
+                        _ => ((seed >> 8) as i64 % max) as i32,
+                    }
+                })
+                .collect();
+            let mut writer = hound::WavWriter::create(&wav_path, spec).unwrap();
+            for &sample in &samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+            samples
+        };
+
+        for (sample_rate, channels, bits_per_sample) in [(16000, 1, 16), (44100, 2, 24)] {
+            let spec = hound::WavSpec { channels, sample_rate, bits_per_sample, sample_format: hound::SampleFormat::Int };
+            let original = write_wav(spec);
+
+            let flac_path = codec::store_recording(&wav_path, StorageFormat::Flac).unwrap();
+            assert_eq!(flac_path, dir.join("audio.flac"));
+            assert!(!wav_path.exists());
+            assert_eq!(codec::find_session_audio(&dir), Some(flac_path.clone()));
+            assert!(std::fs::metadata(&flac_path).unwrap().len() < (original.len() * bits_per_sample as usize / 8) as u64);
+
+            let info = codec::audio_info(&flac_path).unwrap();
+            assert_eq!((info.sample_rate, info.channels), (sample_rate, channels));
+            assert_eq!(info.frames, (original.len() / channels as usize) as u64);
+
+            // Lossless: every sample comes back exactly
+            let scale = (1i64 << (bits_per_sample - 1)) as f32;
+            let decoded: Vec<i32> = AudioReader::open(&flac_path).unwrap()
+                .map(|sample| (sample.unwrap() * scale).round() as i32)
+                .collect();
+            assert_eq!(decoded, original);
+
+            // Recording more audio into the session starts from an identical WAV
+            codec::restore_wav(&dir).unwrap();
+            let reader = hound::WavReader::open(&wav_path).unwrap();
+            assert_eq!(reader.spec(), spec);
+            let restored: Vec<i32> = reader.into_samples::<i32>().map(|s| s.unwrap()).collect();
+            assert_eq!(restored, original);
+            codec::store_recording(&wav_path, StorageFormat::Wav).unwrap();
+            assert!(!flac_path.exists());
+        }
+
+        #[cfg(feature = "opus")]
+        {
+            let spec = hound::WavSpec { channels: 1, sample_rate: 16000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+            let original = write_wav(spec);
+            let opus_path = codec::store_recording(&wav_path, StorageFormat::Opus).unwrap();
+            assert_eq!(opus_path, dir.join("audio.opus"));
+
+            // Lossy, but the same length and lined up with the original
+            let info = codec::audio_info(&opus_path).unwrap();
+            assert_eq!((info.sample_rate, info.channels), (16000, 1));
+            assert!((info.frames as i64 - original.len() as i64).abs() <= 1);
+            let decoded: Vec<f32> = AudioReader::open(&opus_path).unwrap().map(|s| s.unwrap()).collect();
+            assert!((decoded.len() as i64 - original.len() as i64).abs() <= 1);
+            let tone_end = original.len() / 3 - 100;
+            let error: f32 = (100..tone_end)
+                .map(|i| (decoded[i] - original[i] as f32 / 32768.0).powi(2))
+                .sum::<f32>() / (tone_end - 100) as f32;
+            assert!(error.sqrt() < 0.05, "opus error {}", error.sqrt());
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_flac_encoder_edge_cases() {
+        use crate::flac::FlacWriter;
+        use std::io::Cursor;
+
+        // The block size the writer uses
+        const BLOCK_SIZE: usize = 4096;
+
+        // Encode `samples` in uneven pieces and decode them with claxon's full
+        // reader, which checks the stream info and every frame's CRCs on the way
+        fn round_trip(samples: &[i32], channels: u16, bits_per_sample: u16) -> Vec<u8> {
+            let mut writer = FlacWriter::new(Cursor::new(Vec::new()), 44100, channels, bits_per_sample).unwrap();
+            for piece in samples.chunks(1001 * channels as usize) {
+                writer.write_samples(piece).unwrap();
+            }
+            let bytes = writer.finalize().unwrap().into_inner();
+
+            let frames = (samples.len() / channels as usize) as u64;
+            let mut reader = claxon::FlacReader::new(Cursor::new(&bytes)).unwrap();
+            let info = reader.streaminfo();
+            assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (44100, channels as u32, bits_per_sample as u32));
+            // No samples reads as an unknown count, as the format defines it
+            assert_eq!(info.samples.unwrap_or(0), frames);
+            assert!(info.min_block_size >= 16 && info.max_block_size as usize <= BLOCK_SIZE);
+
+            let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+            assert_eq!(decoded.len(), samples.len(), "{} channels at {} bits", channels, bits_per_sample);
+            assert!(decoded == samples, "{} channels at {} bits don't decode to the input", channels, bits_per_sample);
+            bytes
+        }
+
+        // Full-range white noise for `bits_per_sample`
+        fn noise(len: usize, bits_per_sample: u16, seed: u32) -> Vec<i32> {
+            let mut seed = seed;
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    (seed as i32) >> (32 - bits_per_sample)
+                })
+                .collect()
+        }
+
+        // Every sample size and channel count the writer accepts
+        for bits_per_sample in [8, 12, 16, 20, 24] {
+            for channels in [1, 2, 6] {
+                round_trip(&noise(5000 * channels as usize, bits_per_sample, 7), channels, bits_per_sample);
+            }
+        }
+
+        // A speech-like tone in mono and 24 bit, which the predictors do code
+        let tone = |frames: usize, channels: u16, amplitude: f64| -> Vec<i32> {
+            (0..frames * channels as usize)
+                .map(|i| ((i / channels as usize) as f64 * 0.0625).sin() * amplitude)
+                .map(|s| s as i32)
+                .collect()
+        };
+        let original = tone(3 * BLOCK_SIZE, 1, 20000.0);
+        let bytes = round_trip(&original, 1, 16);
+        assert!(bytes.len() < original.len(), "a tone took {} bytes, over half its 16 bit size", bytes.len());
+        round_trip(&tone(3 * BLOCK_SIZE, 2, 8_000_000.0), 2, 24);
+
+        // Block sizes around the predictor orders and the fixed block size
+        for frames in [0, 1, 2, 3, 4, 5, 15, 16, 17, 1001, BLOCK_SIZE - 1, BLOCK_SIZE, BLOCK_SIZE + 1, 2 * BLOCK_SIZE + 33] {
+            round_trip(&tone(frames, 2, 30000.0), 2, 16);
+            round_trip(&noise(frames, 24, frames as u32), 1, 24);
+        }
+
+        // A loud click in quiet audio: one partition needs the largest Rice
+        // parameter while the rest of the block stays cheap to predict
+        let mut click = vec![0; BLOCK_SIZE * 2];
+        click[1000..1064].copy_from_slice(&noise(64, 20, 3));
+        round_trip(&click, 1, 24);
+
+        // Digital silence is a constant subframe per channel
+        let bytes = round_trip(&vec![0; 4 * BLOCK_SIZE * 2], 2, 16);
+        assert!(bytes.len() < 200, "silence took {} bytes", bytes.len());
+
+        // Full scale: the extremes held, alternating, and the largest possible
+        // residuals, which only verbatim subframes can hold
+        for bits_per_sample in [8, 16, 24] {
+            let (min, max) = (-(1i32 << (bits_per_sample - 1)), (1i32 << (bits_per_sample - 1)) - 1);
+            round_trip(&vec![min; 3000], 1, bits_per_sample);
+            round_trip(&vec![max; 3000 * 2], 2, bits_per_sample);
+            let alternating: Vec<i32> = (0..BLOCK_SIZE + 100).map(|i| if i % 2 == 0 { min } else { max }).collect();
+            round_trip(&alternating, 1, bits_per_sample);
+            let square: Vec<i32> = (0..BLOCK_SIZE * 2).map(|i| if i / 3 % 2 == 0 { min } else { max }).collect();
+            round_trip(&square, 2, bits_per_sample);
+        }
+    }
+
+    #[test]
+    fn test_wav_append_in_place() {
+        let dir = std::env::temp_dir().join(format!("polka-wav-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 24-bit mono with an odd number of frames has a padding byte to write over
+        let spec = hound::WavSpec { channels: 1, sample_rate: 16000, bits_per_sample: 24, sample_format: hound::SampleFormat::Int };
+        let write = |name: &str, samples: &[i32]| {
+            let path = dir.join(name);
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            for &sample in samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+            path
+        };
+        let first: Vec<i32> = (0..1001).map(|i| i * 1000 - 500_000).collect();
+        let second: Vec<i32> = (0..777).map(|i| 4_000_000 - i * 3000).collect();
+        let third: Vec<i32> = (0..10).collect();
+        let main_path = write("audio.wav", &first);
+        let second_path = write("audio_segment_1.wav", &second);
+        let third_path = write("audio_segment_2.wav", &third);
+
+        wav::append_data(&main_path, &second_path).unwrap();
+        wav::append_data(&main_path, &third_path).unwrap();
+
+        let reader = hound::WavReader::open(&main_path).unwrap();
+        assert_eq!(reader.spec(), spec);
+        let samples: Vec<i32> = reader.into_samples::<i32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, [first, second, third].concat());
+
+        // Sizes in the header match the file, padding included
+        let mut file = std::fs::File::open(&main_path).unwrap();
+        let chunk = wav::find_data_chunk(&mut file).unwrap();
+        assert_eq!(chunk.data_len, 1788 * 3);
+        assert_eq!(file.metadata().unwrap().len(), chunk.data_offset + chunk.data_len);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_interrupted_segments() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let dir = std::env::temp_dir().join(format!("polka-recovery-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let spec = hound::WavSpec { channels: 2, sample_rate: 16000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let write = |name: &str, samples: &[i16]| {
+            let path = dir.join(name);
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            for &sample in samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+            path
+        };
+        // What a writer leaves behind when it never finalizes: zero sizes and maybe half a frame
+        let interrupt = |path: &std::path::Path, partial: &[u8]| {
+            let mut file = std::fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+            let chunk = wav::find_data_chunk(&mut file).unwrap();
+            file.seek(SeekFrom::Start(4)).unwrap();
+            file.write_all(&0u32.to_le_bytes()).unwrap();
+            file.seek(SeekFrom::Start(chunk.size_offset)).unwrap();
+            file.write_all(&0u32.to_le_bytes()).unwrap();
+            file.seek(SeekFrom::End(0)).unwrap();
+            file.write_all(partial).unwrap();
+        };
+
+        let recorded: Vec<i16> = (0..2000).map(|i| (i * 7) as i16).collect();
+        let appended: Vec<i16> = (0..1000).map(|i| -(i as i16)).collect();
+        let interrupted: Vec<i16> = (0..600).map(|i| (i * 3) as i16).collect();
+        let main_path = write("audio.wav", &recorded);
+
+        // A pause got as far as copying segment 0 but not patching the header
+        let segment_0 = write("audio_segment_0.wav", &appended);
+        {
+            let bytes = std::fs::read(&segment_0).unwrap();
+            let mut main = std::fs::OpenOptions::new().append(true).open(&main_path).unwrap();
+            main.write_all(&bytes[44..1044]).unwrap();
+        }
+        std::fs::write(dir.join("audio_segment_0.converted.wav"), b"RIFF").unwrap();
+        let segment_1 = write("audio_segment_1.wav", &interrupted);
+        interrupt(&segment_1, &[1, 2, 3]);
+        // The app died right after opening a segment
+        std::fs::write(dir.join("audio_segment_2.wav"), b"").unwrap();
+
+        assert!(audio::recover_segments(&dir).unwrap());
+
+        let reader = hound::WavReader::open(&main_path).unwrap();
+        assert_eq!(reader.spec(), spec);
+        let samples: Vec<i16> = reader.into_samples::<i16>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, [recorded, appended, interrupted].concat());
+
+        // Only the stitched recording is left
+        let mut names: Vec<String> = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["audio.wav"]);
+        assert!(!audio::recover_segments(&dir).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recovery_after_append_patched_header() {
+        let dir = std::env::temp_dir().join(format!("polka-recovery-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let spec = hound::WavSpec { channels: 1, sample_rate: 16000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let write = |name: &str, samples: &[i16]| {
+            let path = dir.join(name);
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            for &sample in samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+            path
+        };
+        let read = |path: &std::path::Path| -> Vec<i16> {
+            hound::WavReader::open(path).unwrap().into_samples::<i16>().map(|s| s.unwrap()).collect()
+        };
+
+        let recorded: Vec<i16> = (0..1500).map(|i| (i * 5) as i16).collect();
+        let appended: Vec<i16> = (0..700).map(|i| -(i as i16)).collect();
+        let main_path = write("audio.wav", &recorded);
+
+        // The app died after the header grew but before the segment was deleted
+        let segment = write("audio_segment_3.wav", &appended);
+        wav::mark_append(&main_path, &segment).unwrap();
+        wav::append_data(&main_path, &segment).unwrap();
+        assert_eq!(read(&main_path), [recorded.clone(), appended.clone()].concat());
+
+        assert!(audio::recover_segments(&dir).unwrap());
+        assert_eq!(read(&main_path), [recorded.clone(), appended.clone()].concat());
+        assert!(!dir.join("audio.wav.appending").exists());
+        assert!(!segment.exists());
+
+        // ...or after the segment was deleted but before the marker was, when the append is complete
+        let segment = write("audio_segment_4.wav", &appended);
+        wav::mark_append(&main_path, &segment).unwrap();
+        wav::append_data(&main_path, &segment).unwrap();
+        std::fs::remove_file(&segment).unwrap();
+
+        assert!(!audio::recover_segments(&dir).unwrap());
+        assert_eq!(read(&main_path), [recorded, appended.clone(), appended].concat());
+        let names: Vec<String> = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names, ["audio.wav"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_duration_from_wav_header() {
+        let dir = std::env::temp_dir().join(format!("polka-duration-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // File size alone would give a different answer for each of these
+        let cases = [(16000, 1, 16, 40_000, 2500), (44100, 2, 16, 44_100 * 3, 3000), (48000, 1, 24, 1000, 20)];
+        for (sample_rate, channels, bits_per_sample, frames, expected_ms) in cases {
+            let path = dir.join(format!("audio_{}_{}.wav", sample_rate, channels));
+            let spec = hound::WavSpec { channels, sample_rate, bits_per_sample, sample_format: hound::SampleFormat::Int };
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            for _ in 0..frames * channels as u32 {
+                writer.write_sample(0i32).unwrap();
+            }
+            writer.finalize().unwrap();
+
+            assert_eq!(codec::audio_info(&path).unwrap().duration_ms(), expected_ms);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_time_stretch_keeps_pitch() {
+        use crate::timestretch::TimeStretcher;
+
+        let sample_rate = 16000;
+        let tone: Vec<f32> = (0..sample_rate * 2)
+            .map(|i| (2.0 * std::f32::consts::PI * 220.0 * i as f32 / sample_rate as f32).sin() * 0.5)
+            .collect();
+        // Rising edges through zero per second give the frequency
+        let frequency = |samples: &[f32]| {
+            let crossings = samples.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+            crossings as f32 * sample_rate as f32 / samples.len() as f32
+        };
+
+        for rate in [0.5, 1.0, 1.5, 3.0] {
+            let mut stretcher = TimeStretcher::new(sample_rate, 1, rate);
+            // Fed in uneven pieces like playback does
+            let mut output = Vec::new();
+            for chunk in tone.chunks(777) {
+                output.extend(stretcher.process(chunk));
+            }
+            output.extend(stretcher.flush());
+
+            let expected = tone.len() as f32 / rate;
+            assert!((output.len() as f32 - expected).abs() < sample_rate as f32 * 0.05,
+                "rate {}: {} samples, expected about {}", rate, output.len(), expected);
+            let middle = &output[output.len() / 4..output.len() * 3 / 4];
+            assert!((frequency(middle) - 220.0).abs() < 5.0, "rate {}: {}Hz", rate, frequency(middle));
+            // Windows that line up cross-fade without cancelling or piling up
+            let rms = (middle.iter().map(|s| s * s).sum::<f32>() / middle.len() as f32).sqrt();
+            assert!((rms - 0.5 / 2f32.sqrt()).abs() < 0.02, "rate {}: rms {}", rate, rms);
+        }
+    }
+
+    #[test]
+    fn test_audio_reader_open_at() {
+        use crate::codec::{self, AudioReader};
+        use crate::settings::StorageFormat;
+
+        let dir = std::env::temp_dir().join(format!("polka-seek-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec = hound::WavSpec { channels: 2, sample_rate: 8000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let wav_path = dir.join("audio.wav");
+        let mut writer = hound::WavWriter::create(&wav_path, spec).unwrap();
+        for i in 0..8000 * 2 {
+            writer.write_sample(((i * 37) % 20000 - 10000) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let all: Vec<f32> = AudioReader::open(&wav_path).unwrap().map(|s| s.unwrap()).collect();
+        let from_wav: Vec<f32> = AudioReader::open_at(&wav_path, 3000).unwrap().map(|s| s.unwrap()).collect();
+        assert_eq!(from_wav, all[3000 * 2..]);
+        // Past the end is just the end
+        assert_eq!(AudioReader::open_at(&wav_path, 10_000).unwrap().count(), 0);
+
+        assert_eq!(AudioReader::open(&wav_path).unwrap().info().bits_per_sample, Some(16));
+
+        let flac_path = codec::store_recording(&wav_path, StorageFormat::Flac).unwrap();
+        let from_flac: Vec<f32> = AudioReader::open_at(&flac_path, 3000).unwrap().map(|s| s.unwrap()).collect();
+        assert_eq!(from_flac, all[3000 * 2..]);
+        assert_eq!(AudioReader::open(&flac_path).unwrap().info().bits_per_sample, Some(16));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_audio_reader_seeks_back_and_forth() {
+        use crate::codec::{self, AudioReader};
+        use crate::settings::StorageFormat;
+
+        let dir = std::env::temp_dir().join(format!("polka-seek-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wav_path = dir.join("audio.wav");
+
+        // Long enough to span many FLAC frames and Ogg pages
+        let spec = hound::WavSpec { channels: 2, sample_rate: 44100, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let frames = 44100 * 20;
+        let mut writer = hound::WavWriter::create(&wav_path, spec).unwrap();
+        for i in 0..frames * 2 {
+            let t = (i / 2) as f64 / 44100.0;
+            let tone = (2.0 * std::f64::consts::PI * (220.0 + 20.0 * t) * t).sin() * 12000.0;
+            writer.write_sample((tone + ((i * 7919) % 2000) as f64 - 1000.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // Far back, far ahead, a little ahead, to the end and past it
+        let targets = [700_000, 12_345, 0, 500_000, 500_100, 505_000, 44100 * 15 + 1, frames - 500, frames, frames + 10];
+        let check = |path: &std::path::Path, all: &[f32], max_error: f32| {
+            let mut reader = AudioReader::open(path).unwrap();
+            for target in targets {
+                reader.seek(target).unwrap();
+                let start = (target.min(frames) * 2) as usize;
+                let expected = &all[start..(start + 4000).min(all.len())];
+                let read: Vec<f32> = reader.by_ref().take(expected.len()).map(|s| s.unwrap()).collect();
+                assert_eq!(read.len(), expected.len(), "{:?} at {}", path, target);
+                let error = read.iter().zip(expected).map(|(a, b)| (a - b).powi(2)).sum::<f32>() / expected.len().max(1) as f32;
+                assert!(error.sqrt() <= max_error, "{:?} at {} is off by {}", path, target, error.sqrt());
+            }
+            assert!(reader.next().is_none());
+        };
+
+        let all: Vec<f32> = AudioReader::open(&wav_path).unwrap().map(|s| s.unwrap()).collect();
+        check(&wav_path, &all, 0.0);
+
+        let flac_path = codec::store_recording(&wav_path, StorageFormat::Flac).unwrap();
+        check(&flac_path, &all, 0.0);
+
+        #[cfg(feature = "opus")]
+        {
+            codec::restore_wav(&dir).unwrap();
+            let opus_path = codec::store_recording(&wav_path, StorageFormat::Opus).unwrap();
+            // Seeking decodes a little audio before the target first, so it
+            // sounds the same as having played up to there
+            let decoded: Vec<f32> = AudioReader::open(&opus_path).unwrap().map(|s| s.unwrap()).collect();
+            assert_eq!(decoded.len(), all.len());
+            check(&opus_path, &decoded, 0.01);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_seek_near_end_of_long_flac() {
+        use crate::flac::FlacSamples;
+
+        let dir = std::env::temp_dir().join(format!("polka-seek-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audio.flac");
+
+        // Three minutes of lecture-quality mono
+        let sample_rate = 8000;
+        let frames = sample_rate as usize * 180;
+        let original: Vec<i32> = (0..frames)
+            .map(|i| ((i as f64 * 0.05).sin() * 8000.0 + ((i * 7919) % 600) as f64 - 300.0) as i32)
+            .collect();
+        let mut writer = crate::flac::FlacWriter::create(&path, sample_rate, 1, 16).unwrap();
+        writer.write_samples(&original).unwrap();
+        writer.finalize().unwrap();
+
+        let (_, mut samples) = FlacSamples::open(&path).unwrap();
+        assert_eq!(samples.by_ref().count(), frames);
+        let all_blocks = samples.decoded_blocks();
+
+        let (_, mut samples) = FlacSamples::open(&path).unwrap();
+        let target = frames as u64 - 5 * sample_rate as u64 - 123;
+        samples.seek(target).unwrap();
+        // Looked up rather than decoded through
+        let seek_blocks = samples.decoded_blocks();
+        assert!(seek_blocks * 10 < all_blocks, "seeking decoded {} of {} blocks", seek_blocks, all_blocks);
+
+        let tail: Vec<i32> = samples.map(|s| (s.unwrap() * 32768.0).round() as i32).collect();
+        assert_eq!(tail, original[target as usize..]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_transcript_line_at() {
+        let line = |t_ms: u64, end_ms: Option<u64>| TranscriptLine {
+            t_ms,
+            speaker: "Speaker".to_string(),
+            text: format!("at {}", t_ms),
+            end_ms,
+            confidence: None,
+            words: Vec::new(),
+        };
+        let lines = vec![line(1000, Some(2500)), line(4000, None), line(4000, Some(5000)), line(7000, None)];
+
+        // Nothing has been said before the first line starts
+        assert_eq!(transcript::line_at(&lines, 0), None);
+        assert_eq!(transcript::line_at(&lines, 999), None);
+        assert_eq!(transcript::line_at(&lines, 1000), Some(0));
+        // A line stays active through the pause after it
+        assert_eq!(transcript::line_at(&lines, 3000), Some(0));
+        // Lines starting together resolve to the later one
+        assert_eq!(transcript::line_at(&lines, 4000), Some(2));
+        assert_eq!(transcript::line_at(&lines, 60_000), Some(3));
+        assert_eq!(transcript::line_at(&[], 5000), None);
+    }
+
+    #[test]
+    fn test_waveform_peaks_are_cached() {
+        let dir = std::env::temp_dir().join(format!("polka-waveform-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audio.wav");
+        let write_audio = |seconds: u32| {
+            let spec = hound::WavSpec { channels: 2, sample_rate: 16000, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+            let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+            // Half a second of sound on both channels, then silence
+            for frame in 0..16000 * seconds {
+                let (left, right) = if frame < 8000 { (0.5f32, -0.25f32) } else { (0.0, 0.0) };
+                writer.write_sample(left).unwrap();
+                writer.write_sample(right).unwrap();
+            }
+            writer.finalize().unwrap();
+        };
+        let close = |a: &[f32], b: &[f32]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3);
+
+        write_audio(1);
+        let computed = waveform::load(&dir, &path).unwrap();
+        assert_eq!(computed.duration_ms(), 1000);
+        assert_eq!(computed.peaks.len(), 100);
+        assert_eq!(computed.peaks[0], (-0.25, 0.5));
+        assert_eq!(computed.peaks[99], (0.0, 0.0));
+        assert!(dir.join(waveform::WAVEFORM_FILE).exists());
+
+        let halves = computed.downsample(2);
+        assert_eq!(halves.duration_ms, 1000);
+        assert!(close(&halves.min, &[-0.25, 0.0]));
+        assert!(close(&halves.max, &[0.5, 0.0]));
+        // Asking for more detail than was cached gives every cached peak
+        assert_eq!(computed.downsample(10_000).max.len(), 100);
+
+        // The second request is served from the sidecar file
+        let cached = waveform::load(&dir, &path).unwrap().downsample(100);
+        let all = computed.downsample(100);
+        assert!(close(&cached.min, &all.min) && close(&cached.max, &all.max));
+
+        // Recording more makes the cache stale
+        write_audio(2);
+        let recomputed = waveform::load(&dir, &path).unwrap();
+        assert_eq!(recomputed.duration_ms(), 2000);
+        assert_eq!(recomputed.peaks.len(), 200);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_input_meter_and_warnings() {
+        use crate::meter::{InputMeter, InputWarning, InputWatch};
+
+        let sample_rate = 48000;
+        let sine = |amplitude: f32, frequency: f32, phase: f32, frames: usize| -> Vec<f32> {
+            (0..frames)
+                .map(|i| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32 + phase).sin())
+                .collect()
+        };
+
+        // A sine's RMS sits 3dB under its peak
+        let levels = InputMeter::new(1).measure(&sine(0.5, 1000.0, 0.0, 4800));
+        assert!((levels.rms_db() - -9.03).abs() < 0.05, "rms {}", levels.rms_db());
+        assert!((levels.true_peak_db() - -6.02).abs() < 0.1, "peak {}", levels.true_peak_db());
+        assert!(!levels.clipped);
+
+        // Samples that straddle the crest still reveal the peak between them
+        let straddling = sine(1.0, sample_rate as f32 / 4.0, std::f32::consts::FRAC_PI_4, 4800);
+        let levels = InputMeter::new(1).measure(&straddling);
+        assert!(straddling.iter().all(|s| s.abs() < 0.71));
+        assert!(levels.true_peak_db() > -2.0, "peak {}", levels.true_peak_db());
+        assert!(!levels.clipped);
+
+        // Feed 50ms buffers the way the level task does
+        let mut meter = InputMeter::new(2);
+        let mut watch = InputWatch::new(sample_rate);
+        let mut run = |seconds: u32, amplitude: f32| {
+            let buffer: Vec<f32> = sine(amplitude, 440.0, 0.0, 2400).into_iter().flat_map(|s| [s, s]).collect();
+            let mut changes = Vec::new();
+            for _ in 0..seconds * 20 {
+                changes.extend(watch.update(&meter.measure(&buffer)));
+            }
+            changes
+        };
+
+        assert_eq!(run(9, 0.0), vec![]);
+        assert_eq!(run(1, 0.0), vec![(InputWarning::Silence, true)]);
+        assert_eq!(run(1, 0.3), vec![(InputWarning::Silence, false)]);
+        // A muffled but working microphone is not silence
+        assert_eq!(run(12, 0.001), vec![]);
+
+        assert_eq!(run(2, 1.0), vec![]);
+        assert_eq!(run(1, 1.0), vec![(InputWarning::Clipping, true)]);
+        assert_eq!(run(2, 0.3), vec![]);
+        assert_eq!(run(1, 0.3), vec![(InputWarning::Clipping, false)]);
+    }
+
+    #[test]
+    fn test_migrations_upgrade_legacy_database() {
+        use crate::migrations;
+
+        let dir = std::env::temp_dir().join(format!("polka-migration-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // A new database ends up at the latest version
+        let fresh = Database::open(&dir.join("fresh.db")).unwrap();
+        fresh.insert_session(&Session {
+            id: "fresh".to_string(),
+            title: "Lecture".to_string(),
+            course: "CS".to_string(),
+            created_at: 1,
+            duration_ms: 0,
+            status: SessionStatus::Archived,
+            notes_path: None,
+            audio_path: None,
+            transcript_path: None,
+            recovered: true,
+            course_id: None,
+            tags: Vec::new(),
+        }).unwrap();
+        assert_eq!(fresh.get_session("fresh").unwrap().unwrap().status, SessionStatus::Archived);
+        drop(fresh);
+        let conn = rusqlite::Connection::open(dir.join("fresh.db")).unwrap();
+        assert_eq!(migrations::schema_version(&conn).unwrap(), migrations::latest_version());
+        drop(conn);
+
+        // A database from before versioning, migrated by the old probe-row code
+        let legacy_path = dir.join("legacy.db");
+        let conn = rusqlite::Connection::open(&legacy_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                course TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                duration_ms INTEGER DEFAULT 0,
+                status TEXT NOT NULL CHECK (status IN ('draft', 'recording', 'complete', 'archived')),
+                notes_path TEXT,
+                audio_path TEXT,
+                transcript_path TEXT
+            );
+            INSERT INTO sessions VALUES ('a', 'Intro', 'CS', 10, 5000, 'complete', 'notes.md', 'audio.wav', 'transcript.jsonl');
+            INSERT INTO sessions VALUES ('b', 'Live', 'CS', 20, 0, 'recording', NULL, NULL, NULL);
+            INSERT INTO sessions (id, title, course, created_at, status) VALUES ('__migration_test__', 'test', 'test', 0, 'archived');",
+        ).unwrap();
+        drop(conn);
+
+        let db = Database::open(&legacy_path).unwrap();
+        let sessions = db.list_sessions().unwrap();
+        assert_eq!(sessions.len(), 2);
+        let intro = db.get_session("a").unwrap().unwrap();
+        assert_eq!((intro.status, intro.duration_ms, intro.recovered), (SessionStatus::Complete, 5000, false));
+        assert_eq!(intro.audio_path.as_deref(), Some("audio.wav"));
+        assert_eq!(db.get_session("b").unwrap().unwrap().status, SessionStatus::Draft);
+        drop(db);
+
+        let mut conn = rusqlite::Connection::open(&legacy_path).unwrap();
+        assert_eq!(migrations::schema_version(&conn).unwrap(), migrations::latest_version());
+        // Only statuses the app knows are accepted now
+        assert!(conn.execute("UPDATE sessions SET status = 'recording' WHERE id = 'a'", []).is_err());
+
+        // Running again is a no-op
+        assert_eq!(migrations::migrate(&mut conn).unwrap(), migrations::latest_version());
+
+        // A failing step is rolled back entirely and stops the run
+        fn create_notes(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+            tx.execute("CREATE TABLE notes (id TEXT)", []).map(|_| ())
+        }
+        fn broken(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+            tx.execute("CREATE TABLE scratch (id TEXT)", [])?;
+            tx.execute("INSERT INTO missing_table VALUES (1)", []).map(|_| ())
+        }
+        let mut steps = migrations::MIGRATIONS.to_vec();
+        steps.push(migrations::Migration { description: "Add notes", apply: create_notes });
+        steps.push(migrations::Migration { description: "Broken", apply: broken });
+        assert!(migrations::run(&mut conn, &steps).is_err());
+        let latest = migrations::latest_version();
+        assert_eq!(migrations::schema_version(&conn).unwrap(), latest + 1);
+        let table_exists = |name: &str| conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1").unwrap()
+            .exists([name]).unwrap();
+        assert!(table_exists("notes"));
+        assert!(!table_exists("scratch"));
+
+        // This build refuses a database written by a newer one
+        drop(conn);
+        assert!(Database::open(&legacy_path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_database_pool_allows_concurrent_access() {
+        let dir = std::env::temp_dir().join(format!("polka-pool-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("polka.db");
+        let db = Database::open(&path).unwrap();
+        let session = |id: String| Session {
+            id,
+            title: "Lecture".to_string(),
+            course: "CS".to_string(),
+            created_at: 0,
+            duration_ms: 0,
+            status: SessionStatus::Draft,
+            notes_path: None,
+            audio_path: None,
+            transcript_path: None,
+            recovered: false,
+            course_id: None,
+            tags: Vec::new(),
+        };
+
+        let writer = rusqlite::Connection::open(&path).unwrap();
+        let journal_mode: String = writer.pragma_query_value(None, "journal_mode", |row| row.get(0)).unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        // Commands share one handle and run side by side
+        let threads: Vec<_> = (0..4).map(|thread| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                for i in 0..25 {
+                    db.insert_session(&session(format!("{}-{}", thread, i))).unwrap();
+                    db.list_sessions().unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(db.list_sessions().unwrap().len(), 100);
+
+        // Reading doesn't wait for a write that is still in progress
+        writer.execute_batch("BEGIN IMMEDIATE; DELETE FROM sessions;").unwrap();
+        assert_eq!(db.list_sessions().unwrap().len(), 100);
+        writer.execute_batch("COMMIT;").unwrap();
+        assert!(db.list_sessions().unwrap().is_empty());
+
+        drop(writer);
+        drop(db);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_transcripts_and_notes() {
+        use crate::models::SearchSource;
+
+        let dir = std::env::temp_dir().join(format!("polka-search-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::open(&dir.join("polka.db")).unwrap();
+        for id in ["biology", "physics", "empty"] {
+            db.insert_session(&Session {
+                id: id.to_string(),
+                title: id.to_string(),
+                course: "Science".to_string(),
+                created_at: 0,
+                duration_ms: 0,
+                status: SessionStatus::Draft,
+                notes_path: None,
+                audio_path: None,
+                transcript_path: None,
+                recovered: false,
+                course_id: None,
+                tags: Vec::new(),
+            }).unwrap();
+        }
+        let line = |t_ms: u64, text: &str| TranscriptLine {
+            t_ms,
+            speaker: "Speaker".to_string(),
+            text: text.to_string(),
+            end_ms: None,
+            confidence: None,
+            words: Vec::new(),
+        };
+        let highlighted = |hit: &SearchHit| -> Vec<String> {
+            let units: Vec<u16> = hit.snippet.encode_utf16().collect();
+            hit.highlights.iter().map(|[start, end]| String::from_utf16(&units[*start..*end]).unwrap()).collect()
+        };
+
+        db.index_transcript_line("biology", &line(1500, "The mitochondria is the powerhouse of the cell")).unwrap();
+        db.index_notes("biology", "# Cell biology\n\nMembranes and **mitochondria**, see the café résumé").unwrap();
+        db.index_transcript("physics", &[line(0, "Intro to thermodynamics"), line(62_000, "Entropy always increases")]).unwrap();
+        assert_eq!(db.list_unindexed_sessions().unwrap().iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), vec!["empty"]);
+
+        // Words match as prefixes, in transcripts and notes alike
+        let hits = db.search("mitochon", 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.session_id == "biology" && highlighted(hit) == vec!["mitochondria"]));
+        let transcript_hit = hits.iter().find(|hit| hit.source == SearchSource::Transcript).unwrap();
+        assert_eq!(transcript_hit.t_ms, Some(1500));
+        assert_eq!(hits.iter().find(|hit| hit.source == SearchSource::Notes).unwrap().t_ms, None);
+
+        let hits = db.search("entropy", 10).unwrap();
+        assert_eq!((hits.len(), hits[0].session_id.as_str(), hits[0].t_ms), (1, "physics", Some(62_000)));
+
+        // Accents don't matter, and highlights count UTF-16 units
+        let hits = db.search("resume", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(highlighted(&hits[0]), vec!["résumé"]);
+
+        // All words have to match; what users type is never taken as query syntax
+        assert!(db.search("entropy mitochondria", 10).unwrap().is_empty());
+        for query in ["\"", "AND (", "cell\" OR", "*", "  "] {
+            db.search(query, 10).unwrap();
+        }
+
+        // Rewriting a transcript or clearing notes replaces what was indexed
+        db.index_transcript("physics", &[line(0, "Intro to thermodynamics")]).unwrap();
+        assert!(db.search("entropy", 10).unwrap().is_empty());
+        db.index_notes("biology", "").unwrap();
+        assert_eq!(db.search("resume", 10).unwrap().len(), 0);
+
+        db.delete_session("biology").unwrap();
+        assert!(db.search("mitochondria", 10).unwrap().is_empty());
+        assert_eq!(db.search("thermo", 10).unwrap().len(), 1);
+
+        drop(db);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_courses_fold_course_names_and_add_up_sessions() {
+        use crate::migrations;
+
+        let dir = std::env::temp_dir().join(format!("polka-courses-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("polka.db");
+
+        // Sessions from before courses, with the same course typed a few ways
+        let mut conn = rusqlite::Connection::open(&path).unwrap();
+        migrations::run(&mut conn, &migrations::MIGRATIONS[..4]).unwrap();
+        for (id, course, created_at, duration_ms) in [
+            ("a", "Biology 101", 1, 60_000),
+            ("b", " biology 101 ", 2, 30_000),
+            ("c", "Physics", 3, 45_000),
+            ("d", "", 4, 10_000),
+        ] {
+            conn.execute(
+                "INSERT INTO sessions (id, title, course, created_at, duration_ms, status) VALUES (?1, 'Lecture', ?2, ?3, ?4, 'draft')",
+                (id, course, created_at, duration_ms),
+            ).unwrap();
+        }
+        drop(conn);
+
+        let db = Database::open(&path).unwrap();
+        let courses = db.list_courses().unwrap();
+        assert_eq!(courses.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["Biology 101", "Physics"]);
+        let biology = courses[0].clone();
+        let a = db.get_session("a").unwrap().unwrap();
+        let b = db.get_session("b").unwrap().unwrap();
+        assert_eq!((a.course_id.as_deref(), a.course.as_str()), (Some(biology.id.as_str()), "Biology 101"));
+        assert_eq!((b.course_id.as_deref(), b.course.as_str()), (Some(biology.id.as_str()), "Biology 101"));
+        assert_eq!(db.get_session("d").unwrap().unwrap().course_id, None);
+        assert_eq!(db.find_course_by_name("BIOLOGY 101").unwrap(), Some(biology.clone()));
+
+        let stats = db.course_stats().unwrap();
+        assert_eq!(stats[0], CourseStats { course_id: biology.id.clone(), session_count: 2, total_duration_ms: 90_000 });
+        assert_eq!((stats[1].session_count, stats[1].total_duration_ms), (1, 45_000));
+
+        // Renaming a course renames it on its sessions
+        let input = CourseInput {
+            name: "  Cell Biology ".to_string(),
+            code: Some("BIO101".to_string()),
+            term: Some(" ".to_string()),
+            color: Some("#2A9D8F".to_string()),
+            instructor: None,
+        }.normalized().unwrap();
+        assert_eq!((input.name.as_str(), input.term.as_deref(), input.color.as_deref()), ("Cell Biology", None, Some("#2a9d8f")));
+        assert!(CourseInput { name: "Art".to_string(), color: Some("teal".to_string()), ..CourseInput::default() }.normalized().is_err());
+        assert!(CourseInput { name: " ".to_string(), ..CourseInput::default() }.normalized().is_err());
+        let renamed = Course { name: input.name, code: input.code, color: input.color, ..biology.clone() };
+        assert!(db.update_course(&renamed).unwrap());
+        assert_eq!(db.get_course(&biology.id).unwrap(), Some(renamed.clone()));
+        assert_eq!(db.get_session("b").unwrap().unwrap().course, "Cell Biology");
+
+        // Names are unique whatever their case
+        let duplicate = Course { id: nanoid!(), name: "physics".to_string(), ..renamed.clone() };
+        assert!(db.insert_course(&duplicate).is_err());
+
+        // Deleting a course keeps its sessions, under no course
+        db.set_session_course("d", Some(&renamed)).unwrap();
+        assert_eq!(db.course_stats().unwrap()[0].session_count, 3);
+        assert!(db.delete_course(&biology.id).unwrap());
+        assert!(!db.delete_course(&biology.id).unwrap());
+        let a = db.get_session("a").unwrap().unwrap();
+        assert_eq!((a.course_id, a.course.as_str()), (None, ""));
+        assert_eq!(db.list_sessions().unwrap().len(), 4);
+        assert_eq!(db.course_stats().unwrap().len(), 1);
+
+        drop(db);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tags_label_and_filter_sessions() {
+        let dir = std::env::temp_dir().join(format!("polka-tags-test-{}", nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::open(&dir.join("polka.db")).unwrap();
+        for (id, created_at) in [("lecture", 3), ("lab", 2), ("review", 1)] {
+            db.insert_session(&Session {
+                id: id.to_string(),
+                title: id.to_string(),
+                course: String::new(),
+                course_id: None,
+                created_at,
+                duration_ms: 0,
+                status: SessionStatus::Draft,
+                notes_path: None,
+                audio_path: None,
+                transcript_path: None,
+                recovered: false,
+                tags: Vec::new(),
+            }).unwrap();
+        }
+        let tag = |name: &str| {
+            let tag = Tag { id: nanoid!(), name: Tag::normalize_name(name).unwrap(), created_at: 0 };
+            db.insert_tag(&tag).unwrap();
+            tag
+        };
+        let ids = |filter: SessionFilter| -> Vec<String> {
+            db.list_sessions_matching(&filter).unwrap().into_iter().map(|s| s.id).collect()
+        };
+        let tag_names = |id: &str| -> Vec<String> {
+            db.get_session(id).unwrap().unwrap().tags.into_iter().map(|t| t.name).collect()
+        };
+
+        assert_eq!(Tag::normalize_name("  exam \t review ").unwrap(), "exam review");
+        assert!(Tag::normalize_name(" ").is_err());
+
+        let exam = tag("exam-review");
+        let lab = tag("lab");
+        let practical = tag("Practical");
+        assert!(db.insert_tag(&Tag { id: nanoid!(), name: "LAB".to_string(), created_at: 0 }).is_err());
+        assert_eq!(db.find_tag_by_name("EXAM-REVIEW").unwrap(), Some(exam.clone()));
+
+        db.add_session_tag("lecture", &exam.id).unwrap();
+        db.add_session_tag("lab", &lab.id).unwrap();
+        db.add_session_tag("lab", &lab.id).unwrap();
+        db.add_session_tag("lab", &exam.id).unwrap();
+        db.add_session_tag("review", &practical.id).unwrap();
+        assert!(db.add_session_tag("missing", &lab.id).is_err());
+        assert_eq!(tag_names("lab"), vec!["exam-review", "lab"]);
+
+        // All the tags by default, any of them when asked
+        assert_eq!(ids(SessionFilter::default()), vec!["lecture", "lab", "review"]);
+        assert_eq!(ids(SessionFilter { tags: vec![exam.id.clone()], any_tag: false }), vec!["lecture", "lab"]);
+        assert_eq!(ids(SessionFilter { tags: vec![exam.id.clone(), lab.id.clone()], any_tag: false }), vec!["lab"]);
+        assert_eq!(ids(SessionFilter { tags: vec![lab.id.clone(), practical.id.clone()], any_tag: true }), vec!["lab", "review"]);
+
+        // Renaming onto an existing tag merges the two
+        let renamed = db.rename_tag(&exam.id, "Exam Review").unwrap().unwrap();
+        assert_eq!((renamed.id.as_str(), renamed.name.as_str()), (exam.id.as_str(), "Exam Review"));
+        let merged = db.rename_tag(&practical.id, "lab").unwrap().unwrap();
+        assert_eq!(merged, lab);
+        assert_eq!(db.get_tag(&practical.id).unwrap(), None);
+        assert_eq!(ids(SessionFilter { tags: vec![lab.id.clone()], any_tag: false }), vec!["lab", "review"]);
+        assert_eq!(db.rename_tag("missing", "anything").unwrap(), None);
+        // A missing tag isn't merged into one that has the name
+        assert_eq!(db.rename_tag(&practical.id, "Exam Review").unwrap(), None);
+        assert_eq!(ids(SessionFilter { tags: vec![exam.id.clone()], any_tag: false }), vec!["lecture", "lab"]);
+
+        db.merge_tags(&lab.id, &exam.id).unwrap();
+        assert_eq!(db.list_tags().unwrap(), vec![renamed.clone()]);
+        assert_eq!(tag_names("lab"), vec!["Exam Review"]);
+        assert_eq!(ids(SessionFilter { tags: vec![exam.id.clone()], any_tag: false }), vec!["lecture", "lab", "review"]);
+
+        assert!(db.remove_session_tag("review", &exam.id).unwrap());
+        assert!(!db.remove_session_tag("review", &exam.id).unwrap());
+        assert!(db.delete_session("lecture").unwrap());
+        assert!(db.delete_tag(&exam.id).unwrap());
+        assert!(db.list_tags().unwrap().is_empty());
+        assert!(tag_names("lab").is_empty());
+
+        drop(db);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,203 @@
+use serde::Serialize;
+
+/// Level reported for no signal at all.
+pub const MIN_DB: f32 = -100.0;
+
+// Samples this close to full scale were most likely cut off by the converter
+const CLIP_LEVEL: f32 = 0.999;
+// Quieter than this (RMS) is a muted or unplugged microphone, not a quiet room
+const SILENCE_DB: f32 = -70.0;
+const SILENCE_WARNING_MS: u64 = 10_000;
+// Seconds of audio in a row that have to clip before the input counts as
+// constantly clipping, and that have to be clean before it no longer does
+const CLIPPING_SECONDS: u32 = 3;
+// Points per sample the true peak is looked for at
+const OVERSAMPLE: usize = 4;
+
+pub fn to_db(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(MIN_DB)
+    } else {
+        MIN_DB
+    }
+}
+
+/// Loudness of a stretch of input, merged from any number of device buffers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Levels {
+    /// Length in samples per channel.
+    pub frames: u64,
+    samples: u64,
+    sum_squares: f64,
+    /// Highest level of the waveform, including between samples.
+    pub true_peak: f32,
+    /// A sample reached full scale.
+    pub clipped: bool,
+}
+
+impl Levels {
+    pub fn merge(&mut self, other: &Levels) {
+        self.frames += other.frames;
+        self.samples += other.samples;
+        self.sum_squares += other.sum_squares;
+        self.true_peak = self.true_peak.max(other.true_peak);
+        self.clipped |= other.clipped;
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        if self.samples == 0 {
+            return MIN_DB;
+        }
+        to_db((self.sum_squares / self.samples as f64).sqrt() as f32)
+    }
+
+    pub fn true_peak_db(&self) -> f32 {
+        to_db(self.true_peak)
+    }
+}
+
+/// Measures interleaved input buffers as they arrive.
+///
+/// The true peak is estimated by interpolating each channel at 4x its
+/// sample rate, which finds the overs a converter or encoder produces even
+/// when no sample reaches full scale. The last samples of every channel are
+/// carried over so peaks between two buffers are found too.
+pub struct InputMeter {
+    channels: usize,
+    history: Vec<[f32; 3]>,
+}
+
+impl InputMeter {
+    pub fn new(channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        InputMeter { channels, history: vec![[0.0; 3]; channels] }
+    }
+
+    pub fn measure(&mut self, data: &[f32]) -> Levels {
+        let mut levels = Levels {
+            frames: (data.len() / self.channels) as u64,
+            samples: data.len() as u64,
+            ..Levels::default()
+        };
+
+        for frame in data.chunks(self.channels) {
+            for (history, &sample) in self.history.iter_mut().zip(frame) {
+                levels.sum_squares += (sample * sample) as f64;
+                levels.clipped |= sample.abs() >= CLIP_LEVEL;
+
+                // Catmull-Rom through the four latest samples, between the middle two
+                let [p0, p1, p2] = *history;
+                let p3 = sample;
+                let mut peak = p3.abs();
+                for step in 1..OVERSAMPLE {
+                    let t = step as f32 / OVERSAMPLE as f32;
+                    let value = 0.5 * (2.0 * p1
+                        + (p2 - p0) * t
+                        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+                        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t);
+                    peak = peak.max(value.abs());
+                }
+                levels.true_peak = levels.true_peak.max(peak);
+                *history = [p1, p2, p3];
+            }
+        }
+        levels
+    }
+}
+
+/// Something wrong with the input the user should know about while recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputWarning {
+    /// Nothing has come in for a while; the microphone is probably muted.
+    Silence,
+    /// The input keeps reaching full scale; the gain is too high.
+    Clipping,
+}
+
+impl InputWarning {
+    pub fn message(self) -> String {
+        match self {
+            InputWarning::Silence => format!(
+                "No sound from the microphone for {} seconds. Check that it isn't muted.",
+                SILENCE_WARNING_MS / 1000
+            ),
+            InputWarning::Clipping => "The microphone input is clipping. Turn its gain down to avoid distorted audio.".to_string(),
+        }
+    }
+}
+
+/// Watches measured input for silence and constant clipping.
+///
+/// Time is counted in audio received rather than on the clock, so nothing
+/// adds up while a recording is paused.
+pub struct InputWatch {
+    sample_rate: u64,
+    silent_frames: u64,
+    silence_warned: bool,
+    // Clipping is judged per second of audio
+    second_frames: u64,
+    second_clipped: bool,
+    clipping_seconds: u32,
+    clean_seconds: u32,
+    clipping_warned: bool,
+}
+
+impl InputWatch {
+    pub fn new(sample_rate: u32) -> Self {
+        InputWatch {
+            sample_rate: sample_rate.max(1) as u64,
+            silent_frames: 0,
+            silence_warned: false,
+            second_frames: 0,
+            second_clipped: false,
+            clipping_seconds: 0,
+            clean_seconds: 0,
+            clipping_warned: false,
+        }
+    }
+
+    /// Take in the next stretch of input. Returns the warnings that started
+    /// (`true`) or cleared (`false`) with it.
+    pub fn update(&mut self, levels: &Levels) -> Vec<(InputWarning, bool)> {
+        let mut changes = Vec::new();
+
+        if levels.rms_db() < SILENCE_DB {
+            self.silent_frames += levels.frames;
+            if !self.silence_warned && self.silent_frames >= self.sample_rate * SILENCE_WARNING_MS / 1000 {
+                self.silence_warned = true;
+                changes.push((InputWarning::Silence, true));
+            }
+        } else {
+            self.silent_frames = 0;
+            if self.silence_warned {
+                self.silence_warned = false;
+                changes.push((InputWarning::Silence, false));
+            }
+        }
+
+        self.second_frames += levels.frames;
+        self.second_clipped |= levels.clipped;
+        if self.second_frames >= self.sample_rate {
+            self.second_frames -= self.sample_rate;
+            if self.second_clipped {
+                self.clipping_seconds += 1;
+                self.clean_seconds = 0;
+            } else {
+                self.clean_seconds += 1;
+                self.clipping_seconds = 0;
+            }
+            self.second_clipped = false;
+
+            if !self.clipping_warned && self.clipping_seconds >= CLIPPING_SECONDS {
+                self.clipping_warned = true;
+                changes.push((InputWarning::Clipping, true));
+            } else if self.clipping_warned && self.clean_seconds >= CLIPPING_SECONDS {
+                self.clipping_warned = false;
+                changes.push((InputWarning::Clipping, false));
+            }
+        }
+
+        changes
+    }
+}
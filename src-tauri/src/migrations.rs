@@ -0,0 +1,228 @@
+use anyhow::{Result, anyhow};
+use nanoid::nanoid;
+use rusqlite::{Connection, Transaction};
+use std::collections::HashMap;
+
+/// One step in the life of the database schema.
+///
+/// Steps are numbered by their place in [`MIGRATIONS`], and the number of the
+/// last one applied is kept in `PRAGMA user_version`. Once released, a step
+/// must never change; fix mistakes with a new one.
+#[derive(Clone, Copy)]
+pub struct Migration {
+    pub description: &'static str,
+    pub apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { description: "Create the sessions table", apply: create_sessions },
+    Migration { description: "Flag recordings recovered after a crash", apply: add_recovered_flag },
+    Migration { description: "Drop the unused recording status", apply: drop_recording_status },
+    Migration { description: "Index transcripts and notes for search", apply: create_search_index },
+    Migration { description: "Turn course names into courses", apply: create_courses },
+    Migration { description: "Label sessions with tags", apply: create_tags },
+];
+
+/// Schema version a database is at once every migration has run.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+/// Bring the database up to date with [`MIGRATIONS`].
+pub fn migrate(conn: &mut Connection) -> Result<u32> {
+    run(conn, MIGRATIONS)
+}
+
+/// Apply the steps of `migrations` the database hasn't had yet, in order.
+/// Each runs in its own transaction together with the version bump, so a
+/// failure leaves the database at the last step that succeeded.
+pub fn run(conn: &mut Connection, migrations: &[Migration]) -> Result<u32> {
+    let current = schema_version(conn)?;
+    if current as usize > migrations.len() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than this version of Polka supports ({}). Please update the app.",
+            current, migrations.len()
+        ));
+    }
+
+    for (index, migration) in migrations.iter().enumerate().skip(current as usize) {
+        let version = index as u32 + 1;
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)
+            .map_err(|e| anyhow!("Migration {} ({}) failed: {}", version, migration.description, e))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        println!("🗄️ Migrated database to version {}: {}", version, migration.description);
+    }
+
+    schema_version(conn)
+}
+
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> rusqlite::Result<bool> {
+    tx.prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists((table, column))
+}
+
+// Databases from before versioning already have the table, in one of a few shapes
+fn create_sessions(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            course TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            duration_ms INTEGER DEFAULT 0,
+            status TEXT NOT NULL CHECK (status IN ('draft', 'complete', 'archived')),
+            notes_path TEXT,
+            audio_path TEXT,
+            transcript_path TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_recovered_flag(tx: &Transaction) -> rusqlite::Result<()> {
+    // Builds from before versioning added it on their own
+    if !has_column(tx, "sessions", "recovered")? {
+        tx.execute("ALTER TABLE sessions ADD COLUMN recovered INTEGER NOT NULL DEFAULT 0", [])?;
+    }
+    Ok(())
+}
+
+// Older builds widened the status check to a `recording` status the app never
+// used, and could leave the row they probed the constraint with behind
+fn drop_recording_status(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE sessions_new (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            course TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            duration_ms INTEGER DEFAULT 0,
+            status TEXT NOT NULL CHECK (status IN ('draft', 'complete', 'archived')),
+            notes_path TEXT,
+            audio_path TEXT,
+            transcript_path TEXT,
+            recovered INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    tx.execute(
+        "INSERT INTO sessions_new (id, title, course, created_at, duration_ms, status, notes_path, audio_path, transcript_path, recovered)
+         SELECT id, title, course, created_at, duration_ms,
+                CASE WHEN status IN ('draft', 'complete', 'archived') THEN status ELSE 'draft' END,
+                notes_path, audio_path, transcript_path, recovered
+         FROM sessions WHERE id != '__migration_test__'",
+        [],
+    )?;
+    tx.execute("DROP TABLE sessions", [])?;
+    tx.execute("ALTER TABLE sessions_new RENAME TO sessions", [])?;
+    Ok(())
+}
+
+// Searchable text lives in a plain table the FTS5 index reads from, so a
+// session's documents can be found by id; the triggers keep the two in step
+fn create_search_index(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE search_documents (
+            id INTEGER PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            kind TEXT NOT NULL CHECK (kind IN ('transcript', 'notes')),
+            t_ms INTEGER,
+            text TEXT NOT NULL
+        );
+        CREATE INDEX search_documents_session ON search_documents (session_id, kind);
+
+        CREATE VIRTUAL TABLE search_index USING fts5(
+            text,
+            content = 'search_documents',
+            content_rowid = 'id',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER search_documents_insert AFTER INSERT ON search_documents BEGIN
+            INSERT INTO search_index (rowid, text) VALUES (new.id, new.text);
+        END;
+        CREATE TRIGGER search_documents_delete AFTER DELETE ON search_documents BEGIN
+            INSERT INTO search_index (search_index, rowid, text) VALUES ('delete', old.id, old.text);
+        END;
+        CREATE TRIGGER search_documents_update AFTER UPDATE ON search_documents BEGIN
+            INSERT INTO search_index (search_index, rowid, text) VALUES ('delete', old.id, old.text);
+            INSERT INTO search_index (rowid, text) VALUES (new.id, new.text);
+        END;",
+    )
+}
+
+// Every spelling of a course name that only differs in case or surrounding
+// space becomes one course, named the way its earliest session had it
+fn create_courses(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE courses (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            code TEXT,
+            term TEXT,
+            color TEXT,
+            instructor TEXT,
+            created_at INTEGER NOT NULL
+        );
+        CREATE UNIQUE INDEX courses_name ON courses (name COLLATE NOCASE);
+
+        ALTER TABLE sessions ADD COLUMN course_id TEXT REFERENCES courses (id) ON DELETE SET NULL;
+        CREATE INDEX sessions_course ON sessions (course_id);",
+    )?;
+
+    let sessions: Vec<(String, String, i64)> = tx
+        .prepare("SELECT id, course, created_at FROM sessions ORDER BY created_at, id")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut courses: HashMap<String, (String, String)> = HashMap::new();
+    for (session_id, course, created_at) in sessions {
+        let name = course.trim();
+        if name.is_empty() {
+            continue;
+        }
+
+        let (course_id, name) = match courses.get(&name.to_lowercase()) {
+            Some(existing) => existing.clone(),
+            None => {
+                let course_id = nanoid!();
+                tx.execute(
+                    "INSERT INTO courses (id, name, created_at) VALUES (?1, ?2, ?3)",
+                    (&course_id, name, created_at),
+                )?;
+                courses.insert(name.to_lowercase(), (course_id.clone(), name.to_string()));
+                (course_id, name.to_string())
+            }
+        };
+        tx.execute(
+            "UPDATE sessions SET course_id = ?1, course = ?2 WHERE id = ?3",
+            (&course_id, &name, &session_id),
+        )?;
+    }
+    Ok(())
+}
+
+fn create_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE tags (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        CREATE UNIQUE INDEX tags_name ON tags (name COLLATE NOCASE);
+
+        CREATE TABLE session_tags (
+            session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+            tag_id TEXT NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+            PRIMARY KEY (session_id, tag_id)
+        );
+        CREATE INDEX session_tags_tag ON session_tags (tag_id);",
+    )
+}
@@ -5,13 +5,118 @@ use std::str::FromStr;
 pub struct Session {
     pub id: String,
     pub title: String,
+    /// Name of the session's course, kept in step with the course it references.
     pub course: String,
+    #[serde(default)]
+    pub course_id: Option<String>,
     pub created_at: i64,
     pub duration_ms: i64,
     pub status: SessionStatus,
     pub notes_path: Option<String>,
     pub audio_path: Option<String>,
     pub transcript_path: Option<String>,
+    /// The recording was interrupted and stitched back together on a later launch.
+    #[serde(default)]
+    pub recovered: bool,
+    /// Filled in when a session is read; tags are changed one at a time.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+/// A label any number of sessions can carry, like "exam-review" or "lab".
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub created_at: i64,
+}
+
+impl Tag {
+    /// Tag names are trimmed, with runs of whitespace inside them made single spaces.
+    pub fn normalize_name(name: &str) -> Result<String, String> {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.is_empty() {
+            return Err("Tag name cannot be empty".to_string());
+        }
+        Ok(name)
+    }
+}
+
+/// Which sessions to list. The default lists them all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionFilter {
+    /// Ids of tags the sessions must carry.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Carrying any one of `tags` is enough, rather than all of them.
+    #[serde(default)]
+    pub any_tag: bool,
+}
+
+/// A course sessions are filed under.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Course {
+    pub id: String,
+    pub name: String,
+    pub code: Option<String>,
+    pub term: Option<String>,
+    /// Colour the course is shown in, as `#rrggbb`.
+    pub color: Option<String>,
+    pub instructor: Option<String>,
+    pub created_at: i64,
+}
+
+/// What the user fills in to create or edit a course.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CourseInput {
+    pub name: String,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub term: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub instructor: Option<String>,
+}
+
+impl CourseInput {
+    /// Trim every field, turning blank optional ones into `None`, and check
+    /// the name is there and the colour is one the UI can show.
+    pub fn normalized(self) -> Result<Self, String> {
+        fn optional(value: Option<String>) -> Option<String> {
+            value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+        }
+        
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Course name cannot be empty".to_string());
+        }
+        
+        let color = optional(self.color);
+        if let Some(color) = &color {
+            let hex = color.strip_prefix('#').unwrap_or("");
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid colour: {}. Must look like #1a2b3c", color));
+            }
+        }
+        
+        Ok(CourseInput {
+            name,
+            code: optional(self.code),
+            term: optional(self.term),
+            color: color.map(|c| c.to_lowercase()),
+            instructor: optional(self.instructor),
+        })
+    }
+}
+
+/// How much has been recorded for a course.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CourseStats {
+    pub course_id: String,
+    pub session_count: i64,
+    pub total_duration_ms: i64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -74,4 +179,52 @@ pub struct TranscriptLine {
     pub t_ms: u64,
     pub speaker: String,
     pub text: String,
+    // Optional fields are omitted when unset so older transcript.jsonl files stay valid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<TranscriptWord>,
+}
+
+/// A single recognized word and where it sits in the recording.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TranscriptWord {
+    pub text: String,
+    pub t_ms: u64,
+    pub end_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+}
+
+/// Where a search hit was found.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSource {
+    Transcript,
+    Notes,
+}
+
+impl SearchSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchSource::Transcript => "transcript",
+            SearchSource::Notes => "notes",
+        }
+    }
+}
+
+/// A place in a session's transcript or notes that matches a search.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub source: SearchSource,
+    /// The text around the match.
+    pub snippet: String,
+    /// Matched ranges of `snippet` as `[start, end)` UTF-16 offsets, the way
+    /// JavaScript indexes strings.
+    pub highlights: Vec<[usize; 2]>,
+    /// Where the line starts in the recording, for transcript hits.
+    pub t_ms: Option<u64>,
 }
@@ -0,0 +1,516 @@
+use anyhow::{Result, anyhow};
+use audiopus::coder::{Decoder, Encoder};
+use audiopus::packet::Packet;
+use audiopus::{Application, Bitrate, Channels, MutSignals, SampleRate};
+use ogg::reading::PacketReader;
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::resample::Resampler;
+
+// Opus always runs at 48kHz internally and Ogg Opus granule positions count 48kHz samples
+const OPUS_RATE: u32 = 48000;
+// 20ms frames, the usual choice for speech
+const FRAME_SAMPLES: usize = 960;
+// Largest packet libopus can produce
+const MAX_PACKET: usize = 4000;
+// Longest frame a packet can decode to (120ms)
+const MAX_FRAME_SAMPLES: usize = 5760;
+// Plenty for intelligible lecture speech while staying tiny
+const BITRATE_PER_CHANNEL: i32 = 32_000;
+// Arbitrary, each file holds a single stream
+const STREAM_SERIAL: u32 = 0x706f_6c6b;
+// Audio decoded before a seek target and thrown away, so the decoder has
+// settled by the time it gets there (RFC 7845 recommends 80ms)
+const PRE_ROLL: u64 = 3840;
+// Seeking stops bisecting and decodes on once it is this close
+const LINEAR_SEEK_BYTES: u64 = 16 * 1024;
+// Frames resampled before a seek target, more than the resampler's filter reaches back
+const RESAMPLER_LEAD: u64 = 64;
+// Bytes read at a time while looking for a page
+const SCAN_BYTES: usize = 16 * 1024;
+
+fn opus_channels(channels: u16) -> Result<Channels> {
+    match channels {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        other => Err(anyhow!("Opus files can only be mono or stereo, not {} channels", other)),
+    }
+}
+
+/// Encoder writing an Ogg Opus file (RFC 7845) from interleaved f32 samples.
+///
+/// Input at any sample rate is resampled to 48kHz. The original rate is kept
+/// in the header, and [`OpusSamples`] converts back to it when reading.
+pub struct OpusWriter<W: Write> {
+    packets: PacketWriter<W>,
+    encoder: Encoder,
+    resampler: Resampler,
+    channels: usize,
+    pre_skip: u64,
+    // 48kHz samples per channel waiting for a full frame
+    pending: Vec<f32>,
+    input_samples: u64,
+    encoded_samples: u64,
+    // Frames are written one behind so the last one can end the stream
+    previous_packet: Option<Vec<u8>>,
+}
+
+impl OpusWriter<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16) -> Result<Self> {
+        OpusWriter::new(BufWriter::new(File::create(path)?), sample_rate, channels)
+    }
+}
+
+impl<W: Write> OpusWriter<W> {
+    pub fn new(writer: W, sample_rate: u32, channels: u16) -> Result<Self> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, opus_channels(channels)?, Application::Voip)
+            .map_err(|e| anyhow!("Failed to create Opus encoder: {}", e))?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(BITRATE_PER_CHANNEL * channels as i32))
+            .map_err(|e| anyhow!("Failed to set Opus bitrate: {}", e))?;
+        // Samples of encoder delay the decoder has to drop
+        let pre_skip = encoder.lookahead()
+            .map_err(|e| anyhow!("Failed to read Opus encoder delay: {}", e))? as u64;
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1);
+        head.push(channels as u8);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+
+        let vendor = b"polka";
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend_from_slice(&0u32.to_le_bytes());
+
+        // Both headers sit on pages of their own
+        let mut packets = PacketWriter::new(writer);
+        packets.write_packet(head.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+        packets.write_packet(tags.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(OpusWriter {
+            packets,
+            encoder,
+            resampler: Resampler::new(sample_rate, OPUS_RATE, channels),
+            channels: channels as usize,
+            pre_skip,
+            pending: Vec::new(),
+            input_samples: 0,
+            encoded_samples: 0,
+            previous_packet: None,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        let resampled = self.resampler.process(samples);
+        self.push(&resampled)
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Result<()> {
+        self.input_samples += (samples.len() / self.channels) as u64;
+        self.pending.extend_from_slice(samples);
+        let frame_len = FRAME_SAMPLES * self.channels;
+        while self.pending.len() >= frame_len {
+            let frame: Vec<f32> = self.pending.drain(..frame_len).collect();
+            self.encode_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn encode_frame(&mut self, frame: &[f32]) -> Result<()> {
+        let mut packet = vec![0u8; MAX_PACKET];
+        let len = self.encoder.encode_float(frame, &mut packet)
+            .map_err(|e| anyhow!("Opus encoding failed: {}", e))?;
+        packet.truncate(len);
+
+        // The previous packet ends where this one starts
+        if let Some(previous) = self.previous_packet.replace(packet) {
+            self.packets.write_packet(previous.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::NormalPacket, self.encoded_samples)?;
+        }
+        self.encoded_samples += FRAME_SAMPLES as u64;
+        Ok(())
+    }
+
+    /// Encode what is left, including the encoder delay, and end the stream.
+    pub fn finalize(mut self) -> Result<W> {
+        let tail = self.resampler.flush();
+        self.push(&tail)?;
+
+        // Silence pushes the last real samples through the encoder's lookahead
+        let audio_samples = self.input_samples;
+        let end = audio_samples + self.pre_skip;
+        while self.encoded_samples < end || self.previous_packet.is_none() {
+            let needed = FRAME_SAMPLES * self.channels - self.pending.len();
+            let silence = vec![0.0; needed];
+            self.pending.extend_from_slice(&silence);
+            let frame: Vec<f32> = self.pending.drain(..).collect();
+            self.encode_frame(&frame)?;
+        }
+
+        // The last page's granule position marks where the audio really ends
+        let last = self.previous_packet.take().unwrap_or_default();
+        self.packets.write_packet(last.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndStream, end)?;
+
+        let mut writer = self.packets.into_inner();
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+struct OpusHead {
+    channels: u16,
+    pre_skip: u64,
+    input_sample_rate: u32,
+}
+
+fn parse_head(data: &[u8]) -> Result<OpusHead> {
+    if data.len() < 19 || &data[..8] != b"OpusHead" {
+        return Err(anyhow!("Not an Ogg Opus file"));
+    }
+    let channels = data[9] as u16;
+    let pre_skip = u16::from_le_bytes([data[10], data[11]]) as u64;
+    let input_sample_rate = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+    Ok(OpusHead {
+        channels,
+        pre_skip,
+        // Zero means the original rate is unknown
+        input_sample_rate: if input_sample_rate == 0 { OPUS_RATE } else { input_sample_rate },
+    })
+}
+
+/// Sample rate, channels and length in frames (at the sample rate) of an Ogg Opus file.
+pub fn read_info(path: &Path) -> Result<(u32, u16, u64)> {
+    let mut file = File::open(path)?;
+    let head = PacketReader::new(BufReader::new(&mut file)).read_packet()?
+        .ok_or_else(|| anyhow!("Empty Opus file {:?}", path))
+        .and_then(|packet| parse_head(&packet.data))?;
+
+    let granule = last_granule_position(&mut file)?;
+    let frames_48k = granule.saturating_sub(head.pre_skip);
+    let frames = frames_48k * head.input_sample_rate as u64 / OPUS_RATE as u64;
+    Ok((head.input_sample_rate, head.channels, frames))
+}
+
+// Granule position of the last page, found without decoding anything
+fn last_granule_position(file: &mut File) -> Result<u64> {
+    // A page is at most 64KB, so the last one starts in the final 64KB
+    let len = file.seek(SeekFrom::End(0))?;
+    let start = len.saturating_sub(65_536 + 27);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    let page = tail.windows(4).rposition(|window| window == b"OggS")
+        .filter(|&position| position + 14 <= tail.len())
+        .ok_or_else(|| anyhow!("No Ogg page found"))?;
+    let mut granule = [0u8; 8];
+    granule.copy_from_slice(&tail[page + 6..page + 14]);
+    Ok(u64::from_le_bytes(granule))
+}
+
+/// Samples of an Ogg Opus file as interleaved f32, at the file's original sample rate.
+pub struct OpusSamples {
+    path: PathBuf,
+    packets: PacketReader<BufReader<File>>,
+    // Read ahead while finding out where a seek landed
+    queued: VecDeque<ogg::Packet>,
+    decoder: Decoder,
+    resampler: Resampler,
+    sample_rate: u32,
+    channels: usize,
+    pre_skip: u64,
+    serial: u32,
+    // Where the first audio page starts, after the headers
+    audio_offset: u64,
+    file_len: u64,
+    // Granule position of the first sample to keep: the pre-skip, or where a seek went
+    skip_until: u64,
+    // Resampled samples still to be dropped on the way to a seek target
+    discard: usize,
+    // Granule position the next packet starts at
+    decoded_frames: u64,
+    decoded: Vec<f32>,
+    output: Vec<f32>,
+    position: usize,
+    done: bool,
+}
+
+impl OpusSamples {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut packets = PacketReader::new(BufReader::new(File::open(path)?));
+        let head = packets.read_packet()?
+            .ok_or_else(|| anyhow!("Empty Opus file {:?}", path))
+            .and_then(|packet| parse_head(&packet.data))?;
+        // Comments are of no use here
+        packets.read_packet()?;
+
+        let decoder = Decoder::new(SampleRate::Hz48000, opus_channels(head.channels)?)
+            .map_err(|e| anyhow!("Failed to create Opus decoder: {}", e))?;
+
+        // The header pages are the ones before the first with a granule position
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut serial = STREAM_SERIAL;
+        let mut audio_offset = 0;
+        while let Some(page) = read_page(&mut file, audio_offset)? {
+            if audio_offset == 0 {
+                serial = page.serial;
+            } else if page.granule != 0 && page.granule != u64::MAX {
+                break;
+            }
+            audio_offset += page.len;
+        }
+
+        Ok(OpusSamples {
+            path: path.to_path_buf(),
+            packets,
+            queued: VecDeque::new(),
+            decoder,
+            resampler: Resampler::new(OPUS_RATE, head.input_sample_rate, head.channels),
+            sample_rate: head.input_sample_rate,
+            channels: head.channels as usize,
+            pre_skip: head.pre_skip,
+            serial,
+            audio_offset,
+            file_len,
+            skip_until: head.pre_skip,
+            discard: 0,
+            decoded_frames: 0,
+            decoded: vec![0.0; MAX_FRAME_SAMPLES * head.channels as usize],
+            output: Vec::new(),
+            position: 0,
+            done: false,
+        })
+    }
+
+    /// Continue from `frame`, at the file's sample rate. Decoding restarts on
+    /// a page found by bisecting the file on granule positions, a little
+    /// before `frame` so the decoder has settled by the time it gets there.
+    pub fn seek(&mut self, frame: u64) -> Result<()> {
+        // The resampler starts a little early so its filter is full by
+        // `frame`, on a frame that lines up exactly with a 48kHz sample
+        let rate = self.sample_rate as u64;
+        let step = rate / gcd(rate, OPUS_RATE as u64);
+        let start_frame = frame.saturating_sub(RESAMPLER_LEAD) / step * step;
+        let target = start_frame * OPUS_RATE as u64 / rate + self.pre_skip;
+        // A packet continued from before the page is dropped, so allow for the longest one
+        let (offset, granule) = self.find_page_before(target.saturating_sub(PRE_ROLL + MAX_FRAME_SAMPLES as u64))?;
+        self.packets.seek_bytes(SeekFrom::Start(offset))?;
+        self.decoder = Decoder::new(SampleRate::Hz48000, opus_channels(self.channels as u16)?)
+            .map_err(|e| anyhow!("Failed to create Opus decoder: {}", e))?;
+        self.resampler = Resampler::new(OPUS_RATE, self.sample_rate, self.channels as u16);
+        self.skip_until = target;
+        self.discard = (frame - start_frame) as usize * self.channels;
+        self.output.clear();
+        self.position = 0;
+        self.done = false;
+
+        // Only the page a packet ends on says where it is, so read to the end
+        // of that page and count back
+        self.queued.clear();
+        while let Some(packet) = self.packets.read_packet()? {
+            let last_in_page = packet.last_in_page();
+            self.queued.push_back(packet);
+            if last_in_page {
+                break;
+            }
+        }
+        self.decoded_frames = match self.queued.back() {
+            // The last page says where the audio ends rather than where its packets do
+            Some(last) if !last.last_in_stream() => {
+                let mut start = last.absgp_page();
+                for packet in &self.queued {
+                    start = start.saturating_sub(self.packet_frames(packet)? as u64);
+                }
+                start
+            }
+            _ => granule,
+        };
+        Ok(())
+    }
+
+    // Where to start reading so that the first packet starts at or before the
+    // granule position `goal`, and the granule position there
+    fn find_page_before(&self, goal: u64) -> Result<(u64, u64)> {
+        let mut file = File::open(&self.path)?;
+        let (mut low, mut low_granule) = (self.audio_offset, 0);
+        let mut high = self.file_len;
+        while high.saturating_sub(low) > LINEAR_SEEK_BYTES {
+            let middle = low + (high - low) / 2;
+            match find_page(&mut file, middle, high, self.serial)? {
+                Some(page) if page.granule <= goal => (low, low_granule) = (page.offset + page.len, page.granule),
+                _ => high = middle,
+            }
+        }
+        Ok((low, low_granule))
+    }
+
+    fn packet_frames(&self, packet: &ogg::Packet) -> Result<usize> {
+        let input = Packet::try_from(&packet.data[..])
+            .map_err(|e| anyhow!("Invalid Opus packet: {}", e))?;
+        self.decoder.nb_samples(input)
+            .map_err(|e| anyhow!("Invalid Opus packet: {}", e))
+    }
+
+    // Decode the next packet into `output`; false at the end of the stream
+    fn decode_next(&mut self) -> Result<bool> {
+        let packet = match self.queued.pop_front() {
+            Some(packet) => packet,
+            None => match self.packets.read_packet()? {
+                Some(packet) => packet,
+                None => return Ok(false),
+            },
+        };
+
+        let input = Packet::try_from(&packet.data[..])
+            .map_err(|e| anyhow!("Invalid Opus packet: {}", e))?;
+        let signals = MutSignals::try_from(&mut self.decoded[..])
+            .map_err(|e| anyhow!("Invalid Opus buffer: {}", e))?;
+        let frames = self.decoder.decode_float(Some(input), signals, false)
+            .map_err(|e| anyhow!("Opus decoding failed: {}", e))?;
+
+        let packet_start = self.decoded_frames;
+        self.decoded_frames += frames as u64;
+
+        // Drop the encoder delay at the start, or what comes before a seek
+        // target, and going by the last page's granule position the padding
+        // at the end
+        let start = self.skip_until.saturating_sub(packet_start).min(frames as u64) as usize;
+        let mut end = frames;
+        if packet.last_in_stream() {
+            end = end.min(packet.absgp_page().saturating_sub(packet_start) as usize);
+        }
+        let end = end.max(start);
+
+        self.output = self.resampler.process(&self.decoded[start * self.channels..end * self.channels]);
+        if packet.last_in_stream() {
+            self.output.extend(self.resampler.flush());
+        }
+        let discard = self.discard.min(self.output.len());
+        self.output.drain(..discard);
+        self.discard -= discard;
+        Ok(true)
+    }
+}
+
+impl Iterator for OpusSamples {
+    type Item = Result<f32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position >= self.output.len() {
+            if self.done {
+                return None;
+            }
+            self.position = 0;
+            self.output.clear();
+            match self.decode_next() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        let sample = self.output[self.position];
+        self.position += 1;
+        Some(Ok(sample))
+    }
+}
+
+// An Ogg page as found by seeking
+struct Page {
+    offset: u64,
+    len: u64,
+    granule: u64,
+    serial: u32,
+}
+
+// The page starting at `offset`, if there is one and its checksum matches
+fn read_page(file: &mut File, offset: u64) -> Result<Option<Page>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut page = Vec::with_capacity(27 + 255);
+    (&mut *file).take(27).read_to_end(&mut page)?;
+    if page.len() < 27 || &page[..4] != b"OggS" || page[4] != 0 {
+        return Ok(None);
+    }
+    let segments = page[26] as usize;
+    (&mut *file).take(segments as u64).read_to_end(&mut page)?;
+    if page.len() < 27 + segments {
+        return Ok(None);
+    }
+    let body_len: u64 = page[27..].iter().map(|&len| len as u64).sum();
+    (&mut *file).take(body_len).read_to_end(&mut page)?;
+    if page.len() as u64 != 27 + segments as u64 + body_len {
+        return Ok(None);
+    }
+
+    let checksum = u32::from_le_bytes([page[22], page[23], page[24], page[25]]);
+    page[22..26].fill(0);
+    if ogg_crc(&page) != checksum {
+        return Ok(None);
+    }
+    let mut granule = [0u8; 8];
+    granule.copy_from_slice(&page[6..14]);
+    Ok(Some(Page {
+        offset,
+        len: page.len() as u64,
+        granule: u64::from_le_bytes(granule),
+        serial: u32::from_le_bytes([page[14], page[15], page[16], page[17]]),
+    }))
+}
+
+// The first page of stream `serial` starting between `from` and `to` that
+// ends a packet, so has a granule position
+fn find_page(file: &mut File, from: u64, to: u64, serial: u32) -> Result<Option<Page>> {
+    let mut start = from;
+    let mut window = Vec::with_capacity(SCAN_BYTES + 3);
+    while start < to {
+        file.seek(SeekFrom::Start(start))?;
+        window.clear();
+        // A capture pattern starting near the end of the window runs on past it
+        (&mut *file).take(SCAN_BYTES as u64 + 3).read_to_end(&mut window)?;
+        let candidates = window.len().saturating_sub(3).min(SCAN_BYTES).min((to - start) as usize);
+        for i in 0..candidates {
+            if &window[i..i + 4] != b"OggS" {
+                continue;
+            }
+            if let Some(page) = read_page(file, start + i as u64)? {
+                if page.serial == serial && page.granule != u64::MAX {
+                    return Ok(Some(page));
+                }
+            }
+        }
+        if window.len() < SCAN_BYTES + 3 {
+            break;
+        }
+        start += SCAN_BYTES as u64;
+    }
+    Ok(None)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+    crc
+}
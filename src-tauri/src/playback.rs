@@ -0,0 +1,549 @@
+use anyhow::{Result, anyhow};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use crate::codec::{AudioInfo, AudioReader};
+use crate::models::TranscriptLine;
+use crate::resample::{Resampler, remap_channels};
+use crate::timestretch::{TimeStretcher, MIN_RATE, MAX_RATE};
+
+// How far decoding runs ahead of what the device is playing
+const BUFFER_MS: u64 = 250;
+// Recording frames decoded at a time
+const DECODE_FRAMES: usize = 2048;
+// How often the playback thread tops the buffer up while playing
+const FILL_INTERVAL: Duration = Duration::from_millis(20);
+// How often `polka://playback-position` is sent while playing
+const POSITION_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where playback is, as sent with `polka://playback-position`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaybackState {
+    pub session_id: String,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub rate: f32,
+    pub is_playing: bool,
+    /// Playback ran to the end of the recording.
+    pub ended: bool,
+    /// Index in the session's transcript of the line being heard.
+    pub active_line: Option<usize>,
+}
+
+// Audio ready for the device and the part of the recording it was made from
+struct Chunk {
+    samples: Vec<f32>,
+    read: usize,
+    source_start: u64,
+    source_frames: u64,
+}
+
+// What the playback thread and the device callback share
+struct Shared {
+    chunks: Mutex<VecDeque<Chunk>>,
+    queued_samples: AtomicU64,
+    // Recording frame of the audio coming out of the speakers right now
+    position: AtomicU64,
+    // The device went away
+    failed: AtomicBool,
+}
+
+impl Shared {
+    fn clear(&self, position: u64) {
+        self.chunks.lock().unwrap().clear();
+        self.queued_samples.store(0, Ordering::Relaxed);
+        self.position.store(position, Ordering::Relaxed);
+    }
+
+    // Copy queued audio into the device buffer and move the position along;
+    // returns how many samples were filled
+    fn take(&self, data: &mut [f32]) -> usize {
+        let Ok(mut chunks) = self.chunks.lock() else {
+            return 0;
+        };
+        let mut written = 0;
+        while written < data.len() {
+            let Some(chunk) = chunks.front_mut() else {
+                break;
+            };
+            let n = (chunk.samples.len() - chunk.read).min(data.len() - written);
+            data[written..written + n].copy_from_slice(&chunk.samples[chunk.read..chunk.read + n]);
+            chunk.read += n;
+            written += n;
+
+            // Output and recording frames line up evenly within a chunk
+            let played = chunk.source_frames * chunk.read as u64 / chunk.samples.len() as u64;
+            self.position.store(chunk.source_start + played, Ordering::Relaxed);
+            if chunk.read == chunk.samples.len() {
+                chunks.pop_front();
+            }
+        }
+        self.queued_samples.fetch_sub(written as u64, Ordering::Relaxed);
+        written
+    }
+}
+
+// A recording being played, owned by the playback thread
+struct Player {
+    session_id: String,
+    path: PathBuf,
+    info: AudioInfo,
+    reader: AudioReader,
+    // Lines of the session's transcript as they were when playback started
+    transcript: Vec<TranscriptLine>,
+    // Recording frame the reader is at
+    decode_position: u64,
+    decoded_all: bool,
+    // Decoded audio from recording frame `source_start` up to the reader,
+    // kept until it is heard so a seek or speed change can make it again
+    source: VecDeque<f32>,
+    source_start: u64,
+    stretcher: TimeStretcher,
+    resampler: Resampler,
+    device_rate: u32,
+    device_channels: u16,
+    shared: Arc<Shared>,
+    stream: cpal::Stream,
+    playing: bool,
+    app_handle: AppHandle,
+}
+
+impl Player {
+    fn open(session_id: String, path: &Path, transcript: Vec<TranscriptLine>, start_ms: u64, rate: f32, app_handle: AppHandle) -> Result<Self> {
+        let info = crate::codec::audio_info(path)?;
+
+        let host = cpal::default_host();
+        let device = host.default_output_device()
+            .ok_or_else(|| anyhow!("No audio output device available"))?;
+        let supported_config = device.default_output_config()
+            .map_err(|e| anyhow!("Failed to get output config: {}", e))?;
+        let config = supported_config.config();
+        println!("🔊 Playing {:?} on {} at {}Hz x{} {:?}",
+                path, device.name().unwrap_or_default(), config.sample_rate.0, config.channels, supported_config.sample_format());
+
+        let start_frame = (start_ms * info.sample_rate as u64 / 1000).min(info.frames);
+        let shared = Arc::new(Shared {
+            chunks: Mutex::new(VecDeque::new()),
+            queued_samples: AtomicU64::new(0),
+            position: AtomicU64::new(start_frame),
+            failed: AtomicBool::new(false),
+        });
+
+        // Devices take whatever sample type they were made for
+        let stream = match supported_config.sample_format() {
+            cpal::SampleFormat::F32 => build_output_stream::<f32>(&device, &config, &shared),
+            cpal::SampleFormat::F64 => build_output_stream::<f64>(&device, &config, &shared),
+            cpal::SampleFormat::I8 => build_output_stream::<i8>(&device, &config, &shared),
+            cpal::SampleFormat::I16 => build_output_stream::<i16>(&device, &config, &shared),
+            cpal::SampleFormat::I32 => build_output_stream::<i32>(&device, &config, &shared),
+            cpal::SampleFormat::I64 => build_output_stream::<i64>(&device, &config, &shared),
+            cpal::SampleFormat::U8 => build_output_stream::<u8>(&device, &config, &shared),
+            cpal::SampleFormat::U16 => build_output_stream::<u16>(&device, &config, &shared),
+            cpal::SampleFormat::U32 => build_output_stream::<u32>(&device, &config, &shared),
+            cpal::SampleFormat::U64 => build_output_stream::<u64>(&device, &config, &shared),
+            format => Err(anyhow!("Unsupported output sample format {:?}", format)),
+        }?;
+
+        let mut player = Player {
+            session_id,
+            path: path.to_path_buf(),
+            info,
+            reader: AudioReader::open_at(path, start_frame)?,
+            transcript,
+            decode_position: start_frame,
+            decoded_all: false,
+            source: VecDeque::new(),
+            source_start: start_frame,
+            stretcher: TimeStretcher::new(info.sample_rate, info.channels, rate),
+            resampler: Resampler::new(info.sample_rate, config.sample_rate.0, config.channels),
+            device_rate: config.sample_rate.0,
+            device_channels: config.channels,
+            shared,
+            stream,
+            playing: false,
+            app_handle,
+        };
+        player.play()?;
+        Ok(player)
+    }
+
+    fn play(&mut self) -> Result<()> {
+        // Playing again after the end starts over
+        if self.is_finished() {
+            self.seek(0)?;
+        }
+        self.fill()?;
+        self.stream.play()?;
+        self.playing = true;
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<()> {
+        self.stream.pause()?;
+        self.playing = false;
+        Ok(())
+    }
+
+    fn seek(&mut self, position_ms: u64) -> Result<()> {
+        let frame = (position_ms * self.info.sample_rate as u64 / 1000).min(self.info.frames);
+        // Audio decoded already is used again; anything else continues the open reader
+        if !(self.source_start..=self.decode_position).contains(&frame) {
+            self.reader.seek(frame)?;
+            self.source.clear();
+            self.source_start = frame;
+            self.decode_position = frame;
+            self.decoded_all = false;
+        }
+        self.requeue(frame, self.stretcher.rate());
+        Ok(())
+    }
+
+    fn set_rate(&mut self, rate: f32) -> Result<()> {
+        // Start the new speed from what is audible now; what was decoded
+        // after that is stretched again rather than decoded again
+        let frame = self.shared.position.load(Ordering::Relaxed).clamp(self.source_start, self.decode_position);
+        self.requeue(frame, rate);
+        Ok(())
+    }
+
+    // Replace what is queued for the device with the decoded audio from
+    // `frame` on, played at `rate`
+    fn requeue(&mut self, frame: u64, rate: f32) {
+        // Nothing from before may bleed into the audio after it
+        self.stretcher = TimeStretcher::new(self.info.sample_rate, self.info.channels, rate);
+        self.resampler = Resampler::new(self.info.sample_rate, self.device_rate, self.device_channels);
+        self.shared.clear(frame);
+
+        self.drop_source_before(frame);
+        let samples: Vec<f32> = self.source.iter().copied().collect();
+        self.queue(&samples, self.source_start, self.decoded_all);
+    }
+
+    fn drop_source_before(&mut self, frame: u64) {
+        let channels = self.info.channels as u64;
+        let frames = frame.saturating_sub(self.source_start).min((self.source.len() as u64) / channels);
+        self.source.drain(..(frames * channels) as usize);
+        self.source_start += frames;
+    }
+
+    // Stretch and convert recording audio starting at `source_start` for the
+    // device; `end` when nothing comes after it
+    fn queue(&mut self, samples: &[f32], source_start: u64, end: bool) {
+        let stretched = self.stretcher.process(samples);
+        let mut output = self.resampler.process(&remap_channels(&stretched, self.info.channels, self.device_channels));
+        if end {
+            let tail = remap_channels(&self.stretcher.flush(), self.info.channels, self.device_channels);
+            output.extend(self.resampler.process(&tail));
+            output.extend(self.resampler.flush());
+        }
+
+        if !output.is_empty() {
+            self.shared.queued_samples.fetch_add(output.len() as u64, Ordering::Relaxed);
+            self.shared.chunks.lock().unwrap().push_back(Chunk {
+                samples: output,
+                read: 0,
+                source_start,
+                source_frames: (samples.len() / self.info.channels as usize) as u64,
+            });
+        }
+    }
+
+    // Decode, stretch and convert until the buffer holds `BUFFER_MS` of audio
+    fn fill(&mut self) -> Result<()> {
+        // What has been heard can't be needed again
+        self.drop_source_before(self.shared.position.load(Ordering::Relaxed));
+
+        let target = BUFFER_MS * self.device_rate as u64 / 1000 * self.device_channels as u64;
+        let channels = self.info.channels as usize;
+        while !self.decoded_all && self.shared.queued_samples.load(Ordering::Relaxed) < target {
+            let mut samples = Vec::with_capacity(DECODE_FRAMES * channels);
+            for sample in self.reader.by_ref().take(DECODE_FRAMES * channels) {
+                samples.push(sample?);
+            }
+            let frames = (samples.len() / channels) as u64;
+
+            self.decoded_all = samples.is_empty();
+            self.source.extend(&samples);
+            self.queue(&samples, self.decode_position, self.decoded_all);
+            self.decode_position += frames;
+        }
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.decoded_all && self.shared.queued_samples.load(Ordering::Relaxed) == 0
+    }
+
+    fn position_ms(&self) -> u64 {
+        let frame = if self.is_finished() { self.info.frames } else { self.shared.position.load(Ordering::Relaxed) };
+        frame * 1000 / self.info.sample_rate.max(1) as u64
+    }
+
+    fn state(&self) -> PlaybackState {
+        let position_ms = self.position_ms();
+        PlaybackState {
+            session_id: self.session_id.clone(),
+            position_ms,
+            duration_ms: self.info.duration_ms(),
+            rate: self.stretcher.rate(),
+            is_playing: self.playing,
+            ended: self.is_finished(),
+            active_line: crate::transcript::line_at(&self.transcript, position_ms),
+        }
+    }
+
+    fn report(&self) -> PlaybackState {
+        let state = self.state();
+        let _ = self.app_handle.emit("polka://playback-position", &state);
+        state
+    }
+}
+
+type Reply = Sender<Result<PlaybackState>>;
+
+enum PlaybackCommand {
+    Play { session_id: String, path: PathBuf, transcript: Vec<TranscriptLine>, start_ms: u64, app_handle: AppHandle, reply: Reply },
+    Pause(Reply),
+    Resume(Reply),
+    Seek(u64, Reply),
+    SetRate(f32, Reply),
+    Stop(Reply),
+    State(Reply),
+}
+
+// Open an output stream on `device` that plays what is queued in `shared`,
+// converted to the device's sample type
+fn build_output_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, shared: &Arc<Shared>) -> Result<cpal::Stream>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let callback_shared = Arc::clone(shared);
+    let error_shared = Arc::clone(shared);
+    let mut buffer = Vec::new();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            buffer.resize(data.len(), 0.0);
+            let written = callback_shared.take(&mut buffer);
+            // Silence when decoding falls behind or the recording is over
+            buffer[written..].fill(0.0);
+            for (out, &sample) in data.iter_mut().zip(&buffer) {
+                *out = T::from_sample(sample);
+            }
+        },
+        move |err| {
+            eprintln!("🔊 Audio output error: {}", err);
+            if let cpal::StreamError::DeviceNotAvailable = err {
+                error_shared.failed.store(true, Ordering::Relaxed);
+            }
+        },
+        None
+    )?;
+    Ok(stream)
+}
+
+// Output streams can't move between threads on every platform, so one
+// thread owns the player and keeps it fed
+static PLAYBACK_COMMANDS: Lazy<Mutex<Sender<PlaybackCommand>>> = Lazy::new(|| {
+    let (commands_tx, commands_rx) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("polka-playback".to_string())
+        .spawn(move || run_playback_thread(commands_rx))
+        .expect("Failed to start playback thread");
+    Mutex::new(commands_tx)
+});
+
+fn run_playback_thread(commands: Receiver<PlaybackCommand>) {
+    let mut player: Option<Player> = None;
+    // The last speed picked carries over to the next recording played
+    let mut rate = 1.0;
+    let mut last_report = Instant::now();
+
+    loop {
+        let playing = player.as_ref().is_some_and(|player| player.playing);
+        let command = if playing {
+            match commands.recv_timeout(FILL_INTERVAL) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            }
+        };
+
+        if let Some(command) = command {
+            handle_command(command, &mut player, &mut rate);
+        }
+
+        let Some(current) = player.as_mut() else {
+            continue;
+        };
+        if !current.playing {
+            continue;
+        }
+        if current.shared.failed.load(Ordering::Relaxed) {
+            eprintln!("🔊 Audio output device went away, stopping playback");
+            current.playing = false;
+            current.report();
+            player = None;
+            continue;
+        }
+        if let Err(e) = current.fill() {
+            eprintln!("🔊 Failed to decode {:?}: {}", current.path, e);
+            let _ = current.pause();
+            current.report();
+            continue;
+        }
+        if current.is_finished() {
+            let _ = current.pause();
+            current.report();
+            println!("🔊 Playback finished");
+        } else if last_report.elapsed() >= POSITION_INTERVAL {
+            current.report();
+            last_report = Instant::now();
+        }
+    }
+}
+
+fn handle_command(command: PlaybackCommand, player: &mut Option<Player>, rate: &mut f32) {
+    let nothing_playing = || anyhow!("Nothing is playing");
+    match command {
+        PlaybackCommand::Play { session_id, path, transcript, start_ms, app_handle, reply } => {
+            // Jumping around the recording that is open seeks its reader
+            if let Some(current) = player.as_mut().filter(|current| current.session_id == session_id && current.path == path) {
+                current.transcript = transcript;
+                let result = current.seek(start_ms)
+                    .and_then(|_| current.play())
+                    .map(|_| current.report());
+                let _ = reply.send(result);
+                return;
+            }
+
+            // Close the previous recording's stream first
+            *player = None;
+            let result = Player::open(session_id, &path, transcript, start_ms, *rate, app_handle)
+                .map(|opened| player.insert(opened).report());
+            let _ = reply.send(result);
+        }
+        PlaybackCommand::Pause(reply) => {
+            let result = match player.as_mut() {
+                Some(current) => current.pause().map(|_| current.report()),
+                None => Err(nothing_playing()),
+            };
+            let _ = reply.send(result);
+        }
+        PlaybackCommand::Resume(reply) => {
+            let result = match player.as_mut() {
+                Some(current) => current.play().map(|_| current.report()),
+                None => Err(nothing_playing()),
+            };
+            let _ = reply.send(result);
+        }
+        PlaybackCommand::Seek(position_ms, reply) => {
+            let result = match player.as_mut() {
+                Some(current) => current.seek(position_ms)
+                    .and_then(|_| if current.playing { current.fill() } else { Ok(()) })
+                    .map(|_| current.report()),
+                None => Err(nothing_playing()),
+            };
+            let _ = reply.send(result);
+        }
+        PlaybackCommand::SetRate(new_rate, reply) => {
+            *rate = new_rate.clamp(MIN_RATE, MAX_RATE);
+            let result = match player.as_mut() {
+                Some(current) => current.set_rate(*rate)
+                    .and_then(|_| if current.playing { current.fill() } else { Ok(()) })
+                    .map(|_| current.report()),
+                None => Err(nothing_playing()),
+            };
+            let _ = reply.send(result);
+        }
+        PlaybackCommand::Stop(reply) => {
+            let result = match player.take() {
+                Some(mut current) => {
+                    current.playing = false;
+                    Ok(current.report())
+                }
+                None => Err(nothing_playing()),
+            };
+            let _ = reply.send(result);
+        }
+        PlaybackCommand::State(reply) => {
+            let _ = reply.send(player.as_ref().map(|current| current.state()).ok_or_else(nothing_playing));
+        }
+    }
+}
+
+// Run a command on the playback thread and wait for its answer
+fn send_command(command: impl FnOnce(Reply) -> PlaybackCommand) -> Result<PlaybackState> {
+    let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+    PLAYBACK_COMMANDS.lock().unwrap().send(command(reply_tx))
+        .map_err(|_| anyhow!("Playback thread is not running"))?;
+    reply_rx.recv().map_err(|_| anyhow!("Playback thread is not running"))?
+}
+
+/// Play a session's recording from `start_ms`, replacing whatever was playing.
+/// If that recording is already open it seeks there instead of opening it again.
+/// `transcript` is what [`PlaybackState::active_line`] points into.
+pub fn play(
+    session_id: &str,
+    path: &Path,
+    transcript: Vec<TranscriptLine>,
+    start_ms: u64,
+    app_handle: AppHandle,
+) -> Result<PlaybackState> {
+    send_command(|reply| PlaybackCommand::Play {
+        session_id: session_id.to_string(),
+        path: path.to_path_buf(),
+        transcript,
+        start_ms,
+        app_handle,
+        reply,
+    })
+}
+
+pub fn pause() -> Result<PlaybackState> {
+    send_command(PlaybackCommand::Pause)
+}
+
+pub fn resume() -> Result<PlaybackState> {
+    send_command(PlaybackCommand::Resume)
+}
+
+pub fn seek(position_ms: u64) -> Result<PlaybackState> {
+    send_command(|reply| PlaybackCommand::Seek(position_ms, reply))
+}
+
+/// Change the speed, from [`MIN_RATE`] to [`MAX_RATE`], keeping the pitch.
+pub fn set_rate(rate: f32) -> Result<PlaybackState> {
+    if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+        return Err(anyhow!("Playback speed must be between {}x and {}x", MIN_RATE, MAX_RATE));
+    }
+    send_command(|reply| PlaybackCommand::SetRate(rate, reply))
+}
+
+pub fn stop() -> Result<PlaybackState> {
+    send_command(PlaybackCommand::Stop)
+}
+
+/// What is playing, if anything.
+pub fn state() -> Option<PlaybackState> {
+    send_command(PlaybackCommand::State).ok()
+}
+
+/// Stop playback if it is playing `session_id`, e.g. before its files change.
+pub fn stop_session(session_id: &str) {
+    if state().is_some_and(|state| state.session_id == session_id) {
+        let _ = stop();
+    }
+}
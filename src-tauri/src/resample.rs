@@ -0,0 +1,195 @@
+use std::f64::consts::PI;
+
+// Zero crossings of the sinc on each side of an output sample; more is
+// sharper but slower
+const ZERO_CROSSINGS: usize = 16;
+// Filter table points per zero crossing, interpolated linearly in between
+const TABLE_RESOLUTION: usize = 256;
+// Cutoff as a fraction of the lower Nyquist frequency, leaving room for the transition band
+const ROLLOFF: f64 = 0.94;
+// Kaiser window shape, about 80dB of stopband attenuation
+const KAISER_BETA: f64 = 8.0;
+
+/// Streaming sample rate converter for interleaved audio.
+///
+/// Every output frame is a windowed-sinc interpolation of the input around
+/// it, low-passed below the lower of the two Nyquist frequencies so that
+/// downsampling doesn't alias. Chunks can have any length: input is kept
+/// until the filter has seen enough of it, so consecutive chunks join
+/// seamlessly and output frame `n` is always taken at input time
+/// `n * from_rate / to_rate`. Call [`Resampler::flush`] at the end of a
+/// stream for the last few frames.
+pub struct Resampler {
+    channels: usize,
+    from_rate: u64,
+    to_rate: u64,
+    // Filter scale (1.0 when upsampling) and reach in input frames on each side
+    scale: f64,
+    half_width: i64,
+    table: Vec<f32>,
+    // Input frames not yet needed by the filter, starting at frame `history_start`
+    history: Vec<f32>,
+    history_start: i64,
+    input_frames: i64,
+    output_frames: u64,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: u16) -> Self {
+        let (from_rate, to_rate) = (from_rate.max(1) as u64, to_rate.max(1) as u64);
+        let scale = (to_rate as f64 / from_rate as f64).min(1.0) * ROLLOFF;
+        let half_width = (ZERO_CROSSINGS as f64 / scale).ceil() as i64;
+
+        Resampler {
+            channels: channels.max(1) as usize,
+            from_rate,
+            to_rate,
+            scale,
+            half_width,
+            table: sinc_table(),
+            history: Vec::new(),
+            history_start: 0,
+            input_frames: 0,
+            output_frames: 0,
+        }
+    }
+
+    /// Whether input and output rates are the same, so samples pass through untouched.
+    pub fn is_passthrough(&self) -> bool {
+        self.from_rate == self.to_rate
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.is_passthrough() {
+            return input.to_vec();
+        }
+
+        let frames = input.len() / self.channels;
+        self.history.extend_from_slice(&input[..frames * self.channels]);
+        self.input_frames += frames as i64;
+        self.drain(self.input_frames)
+    }
+
+    /// Output the frames still held back waiting for input that will never come.
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.is_passthrough() {
+            return Vec::new();
+        }
+
+        // Past the end of the stream is silence
+        let input_frames = self.input_frames;
+        self.history.resize(self.history.len() + (self.half_width as usize + 1) * self.channels, 0.0);
+        self.input_frames += self.half_width + 1;
+        let output = self.drain(input_frames);
+        self.input_frames = input_frames;
+        output
+    }
+
+    // Produce every output frame whose input time is before `end` and whose
+    // filter window is fully buffered
+    fn drain(&mut self, end: i64) -> Vec<f32> {
+        let channels = self.channels;
+        let available = self.history_start + (self.history.len() / channels) as i64;
+        let mut output = Vec::new();
+
+        loop {
+            // Exact input time of the next output frame, as whole frames plus a fraction
+            let numerator = self.output_frames * self.from_rate;
+            let center = (numerator / self.to_rate) as i64;
+            let frac = (numerator % self.to_rate) as f64 / self.to_rate as f64;
+            if center >= end || center + self.half_width >= available {
+                break;
+            }
+
+            let first = center - self.half_width + 1;
+            let start = output.len();
+            output.resize(start + channels, 0.0);
+            for index in first..=center + self.half_width {
+                // Before the stream started is silence
+                if index < self.history_start {
+                    continue;
+                }
+                let weight = self.kernel((index - center) as f64 - frac);
+                let offset = (index - self.history_start) as usize * channels;
+                for (out, &sample) in output[start..].iter_mut().zip(&self.history[offset..offset + channels]) {
+                    *out += sample * weight;
+                }
+            }
+            self.output_frames += 1;
+        }
+
+        // Drop input that no future output frame reaches back to
+        let next_center = (self.output_frames * self.from_rate / self.to_rate) as i64;
+        let keep_from = (next_center - self.half_width + 1).min(available);
+        if keep_from > self.history_start {
+            self.history.drain(..(keep_from - self.history_start) as usize * channels);
+            self.history_start = keep_from;
+        }
+
+        output
+    }
+
+    // Filter weight for an input frame `distance` input frames from the output frame
+    fn kernel(&self, distance: f64) -> f32 {
+        let position = distance.abs() * self.scale * TABLE_RESOLUTION as f64;
+        let index = position as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let frac = (position - index as f64) as f32;
+        let value = self.table[index] + (self.table[index + 1] - self.table[index]) * frac;
+        value * self.scale as f32
+    }
+}
+
+// One side of a Kaiser-windowed sinc, from 0 to ZERO_CROSSINGS
+fn sinc_table() -> Vec<f32> {
+    let len = ZERO_CROSSINGS * TABLE_RESOLUTION;
+    let window_norm = bessel_i0(KAISER_BETA);
+    (0..=len)
+        .map(|i| {
+            let x = i as f64 / TABLE_RESOLUTION as f64;
+            let sinc = if i == 0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let ratio = i as f64 / len as f64;
+            let window = bessel_i0(KAISER_BETA * (1.0 - ratio * ratio).sqrt()) / window_norm;
+            (sinc * window) as f32
+        })
+        .collect()
+}
+
+// Zeroth order modified Bessel function of the first kind, for the Kaiser window
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= half_x / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Convert interleaved frames from one channel count to another: averaging
+/// down to mono, repeating mono onto every channel, or keeping the first
+/// channels otherwise.
+pub fn remap_channels(input: &[f32], from: u16, to: u16) -> Vec<f32> {
+    let (from, to) = (from.max(1) as usize, to.max(1) as usize);
+    if from == to {
+        return input.to_vec();
+    }
+
+    let mut output = Vec::with_capacity(input.len() / from * to);
+    for frame in input.chunks_exact(from) {
+        if to == 1 {
+            output.push(frame.iter().sum::<f32>() / from as f32);
+        } else if from == 1 {
+            output.extend(std::iter::repeat_n(frame[0], to));
+        } else {
+            output.extend((0..to).map(|channel| frame[channel.min(from - 1)]));
+        }
+    }
+    output
+}
@@ -0,0 +1,151 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// App-wide preferences, stored in `~/.polka/settings.json`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Name of the microphone to record from. `None` follows the system default.
+    pub input_device: Option<String>,
+    /// Format new recordings are saved in.
+    pub recording_format: RecordingFormat,
+    /// File format a recording is kept in once it is stopped.
+    pub storage_format: StorageFormat,
+}
+
+/// How a finished recording is stored on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    /// Uncompressed, the format recordings are captured in.
+    #[default]
+    Wav,
+    /// Lossless, usually about half the size of WAV.
+    Flac,
+    /// Lossy speech codec, a small fraction of the size of WAV.
+    Opus,
+}
+
+impl StorageFormat {
+    /// Fails for formats this build can't write.
+    pub fn validate(&self) -> Result<()> {
+        if *self == StorageFormat::Opus && !cfg!(feature = "opus") {
+            return Err(anyhow!("This build has no Opus support, rebuild with the `opus` feature"));
+        }
+        Ok(())
+    }
+}
+
+/// How samples are stored in a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+/// Sample rate, sample format and channel layout of a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingFormat {
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+    /// 1 for mono, 2 for stereo.
+    pub channels: u16,
+}
+
+impl Default for RecordingFormat {
+    // 16kHz mono is all speech recognition needs and keeps lectures small
+    fn default() -> Self {
+        RecordingFormat {
+            sample_rate: 16000,
+            sample_format: SampleFormat::Int16,
+            channels: 1,
+        }
+    }
+}
+
+impl RecordingFormat {
+    pub fn validate(&self) -> Result<()> {
+        if !(8000..=192_000).contains(&self.sample_rate) {
+            return Err(anyhow!("Sample rate must be between 8 and 192 kHz"));
+        }
+        if !(1..=2).contains(&self.channels) {
+            return Err(anyhow!("Recordings can only be mono or stereo"));
+        }
+        Ok(())
+    }
+
+    pub fn wav_spec(&self) -> hound::WavSpec {
+        let (bits_per_sample, sample_format) = match self.sample_format {
+            SampleFormat::Int16 => (16, hound::SampleFormat::Int),
+            SampleFormat::Int24 => (24, hound::SampleFormat::Int),
+            SampleFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+
+        hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+pub fn settings_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
+    Ok(home.join(".polka").join("settings.json"))
+}
+
+impl AppSettings {
+    /// Read the saved settings, or the defaults if nothing was saved yet.
+    pub fn load() -> Result<Self> {
+        let path = settings_path()?;
+        if !path.exists() {
+            return Ok(AppSettings::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Failed to parse settings {:?}: {}", path, e))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.recording_format.validate()?;
+        self.storage_format.validate()?;
+
+        let path = settings_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write next to the file and rename, so a crash never leaves half a settings file
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        crate::durable::replace(&temp_path, &path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_formats() {
+        // Settings saved before the format existed still load with the default
+        let settings: AppSettings = serde_json::from_str(r#"{"input_device":null}"#).unwrap();
+        assert_eq!(settings.recording_format, RecordingFormat::default());
+        let format: RecordingFormat =
+            serde_json::from_str(r#"{"sample_rate":48000,"sample_format":"float32","channels":2}"#).unwrap();
+        let spec = format.wav_spec();
+        assert_eq!((spec.sample_rate, spec.channels, spec.bits_per_sample), (48000, 2, 32));
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert!(RecordingFormat { channels: 3, ..format }.validate().is_err());
+        assert!(RecordingFormat { sample_format: SampleFormat::Int24, ..format }.validate().is_ok());
+        assert!(StorageFormat::Flac.validate().is_ok());
+        assert_eq!(StorageFormat::Opus.validate().is_ok(), cfg!(feature = "opus"));
+    }
+}
@@ -1,14 +1,135 @@
-use std::path::PathBuf;
-use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::collections::HashMap;
-use tokio::time::{interval, Duration};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
 use tauri::{AppHandle, Emitter};
 use once_cell::sync::Lazy;
+use crate::codec::AudioReader;
+use crate::db::Database;
+use crate::models::{TranscriptLine, TranscriptWord};
+use crate::resample::Resampler;
+use crate::transcript::{self, TranscriptWriter};
+use crate::vad::{GateAction, SpeechGate, VadConfig};
 
-// Global speech recognition state
-static SPEECH_PROCESSING: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> = 
+/// Sample rate of the audio speech engines are fed. Capture and batch
+/// transcription resample to it, so engines never convert audio themselves.
+pub const SPEECH_SAMPLE_RATE: u32 = 16000;
+
+/// A piece of recognized text placed on the recording timeline.
+///
+/// Times are milliseconds of captured audio (pauses excluded), so they line up
+/// with positions in the session's `audio.wav`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeechSegment {
+    /// Identifies the utterance within one engine run. Partial results for the
+    /// same utterance share it, and it never changes once the final arrives.
+    pub utterance_id: u64,
+    /// `false` while the engine may still revise the text.
+    pub is_final: bool,
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// Engine confidence in 0.0..=1.0, if the engine reports one.
+    pub confidence: Option<f32>,
+    /// Word-level timings, empty if the engine can't provide them.
+    pub words: Vec<TranscriptWord>,
+}
+
+impl SpeechSegment {
+    pub fn into_transcript_line(self, speaker: &str) -> TranscriptLine {
+        TranscriptLine {
+            t_ms: self.start_ms,
+            speaker: speaker.to_string(),
+            text: self.text,
+            end_ms: Some(self.end_ms),
+            confidence: self.confidence,
+            words: self.words,
+        }
+    }
+}
+
+/// A speech recognition backend.
+///
+/// Engines receive mono `f32` PCM in the range -1.0..=1.0 at
+/// [`SPEECH_SAMPLE_RATE`], resampled from the audio written to the session's
+/// WAV file, and return whatever text they have
+/// recognized so far. They are driven from a dedicated thread, so blocking
+/// inference inside `feed`/`flush` is fine.
+///
+/// Audio first passes a [`SpeechGate`]: engines only hear speech, are flushed
+/// at the end of every utterance and are told how much silence was left out.
+pub trait SpeechEngine: Send {
+    /// Short identifier used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Prepare for a new stream of audio at the given sample rate.
+    fn start(&mut self, sample_rate: u32) -> Result<()>;
+
+    /// Push captured samples; returns any segments recognized as a result.
+    /// Streaming engines may return partial segments that are superseded by
+    /// later ones with the same `utterance_id`.
+    fn feed(&mut self, samples: &[f32]) -> Result<Vec<SpeechSegment>>;
+
+    /// Recognize whatever audio is still buffered. Everything returned here is
+    /// final, including utterances that were only partially reported so far.
+    fn flush(&mut self) -> Result<Vec<SpeechSegment>>;
+
+    /// Move the engine's clock past `samples` samples that were not fed
+    /// because they held no speech. Only called right after `flush`.
+    fn skip(&mut self, samples: usize);
+
+    /// Release engine resources. The engine may be started again afterwards.
+    fn stop(&mut self) -> Result<()>;
+}
+
+/// Create the engine used for live transcription.
+///
+/// Prefers the offline Whisper engine and falls back to the mock when no
+/// model is installed in `~/.polka/models`.
+pub fn create_engine() -> Box<dyn SpeechEngine> {
+    #[cfg(feature = "whisper")]
+    match crate::whisper::WhisperSpeechEngine::from_default_model() {
+        Ok(engine) => return Box::new(engine),
+        Err(e) => println!("🎤 Whisper engine unavailable, using mock transcription: {}", e),
+    }
+
+    Box::new(MockSpeechEngine::new())
+}
+
+/// Create an engine for re-transcribing finished recordings.
+///
+/// Unlike [`create_engine`] this never falls back to the mock, since that would
+/// just replace one fake transcript with another.
+pub fn create_batch_engine() -> Result<Box<dyn SpeechEngine>> {
+    #[cfg(feature = "whisper")]
+    {
+        let engine = crate::whisper::WhisperSpeechEngine::from_default_model()?;
+        Ok(Box::new(engine))
+    }
+
+    #[cfg(not(feature = "whisper"))]
+    Err(anyhow!("This build does not include a speech recognition engine"))
+}
+
+enum SpeechCommand {
+    Samples(Vec<f32>),
+    Stop,
+}
+
+struct SpeechHandle {
+    command_tx: Sender<SpeechCommand>,
+    thread: JoinHandle<()>,
+}
+
+// Global speech recognition state: one engine thread per session
+static SPEECH_PROCESSING: Lazy<Mutex<HashMap<String, SpeechHandle>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Batch transcription jobs and their cancellation flags
+static TRANSCRIPTION_JOBS: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
 // Mock speech recognition data - realistic phrases that would be spoken
@@ -25,101 +146,152 @@ static MOCK_SPEECH_PHRASES: &[&str] = &[
     "In conclusion, this methodology provides robust and reliable results.",
 ];
 
-pub fn is_speech_processing(session_id: &str) -> bool {
-    if let Ok(processing_map) = SPEECH_PROCESSING.lock() {
-        if let Some(flag) = processing_map.get(session_id) {
-            return flag.load(Ordering::Relaxed);
+/// Engine that ignores the audio content and "recognizes" canned phrases,
+/// paced by the amount of audio it has been fed.
+pub struct MockSpeechEngine {
+    sample_rate: u32,
+    samples_seen: u64,
+    phrase_index: usize,
+    words_in_current_phrase: Vec<String>,
+    current_phrase_progress: usize,
+    word_times_ms: Vec<u64>,
+    next_emit_ms: u64,
+}
+
+impl MockSpeechEngine {
+    pub fn new() -> Self {
+        MockSpeechEngine {
+            sample_rate: 16000,
+            samples_seen: 0,
+            phrase_index: 0,
+            words_in_current_phrase: Vec::new(),
+            current_phrase_progress: 0,
+            word_times_ms: Vec::new(),
+            // Wait a bit before starting
+            next_emit_ms: 1000,
+        }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.samples_seen * 1000 / self.sample_rate.max(1) as u64
+    }
+
+    fn current_segment(&self, word_count: usize, end_ms: u64, is_final: bool) -> SpeechSegment {
+        let words_so_far = &self.words_in_current_phrase[..word_count];
+        let words = words_so_far.iter()
+            .zip(&self.word_times_ms)
+            .enumerate()
+            .map(|(i, (word, &t_ms))| TranscriptWord {
+                text: word.clone(),
+                t_ms,
+                end_ms: self.word_times_ms.get(i + 1).copied().unwrap_or(end_ms),
+                confidence: None,
+            })
+            .collect();
+
+        SpeechSegment {
+            utterance_id: self.phrase_index as u64,
+            is_final,
+            text: words_so_far.join(" "),
+            start_ms: self.word_times_ms[0],
+            end_ms,
+            confidence: None,
+            words,
         }
     }
-    false
 }
 
-pub async fn start_speech_processing(
-    session_id: String,
-    _audio_path: PathBuf,
-    app_handle: AppHandle,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("🎤 Starting intelligent mock speech processing for session: {}", session_id);
-    
-    // Create and store processing flag
-    let is_processing = Arc::new(AtomicBool::new(true));
-    {
-        let mut processing_map = SPEECH_PROCESSING.lock().unwrap();
-        processing_map.insert(session_id.clone(), Arc::clone(&is_processing));
-    }
-    
-    // Spawn background task that generates more realistic transcription
-    let session_id_clone = session_id.clone();
-    let app_handle_clone = app_handle.clone();
-    
-    tokio::spawn(async move {
-        let mut interval = interval(Duration::from_millis(500)); // Check every 500ms
-        let mut phrase_index = 0;
-        let mut current_phrase_progress = 0;
-        let mut words_in_current_phrase = Vec::new();
-        let mut last_emission_time = std::time::Instant::now();
-        
-        // Wait a bit before starting
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        
-        while is_speech_processing(&session_id_clone) {
-            interval.tick().await;
-            
+impl Default for MockSpeechEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpeechEngine for MockSpeechEngine {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn start(&mut self, sample_rate: u32) -> Result<()> {
+        *self = MockSpeechEngine::new();
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    fn feed(&mut self, samples: &[f32]) -> Result<Vec<SpeechSegment>> {
+        self.samples_seen += samples.len() as u64;
+        let now_ms = self.elapsed_ms();
+        let mut segments = Vec::new();
+
+        // Simulate natural speech pace: one more word every 0.8-1.2s of audio
+        while now_ms >= self.next_emit_ms {
             // Get current phrase and split into words
-            if words_in_current_phrase.is_empty() {
-                let phrase = MOCK_SPEECH_PHRASES[phrase_index % MOCK_SPEECH_PHRASES.len()];
-                words_in_current_phrase = phrase.split_whitespace()
+            if self.words_in_current_phrase.is_empty() {
+                let phrase = MOCK_SPEECH_PHRASES[self.phrase_index % MOCK_SPEECH_PHRASES.len()];
+                self.words_in_current_phrase = phrase.split_whitespace()
                     .map(|s| s.to_string())
                     .collect();
-                current_phrase_progress = 0;
-                phrase_index += 1;
+                self.current_phrase_progress = 0;
+                self.phrase_index += 1;
+                self.word_times_ms.clear();
             }
-            
-            // Check if enough time has passed (simulate natural speech pace)
-            let time_since_last = last_emission_time.elapsed();
-            let should_emit = time_since_last >= Duration::from_millis(800 + (rand::random::<u64>() % 400));
-            
-            if should_emit && current_phrase_progress < words_in_current_phrase.len() {
-                // Emit partial phrase (words spoken so far)
-                let words_so_far = &words_in_current_phrase[0..=current_phrase_progress];
-                let partial_text = words_so_far.join(" ");
-                
-                // Only emit if we have a meaningful chunk (2+ words or end of phrase)
-                if words_so_far.len() >= 2 || current_phrase_progress == words_in_current_phrase.len() - 1 {
-                    let _ = app_handle_clone.emit("polka://transcript-line", serde_json::json!({
-                        "session_id": session_id_clone,
-                        "text": partial_text,
-                        "timestamp": chrono::Utc::now().timestamp_millis()
-                    }));
-                    
-                    println!("🎤 Transcribed: {}", partial_text);
-                    last_emission_time = std::time::Instant::now();
-                }
-                
-                current_phrase_progress += 1;
-                
-                // If we've finished the current phrase, clear it
-                if current_phrase_progress >= words_in_current_phrase.len() {
-                    words_in_current_phrase.clear();
-                    // Add a pause between phrases
-                    tokio::time::sleep(Duration::from_millis(1500)).await;
-                }
+
+            // Each word is "spoken" from its emission tick until the next one
+            self.word_times_ms.push(self.next_emit_ms);
+            let word_end_ms = self.next_emit_ms + 800;
+
+            // Emit partial phrase (words spoken so far)
+            let words_so_far = &self.words_in_current_phrase[0..=self.current_phrase_progress];
+
+            // Only emit if we have a meaningful chunk (2+ words or end of phrase)
+            let is_final = self.current_phrase_progress == self.words_in_current_phrase.len() - 1;
+            if words_so_far.len() >= 2 || is_final {
+                segments.push(self.current_segment(words_so_far.len(), word_end_ms, is_final));
+            }
+
+            self.current_phrase_progress += 1;
+            self.next_emit_ms += 800 + (rand::random::<u64>() % 400);
+
+            // If we've finished the current phrase, clear it and add a pause between phrases
+            if self.current_phrase_progress >= self.words_in_current_phrase.len() {
+                self.words_in_current_phrase.clear();
+                self.next_emit_ms += 1500;
             }
         }
-        
-        println!("🎤 Intelligent mock speech processing stopped for session: {}", session_id_clone);
-    });
-    
-    Ok(())
+
+        Ok(segments)
+    }
+
+    fn flush(&mut self) -> Result<Vec<SpeechSegment>> {
+        // Finalize a phrase that was cut off mid-way
+        if self.words_in_current_phrase.is_empty() || self.current_phrase_progress == 0 {
+            return Ok(Vec::new());
+        }
+
+        let segment = self.current_segment(self.current_phrase_progress, self.elapsed_ms(), true);
+        self.words_in_current_phrase.clear();
+        Ok(vec![segment])
+    }
+
+    fn skip(&mut self, samples: usize) {
+        self.samples_seen += samples as u64;
+        // Carry on "speaking" after the silence rather than catching up inside it
+        self.next_emit_ms = self.next_emit_ms.max(self.elapsed_ms());
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.words_in_current_phrase.clear();
+        Ok(())
+    }
 }
 
 // Simple random number generation for timing variation
 mod rand {
     use std::sync::atomic::{AtomicU64, Ordering};
     static SEED: AtomicU64 = AtomicU64::new(1);
-    
-    pub fn random<T>() -> T 
-    where 
+
+    pub fn random<T>() -> T
+    where
         T: From<u64>
     {
         let seed = SEED.load(Ordering::Relaxed);
@@ -129,25 +301,382 @@ mod rand {
     }
 }
 
-pub fn stop_speech_processing(session_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("🎤 Stopping mock speech processing for session: {}", session_id);
-    
-    // Set processing flag to false
+pub fn is_speech_processing(session_id: &str) -> bool {
     if let Ok(processing_map) = SPEECH_PROCESSING.lock() {
-        if let Some(flag) = processing_map.get(session_id) {
-            flag.store(false, Ordering::Relaxed);
-        }
+        return processing_map.contains_key(session_id);
     }
-    
-    Ok(())
+    false
 }
 
-pub fn cleanup_speech_context(session_id: &str) {
-    // Remove from global state
+/// Start live transcription for a recording session.
+///
+/// Audio reaches the engine through [`feed_samples`], which the capture
+/// callback calls with the audio it writes to disk, as mono resampled to
+/// [`SPEECH_SAMPLE_RATE`].
+///
+/// Final results are written to `transcript_path` by the backend as they
+/// arrive, independently of any window; partial results are only sent to the
+/// frontend. `vad_config` decides what counts as speech. Saved lines are
+/// added to the search index in `db`.
+pub fn start_speech_processing(
+    session_id: String,
+    transcript_path: PathBuf,
+    vad_config: VadConfig,
+    db: Database,
+    app_handle: AppHandle,
+) -> Result<()> {
+    if is_speech_processing(&session_id) {
+        return Err(anyhow!("Speech processing already running"));
+    }
+
+    // Open the transcript up front so a bad path fails the start, not the first line
+    let transcript = TranscriptWriter::open(&transcript_path)?;
+
+    let mut engine = create_engine();
+    engine.start(SPEECH_SAMPLE_RATE)?;
+    let gate = SpeechGate::new(&vad_config, SPEECH_SAMPLE_RATE);
+    println!("🎤 Starting {} speech processing for session: {}", engine.name(), session_id);
+
+    let (command_tx, command_rx) = mpsc::channel::<SpeechCommand>();
+
+    // Engines may block for a while during inference, so run them on their own thread
+    let output = SpeechOutput {
+        session_id: session_id.clone(),
+        // Utterance ids restart with every engine run, so scope them to this run
+        run_id: nanoid::nanoid!(8),
+        transcript,
+        db,
+        app_handle,
+    };
+    let thread = std::thread::Builder::new()
+        .name(format!("speech-{}", session_id))
+        .spawn(move || run_speech_engine(engine, gate, command_rx, output))?;
+
     {
         let mut processing_map = SPEECH_PROCESSING.lock().unwrap();
-        processing_map.remove(session_id);
+        processing_map.insert(session_id, SpeechHandle { command_tx, thread });
     }
-    
-    println!("🎤 Cleaned up mock speech context for session: {}", session_id);
+
+    Ok(())
+}
+
+/// Where a live engine's results go.
+struct SpeechOutput {
+    session_id: String,
+    run_id: String,
+    transcript: TranscriptWriter,
+    db: Database,
+    app_handle: AppHandle,
+}
+
+impl SpeechOutput {
+    fn publish(&mut self, segments: Vec<SpeechSegment>) {
+        for segment in segments {
+            let utterance_id = format!("{}-{}", self.run_id, segment.utterance_id);
+
+            let _ = self.app_handle.emit("polka://transcript-line", serde_json::json!({
+                "session_id": self.session_id,
+                "utterance_id": utterance_id,
+                "is_final": segment.is_final,
+                "speaker": "Speaker",
+                "text": segment.text,
+                "t_ms": segment.start_ms,
+                "end_ms": segment.end_ms,
+                "confidence": segment.confidence,
+                "words": segment.words,
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            }));
+
+            if segment.is_final {
+                println!("🎤 Transcribed: {}", segment.text);
+                let line = segment.into_transcript_line("Speaker");
+                if let Err(e) = self.transcript.append(&line) {
+                    eprintln!("🎤 Failed to save transcript line: {}", e);
+                } else if let Err(e) = self.db.index_transcript_line(&self.session_id, &line) {
+                    eprintln!("🎤 Failed to index transcript line: {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn run_speech_engine(
+    mut engine: Box<dyn SpeechEngine>,
+    mut gate: SpeechGate,
+    command_rx: Receiver<SpeechCommand>,
+    mut output: SpeechOutput,
+) {
+    // A closed channel means the session state was dropped without an explicit stop
+    while let Ok(command) = command_rx.recv() {
+        match command {
+            SpeechCommand::Samples(samples) => {
+                for action in gate.process(&samples) {
+                    match apply_gate_action(engine.as_mut(), action) {
+                        Ok(segments) => output.publish(segments),
+                        Err(e) => eprintln!("🎤 Speech engine error: {}", e),
+                    }
+                }
+            }
+            SpeechCommand::Stop => break,
+        }
+    }
+
+    // Finish the utterance that was still going when recording stopped
+    for action in gate.finish() {
+        match apply_gate_action(engine.as_mut(), action) {
+            Ok(segments) => output.publish(segments),
+            Err(e) => eprintln!("🎤 Failed to flush speech engine: {}", e),
+        }
+    }
+    if let Err(e) = engine.stop() {
+        eprintln!("🎤 Failed to stop speech engine: {}", e);
+    }
+
+    println!("🎤 {} speech processing stopped for session: {}", engine.name(), output.session_id);
+}
+
+// Pass one decision of the speech gate on to the engine
+fn apply_gate_action(engine: &mut dyn SpeechEngine, action: GateAction) -> Result<Vec<SpeechSegment>> {
+    match action {
+        GateAction::Feed(samples) => engine.feed(&samples),
+        GateAction::Skip(samples) => {
+            engine.skip(samples);
+            Ok(Vec::new())
+        }
+        GateAction::EndOfSpeech => engine.flush(),
+    }
+}
+
+/// Hand captured mono samples to the session's speech engine, if one is running.
+pub fn feed_samples(session_id: &str, samples: &[f32]) {
+    if let Ok(processing_map) = SPEECH_PROCESSING.lock() {
+        if let Some(handle) = processing_map.get(session_id) {
+            let _ = handle.command_tx.send(SpeechCommand::Samples(samples.to_vec()));
+        }
+    }
+}
+
+/// Stop live transcription, waiting until buffered audio has been recognized
+/// and every final line is on disk.
+pub fn stop_speech_processing(session_id: &str) -> Result<()> {
+    println!("🎤 Stopping speech processing for session: {}", session_id);
+
+    let handle = {
+        let mut processing_map = SPEECH_PROCESSING.lock().unwrap();
+        processing_map.remove(session_id)
+    };
+
+    match handle {
+        Some(handle) => {
+            let _ = handle.command_tx.send(SpeechCommand::Stop);
+            handle.thread.join()
+                .map_err(|_| anyhow!("Speech engine thread panicked"))
+        }
+        None => Err(anyhow!("Speech processing not running")),
+    }
+}
+
+/// Flush and stop every running engine, used when the app is shutting down.
+pub fn stop_all_speech_processing() {
+    let session_ids: Vec<String> = {
+        let processing_map = SPEECH_PROCESSING.lock().unwrap();
+        processing_map.keys().cloned().collect()
+    };
+
+    for session_id in session_ids {
+        if let Err(e) = stop_speech_processing(&session_id) {
+            eprintln!("🎤 Failed to stop speech processing for {}: {}", session_id, e);
+        }
+    }
+}
+
+enum TranscriptionOutcome {
+    Completed(usize),
+    Cancelled,
+}
+
+pub fn is_transcribing(session_id: &str) -> bool {
+    if let Ok(jobs) = TRANSCRIPTION_JOBS.lock() {
+        return jobs.contains_key(session_id);
+    }
+    false
+}
+
+/// Run speech recognition over a finished recording in the background and
+/// replace its transcript with the result.
+///
+/// Progress is reported through `polka://transcription-progress` and the
+/// outcome through `polka://transcription-finished`. The existing transcript
+/// is only replaced once the whole file has been processed, and its search
+/// index in `db` with it.
+pub fn start_session_transcription(
+    session_id: String,
+    audio_path: PathBuf,
+    transcript_path: PathBuf,
+    vad_config: VadConfig,
+    db: Database,
+    app_handle: AppHandle,
+) -> Result<()> {
+    let mut engine = create_batch_engine()?;
+
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    {
+        let mut jobs = TRANSCRIPTION_JOBS.lock().unwrap();
+        if jobs.contains_key(&session_id) {
+            return Err(anyhow!("Transcription already running"));
+        }
+        jobs.insert(session_id.clone(), Arc::clone(&cancel_flag));
+    }
+
+    println!("📝 Starting {} transcription for session: {}", engine.name(), session_id);
+
+    let thread_session_id = session_id.clone();
+    let spawned = std::thread::Builder::new()
+        .name(format!("transcribe-{}", session_id))
+        .spawn(move || {
+            let session_id = thread_session_id;
+            let result = transcribe_audio_file(
+                &session_id,
+                &audio_path,
+                &transcript_path,
+                &vad_config,
+                engine.as_mut(),
+                &cancel_flag,
+                &db,
+                &app_handle,
+            );
+
+            if let Err(e) = engine.stop() {
+                eprintln!("📝 Failed to stop speech engine: {}", e);
+            }
+            TRANSCRIPTION_JOBS.lock().unwrap().remove(&session_id);
+
+            let payload = match result {
+                Ok(TranscriptionOutcome::Completed(lines)) => {
+                    println!("📝 Transcription completed for session: {} ({} lines)", session_id, lines);
+                    serde_json::json!({ "session_id": session_id, "status": "completed", "lines": lines })
+                }
+                Ok(TranscriptionOutcome::Cancelled) => {
+                    println!("📝 Transcription cancelled for session: {}", session_id);
+                    serde_json::json!({ "session_id": session_id, "status": "cancelled" })
+                }
+                Err(e) => {
+                    eprintln!("📝 Transcription failed for session {}: {}", session_id, e);
+                    serde_json::json!({ "session_id": session_id, "status": "failed", "error": e.to_string() })
+                }
+            };
+            let _ = app_handle.emit("polka://transcription-finished", payload);
+        });
+
+    if let Err(e) = spawned {
+        TRANSCRIPTION_JOBS.lock().unwrap().remove(&session_id);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+pub fn cancel_session_transcription(session_id: &str) -> Result<()> {
+    let jobs = TRANSCRIPTION_JOBS.lock().unwrap();
+    match jobs.get(session_id) {
+        Some(cancel_flag) => {
+            cancel_flag.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        None => Err(anyhow!("No transcription running for this session")),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn transcribe_audio_file(
+    session_id: &str,
+    audio_path: &Path,
+    transcript_path: &Path,
+    vad_config: &VadConfig,
+    engine: &mut dyn SpeechEngine,
+    cancel_flag: &AtomicBool,
+    db: &Database,
+    app_handle: &AppHandle,
+) -> Result<TranscriptionOutcome> {
+    let mut samples = AudioReader::open(audio_path)?;
+    let info = samples.info();
+    let channels = info.channels.max(1) as usize;
+    let total_ms = info.duration_ms();
+
+    engine.start(SPEECH_SAMPLE_RATE)?;
+    let mut gate = SpeechGate::new(vad_config, SPEECH_SAMPLE_RATE);
+    let mut resampler = Resampler::new(info.sample_rate, SPEECH_SAMPLE_RATE, 1);
+
+    // Feed one second at a time so cancellation and progress stay responsive
+    let chunk_len = info.sample_rate as usize * channels;
+    let mut chunk = Vec::with_capacity(chunk_len);
+    let mut segments = Vec::new();
+    let mut processed_frames = 0u64;
+    let mut chunks_fed = 0u64;
+
+    loop {
+        if cancel_flag.load(Ordering::Relaxed) {
+            return Ok(TranscriptionOutcome::Cancelled);
+        }
+
+        chunk.clear();
+        for sample in samples.by_ref().take(chunk_len) {
+            chunk.push(sample?);
+        }
+        if chunk.is_empty() {
+            break;
+        }
+
+        let mono: Vec<f32> = chunk.chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        processed_frames += mono.len() as u64;
+        for action in gate.process(&resampler.process(&mono)) {
+            segments.extend(apply_gate_action(engine, action)?);
+        }
+
+        chunks_fed += 1;
+        if chunks_fed.is_multiple_of(10) {
+            let processed_ms = processed_frames * 1000 / info.sample_rate.max(1) as u64;
+            emit_transcription_progress(app_handle, session_id, processed_ms, total_ms);
+        }
+    }
+
+    let mut actions = gate.process(&resampler.flush());
+    actions.extend(gate.finish());
+    for action in actions {
+        segments.extend(apply_gate_action(engine, action)?);
+    }
+    if cancel_flag.load(Ordering::Relaxed) {
+        return Ok(TranscriptionOutcome::Cancelled);
+    }
+    emit_transcription_progress(app_handle, session_id, total_ms, total_ms);
+
+    let mut lines: Vec<TranscriptLine> = segments.into_iter()
+        .map(|segment| segment.into_transcript_line("Speaker"))
+        .collect();
+
+    // The text is worth keeping even when telling the speakers apart fails
+    match crate::diarization::diarize_lines(audio_path, &mut lines, None) {
+        Ok(speakers) => println!("📝 Found {} speaker(s) in session: {}", speakers, session_id),
+        Err(e) => eprintln!("📝 Speaker diarization failed for session {}: {}", session_id, e),
+    }
+
+    transcript::write_lines(transcript_path, &lines)?;
+    // The file is what counts; a stale index only affects search
+    if let Err(e) = db.index_transcript(session_id, &lines) {
+        eprintln!("📝 Failed to index transcript of session {}: {}", session_id, e);
+    }
+
+    Ok(TranscriptionOutcome::Completed(lines.len()))
+}
+
+fn emit_transcription_progress(app_handle: &AppHandle, session_id: &str, processed_ms: u64, total_ms: u64) {
+    let progress = if total_ms == 0 { 1.0 } else { processed_ms as f64 / total_ms as f64 };
+    let _ = app_handle.emit("polka://transcription-progress", serde_json::json!({
+        "session_id": session_id,
+        "progress": progress.min(1.0),
+        "processed_ms": processed_ms,
+        "total_ms": total_ms
+    }));
 }
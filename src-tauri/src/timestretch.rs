@@ -0,0 +1,196 @@
+use std::f32::consts::PI;
+
+/// Slowest and fastest playback the stretcher is meant for.
+pub const MIN_RATE: f32 = 0.5;
+pub const MAX_RATE: f32 = 3.0;
+
+// Length of the windows cut from the input; long enough for the lowest voices
+const FRAME_MS: u32 = 30;
+// How far a window may move from where the rate puts it to line up with the
+// previous one
+const TOLERANCE_MS: u32 = 10;
+
+/// Streaming WSOLA (waveform similarity overlap-add) time stretcher: changes
+/// the speed of interleaved f32 audio without changing its pitch.
+///
+/// Windows are taken from the input at `rate` times the spacing they are
+/// written to the output at, each nudged to wherever it best continues the
+/// waveform of the one before, and cross-faded together. At a rate of 1.0
+/// samples pass through untouched.
+pub struct TimeStretcher {
+    channels: usize,
+    rate: f64,
+    frame_len: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    // Input not yet used up and the absolute frame its first sample is at
+    input: Vec<f32>,
+    input_start: u64,
+    // Where the rate puts the next window, and where the previous one went
+    nominal: f64,
+    previous: Option<u64>,
+    // Overlap-add of windows written so far, the first `hop` frames of which are done
+    output: Vec<f32>,
+}
+
+impl TimeStretcher {
+    pub fn new(sample_rate: u32, channels: u16, rate: f32) -> Self {
+        let channels = channels.max(1) as usize;
+        // An even length keeps the two halves of the window the same
+        let frame_len = ((sample_rate * FRAME_MS / 1000) as usize).max(16) & !1;
+        let hop = frame_len / 2;
+        // A periodic Hann window at half overlap adds up to exactly one
+        let window = (0..frame_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_len as f32).cos())
+            .collect();
+
+        TimeStretcher {
+            channels,
+            rate: rate.clamp(MIN_RATE, MAX_RATE) as f64,
+            frame_len,
+            hop,
+            tolerance: (sample_rate * TOLERANCE_MS / 1000) as usize,
+            window,
+            input: Vec::new(),
+            input_start: 0,
+            nominal: 0.0,
+            previous: None,
+            output: vec![0.0; frame_len * channels],
+        }
+    }
+
+    pub fn rate(&self) -> f32 {
+        self.rate as f32
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.rate == 1.0
+    }
+
+    /// Stretch the next stretch of input. Output lags the input by about one
+    /// window; [`TimeStretcher::flush`] returns the rest at the end.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.is_passthrough() {
+            return input.to_vec();
+        }
+
+        self.input.extend_from_slice(input);
+        let mut result = Vec::new();
+        while let Some(start) = self.next_window_start() {
+            self.add_window(start);
+            result.extend(self.output.drain(..self.hop * self.channels));
+            self.output.resize(self.frame_len * self.channels, 0.0);
+        }
+        self.discard_used_input();
+        result
+    }
+
+    /// Stretch what is left of the input and return the rest of the output.
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.is_passthrough() {
+            return Vec::new();
+        }
+
+        // Silence after the end lets the last windows be placed like any other
+        let end = self.input_start + self.input_frames() as u64;
+        let padding = self.frame_len + self.hop + self.tolerance;
+        self.input.resize(self.input.len() + padding * self.channels, 0.0);
+
+        let mut result = Vec::new();
+        while (self.nominal as u64) < end {
+            let Some(start) = self.next_window_start() else {
+                break;
+            };
+            self.add_window(start);
+            result.extend(self.output.drain(..self.hop * self.channels));
+            self.output.resize(self.frame_len * self.channels, 0.0);
+        }
+        // The fading half of the last window
+        result.extend(self.output.drain(..self.hop * self.channels));
+
+        self.input.clear();
+        self.input_start = end;
+        self.nominal = end as f64;
+        self.previous = None;
+        self.output = vec![0.0; self.frame_len * self.channels];
+        result
+    }
+
+    fn input_frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    // Where the next window goes, if there is enough input to place it
+    fn next_window_start(&self) -> Option<u64> {
+        let end = self.input_start + self.input_frames() as u64;
+        let nominal = self.nominal as u64;
+        let Some(previous) = self.previous else {
+            return (nominal + self.frame_len as u64 <= end).then_some(nominal);
+        };
+
+        // The furthest window searched and the natural continuation of the
+        // previous one both have to be in
+        let latest = nominal + self.tolerance as u64 + self.frame_len as u64;
+        let continuation = previous + self.hop as u64 + self.frame_len as u64;
+        if latest.max(continuation) > end {
+            return None;
+        }
+
+        let earliest = nominal.saturating_sub(self.tolerance as u64).max(self.input_start);
+        let target = previous + self.hop as u64;
+        Some(self.best_match(target, earliest, nominal + self.tolerance as u64))
+    }
+
+    // The start in `earliest..=latest` whose first half-window looks most
+    // like the one at `target`
+    fn best_match(&self, target: u64, earliest: u64, latest: u64) -> u64 {
+        let overlap = self.frame_len - self.hop;
+        // Channels are summed, similarity only needs one
+        let mono = |start: u64, len: usize| -> Vec<f32> {
+            let offset = (start - self.input_start) as usize * self.channels;
+            self.input[offset..offset + len * self.channels]
+                .chunks(self.channels)
+                .map(|frame| frame.iter().sum::<f32>())
+                .collect()
+        };
+        let reference = mono(target, overlap);
+        let region = mono(earliest, (latest - earliest) as usize + overlap);
+
+        let mut best = (f32::MIN, target.clamp(earliest, latest));
+        for (shift, candidate) in region.windows(overlap).enumerate() {
+            let mut dot = 0.0;
+            let mut energy = 0.0;
+            for (a, b) in reference.iter().zip(candidate) {
+                dot += a * b;
+                energy += b * b;
+            }
+            let score = dot / (energy.sqrt() + 1e-9);
+            if score > best.0 {
+                best = (score, earliest + shift as u64);
+            }
+        }
+        best.1
+    }
+
+    fn add_window(&mut self, start: u64) {
+        let offset = (start - self.input_start) as usize * self.channels;
+        let frame = &self.input[offset..offset + self.frame_len * self.channels];
+        for (i, (out, sample)) in self.output.iter_mut().zip(frame).enumerate() {
+            *out += sample * self.window[i / self.channels];
+        }
+        self.previous = Some(start);
+        self.nominal += self.hop as f64 * self.rate;
+    }
+
+    // Drop input no later window can reach
+    fn discard_used_input(&mut self) {
+        let mut keep_from = (self.nominal as u64).saturating_sub(self.tolerance as u64);
+        if let Some(previous) = self.previous {
+            keep_from = keep_from.min(previous + self.hop as u64);
+        }
+        let used = keep_from.saturating_sub(self.input_start).min(self.input_frames() as u64);
+        self.input.drain(..used as usize * self.channels);
+        self.input_start += used;
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Result, anyhow};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::models::TranscriptLine;
+
+/// Append one line to a session's `transcript.jsonl`, creating the file if needed.
+pub fn append_line(path: &Path, line: &TranscriptLine) -> Result<()> {
+    TranscriptWriter::open(path)?.append(line)
+}
+
+/// Keeps a session's transcript open for appending while it is being recorded.
+///
+/// Every line is synced to disk before `append` returns, so closing the
+/// window or losing the process never costs more than the line in flight.
+pub struct TranscriptWriter {
+    path: PathBuf,
+    file: File,
+}
+
+impl TranscriptWriter {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Failed to open transcript file {:?}: {}", path, e))?;
+
+        let mut writer = TranscriptWriter { path: path.to_path_buf(), file };
+        writer.drop_incomplete_last_line()?;
+        Ok(writer)
+    }
+
+    // A crash mid-write leaves a line without its newline; new lines must not be glued onto it
+    fn drop_incomplete_last_line(&mut self) -> Result<()> {
+        let len = self.file.metadata()?.len();
+        if len == 0 {
+            return Ok(());
+        }
+
+        let mut last_byte = [0u8; 1];
+        self.file.seek(SeekFrom::Start(len - 1))?;
+        self.file.read_exact(&mut last_byte)?;
+        if last_byte[0] == b'\n' {
+            return Ok(());
+        }
+
+        let mut content = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut content)?;
+        let keep = content.iter().rposition(|&b| b == b'\n').map_or(0, |pos| pos + 1);
+
+        eprintln!("📄 Dropping incomplete last line of {:?}", self.path);
+        self.file.set_len(keep as u64)?;
+        Ok(())
+    }
+
+    pub fn append(&mut self, line: &TranscriptLine) -> Result<()> {
+        // One write per line keeps concurrent appends from interleaving
+        let mut json_line = serde_json::to_string(line)?;
+        json_line.push('\n');
+
+        self.file.write_all(json_line.as_bytes())
+            .map_err(|e| anyhow!("Failed to write to transcript file {:?}: {}", self.path, e))?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Read every line of a transcript. A missing file is an empty transcript.
+pub fn read_lines(path: &Path) -> Result<Vec<TranscriptLine>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+    let raw_lines: Vec<String> = reader.lines()
+        .collect::<std::io::Result<_>>()
+        .map_err(|e| anyhow!("Failed to read line: {}", e))?;
+    let raw_lines: Vec<&str> = raw_lines.iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut lines = Vec::new();
+    for (index, line) in raw_lines.iter().enumerate() {
+        match serde_json::from_str::<TranscriptLine>(line) {
+            Ok(transcript_line) => lines.push(transcript_line),
+            // A line cut short by a crash mid-write only ever sits at the end
+            Err(e) if index == raw_lines.len() - 1 => {
+                eprintln!("📄 Ignoring incomplete last transcript line in {:?}: {}", path, e);
+            }
+            Err(e) => return Err(anyhow!("Failed to parse transcript line: {}", e)),
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Replace a transcript wholesale.
+///
+/// The new content is written next to the file and renamed over it, so a
+/// failure never leaves half a transcript behind.
+pub fn write_lines(path: &Path, lines: &[TranscriptLine]) -> Result<()> {
+    let temp_path = path.with_extension("jsonl.tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for line in lines {
+            writeln!(writer, "{}", serde_json::to_string(line)?)?;
+        }
+        writer.flush()?;
+    }
+
+    crate::durable::replace(&temp_path, path)
+}
+
+/// Relabel every line spoken by `from` as `to`. Renaming onto a speaker that
+/// already exists merges the two. Returns how many lines changed.
+pub fn rename_speaker(path: &Path, from: &str, to: &str) -> Result<usize> {
+    let mut lines = read_lines(path)?;
+
+    let mut renamed = 0;
+    for line in lines.iter_mut().filter(|line| line.speaker == from) {
+        line.speaker = to.to_string();
+        renamed += 1;
+    }
+
+    if renamed > 0 {
+        write_lines(path, &lines)?;
+    }
+    Ok(renamed)
+}
+
+/// Index of the line being spoken `t_ms` into the recording: the last one to
+/// have started by then. Stays on a line through the pause after it.
+pub fn line_at(lines: &[TranscriptLine], t_ms: u64) -> Option<usize> {
+    lines.iter()
+        .enumerate()
+        .filter(|(_, line)| line.t_ms <= t_ms)
+        .max_by_key(|(index, line)| (line.t_ms, *index))
+        .map(|(index, _)| index)
+}
@@ -0,0 +1,478 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use crate::codec::AudioReader;
+use crate::models::TranscriptLine;
+
+/// Per-session VAD settings live next to the recording.
+pub const VAD_CONFIG_FILE: &str = "vad.json";
+
+// Loudness is judged over 10ms frames
+const FRAME_MS: u32 = 10;
+
+/// Voice activity detection thresholds for one session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VadConfig {
+    /// Frames louder than this (RMS, in dBFS) count as speech.
+    pub threshold_db: f32,
+    /// Sound has to last this long to start a speech segment, so clicks and coughs don't.
+    pub min_speech_ms: u32,
+    /// Quiet has to last this long to end a speech segment.
+    pub min_silence_ms: u32,
+    /// Audio kept before each speech segment so the first word isn't clipped.
+    pub padding_ms: u32,
+    /// Shorten long silences in `audio.wav` when the recording is stopped.
+    pub trim_silence: bool,
+    /// Length silences are shortened to when trimming.
+    pub max_silence_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        VadConfig {
+            threshold_db: -45.0,
+            min_speech_ms: 150,
+            min_silence_ms: 600,
+            padding_ms: 200,
+            trim_silence: false,
+            max_silence_ms: 2000,
+        }
+    }
+}
+
+impl VadConfig {
+    /// Read a session's settings, falling back to the defaults when it has none.
+    pub fn load(session_dir: &Path) -> Result<Self> {
+        let path = session_dir.join(VAD_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(VadConfig::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Failed to parse VAD settings {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, session_dir: &Path) -> Result<()> {
+        self.validate()?;
+        fs::create_dir_all(session_dir)?;
+        fs::write(session_dir.join(VAD_CONFIG_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(-100.0..=0.0).contains(&self.threshold_db) {
+            return Err(anyhow!("Threshold must be between -100 and 0 dBFS"));
+        }
+        if self.min_speech_ms < FRAME_MS || self.min_silence_ms < FRAME_MS {
+            return Err(anyhow!("Minimum speech and silence lengths must be at least {}ms", FRAME_MS));
+        }
+        // The quiet tail fed before a segment ends doubles as its trailing padding
+        if self.padding_ms > self.min_silence_ms {
+            return Err(anyhow!("Padding cannot be longer than the minimum silence"));
+        }
+        Ok(())
+    }
+}
+
+/// A change between speech and silence, as a sample position in the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    /// Speech started at this sample. Reported once it has lasted `min_speech_ms`.
+    SpeechStart(u64),
+    /// Speech ended after this sample. Reported once silence has lasted `min_silence_ms`.
+    SpeechEnd(u64),
+}
+
+/// Energy-based voice activity detector over a stream of mono samples.
+pub struct VoiceActivityDetector {
+    threshold_rms: f32,
+    min_speech_frames: u32,
+    min_silence_frames: u32,
+    frame_len: usize,
+    frame: Vec<f32>,
+    frames_seen: u64,
+    in_speech: bool,
+    // Consecutive frames that disagree with the current state, and where they began
+    run_frames: u32,
+    run_start_frame: u64,
+    last_voiced_frame_end: u64,
+}
+
+impl VoiceActivityDetector {
+    pub fn new(config: &VadConfig, sample_rate: u32) -> Self {
+        let frames = |ms: u32| (ms / FRAME_MS).max(1);
+        let frame_len = (sample_rate * FRAME_MS / 1000).max(1) as usize;
+
+        VoiceActivityDetector {
+            threshold_rms: 10f32.powf(config.threshold_db / 20.0),
+            min_speech_frames: frames(config.min_speech_ms),
+            min_silence_frames: frames(config.min_silence_ms),
+            frame_len,
+            frame: Vec::with_capacity(frame_len),
+            frames_seen: 0,
+            in_speech: false,
+            run_frames: 0,
+            run_start_frame: 0,
+            last_voiced_frame_end: 0,
+        }
+    }
+
+    pub fn in_speech(&self) -> bool {
+        self.in_speech
+    }
+
+    // How many more samples complete the frame being collected
+    fn samples_to_frame_end(&self) -> usize {
+        self.frame_len - self.frame.len()
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<VadEvent> {
+        let mut events = Vec::new();
+        let mut rest = samples;
+
+        while !rest.is_empty() {
+            let take = self.samples_to_frame_end().min(rest.len());
+            self.frame.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+
+            if self.frame.len() == self.frame_len {
+                let voiced = rms(&self.frame) >= self.threshold_rms;
+                self.frame.clear();
+                events.extend(self.process_frame(voiced));
+            }
+        }
+
+        events
+    }
+
+    fn process_frame(&mut self, voiced: bool) -> Option<VadEvent> {
+        let frame = self.frames_seen;
+        self.frames_seen += 1;
+        let frame_len = self.frame_len as u64;
+
+        if voiced {
+            self.last_voiced_frame_end = frame + 1;
+        }
+
+        if self.in_speech == voiced {
+            self.run_frames = 0;
+            return None;
+        }
+
+        if self.run_frames == 0 {
+            self.run_start_frame = frame;
+        }
+        self.run_frames += 1;
+
+        if self.in_speech && self.run_frames >= self.min_silence_frames {
+            self.in_speech = false;
+            self.run_frames = 0;
+            return Some(VadEvent::SpeechEnd(self.last_voiced_frame_end * frame_len));
+        }
+        if !self.in_speech && self.run_frames >= self.min_speech_frames {
+            self.in_speech = true;
+            self.run_frames = 0;
+            return Some(VadEvent::SpeechStart(self.run_start_frame * frame_len));
+        }
+
+        None
+    }
+
+    /// End of the stream: closes a speech segment that is still open.
+    pub fn finish(&mut self) -> Option<VadEvent> {
+        self.frame.clear();
+        self.run_frames = 0;
+        if !self.in_speech {
+            return None;
+        }
+        self.in_speech = false;
+        Some(VadEvent::SpeechEnd(self.last_voiced_frame_end * self.frame_len as u64))
+    }
+}
+
+/// What to do with the audio a [`SpeechGate`] was given.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateAction {
+    /// Pass these samples to the speech engine.
+    Feed(Vec<f32>),
+    /// This many samples of silence were dropped; the engine's clock must move past them.
+    Skip(usize),
+    /// A speech segment is over; the engine should recognize what it has buffered.
+    EndOfSpeech,
+}
+
+/// Sits in front of a speech engine so that it only hears speech.
+///
+/// While nobody speaks, the last few hundred milliseconds are held back.
+/// When speech starts they are released together with `padding_ms` of lead-in
+/// and everything older is skipped; once speech ends the engine is told to
+/// finish the utterance.
+pub struct SpeechGate {
+    detector: VoiceActivityDetector,
+    padding_samples: u64,
+    max_pending: usize,
+    pending: VecDeque<f32>,
+    pending_start: u64,
+    position: u64,
+}
+
+impl SpeechGate {
+    pub fn new(config: &VadConfig, sample_rate: u32) -> Self {
+        let detector = VoiceActivityDetector::new(config, sample_rate);
+        let samples = |ms: u32| ms as u64 * sample_rate as u64 / 1000;
+        let padding_samples = samples(config.padding_ms);
+        // Enough to reach back from detection to the speech onset plus padding
+        let max_pending = (padding_samples + samples(config.min_speech_ms)) as usize + 2 * detector.frame_len;
+
+        SpeechGate {
+            detector,
+            padding_samples,
+            max_pending,
+            pending: VecDeque::new(),
+            pending_start: 0,
+            position: 0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<GateAction> {
+        let mut actions = Vec::new();
+        let mut rest = samples;
+
+        // Frame by frame, so every event applies to exactly the audio before it
+        while !rest.is_empty() {
+            let take = self.detector.samples_to_frame_end().min(rest.len());
+            let piece = &rest[..take];
+            rest = &rest[take..];
+
+            if self.detector.in_speech() {
+                push_action(&mut actions, GateAction::Feed(piece.to_vec()));
+            } else {
+                self.pending.extend(piece);
+            }
+            self.position += take as u64;
+
+            for event in self.detector.process(piece) {
+                match event {
+                    VadEvent::SpeechStart(onset) => {
+                        let keep_from = onset.saturating_sub(self.padding_samples).max(self.pending_start);
+                        let skip = ((keep_from - self.pending_start) as usize).min(self.pending.len());
+                        self.pending.drain(..skip);
+                        push_action(&mut actions, GateAction::Skip(skip));
+                        push_action(&mut actions, GateAction::Feed(self.pending.drain(..).collect()));
+                    }
+                    VadEvent::SpeechEnd(_) => {
+                        actions.push(GateAction::EndOfSpeech);
+                        self.pending_start = self.position;
+                    }
+                }
+            }
+
+            if !self.detector.in_speech() && self.pending.len() > self.max_pending {
+                let overflow = self.pending.len() - self.max_pending;
+                self.pending.drain(..overflow);
+                self.pending_start += overflow as u64;
+                push_action(&mut actions, GateAction::Skip(overflow));
+            }
+        }
+
+        actions
+    }
+
+    /// End of the stream: finishes an utterance that is still going.
+    pub fn finish(&mut self) -> Vec<GateAction> {
+        match self.detector.finish() {
+            Some(_) => vec![GateAction::EndOfSpeech],
+            None => Vec::new(),
+        }
+    }
+}
+
+// Merge with the previous action where possible to keep the list short
+fn push_action(actions: &mut Vec<GateAction>, action: GateAction) {
+    match (actions.last_mut(), action) {
+        (_, GateAction::Skip(0)) => {}
+        (_, GateAction::Feed(samples)) if samples.is_empty() => {}
+        (Some(GateAction::Skip(total)), GateAction::Skip(n)) => *total += n,
+        (Some(GateAction::Feed(buffer)), GateAction::Feed(samples)) => buffer.extend(samples),
+        (_, action) => actions.push(action),
+    }
+}
+
+/// A stretch of the recording without speech.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SilenceSpan {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl SilenceSpan {
+    pub fn duration_ms(&self) -> u64 {
+        self.end_ms - self.start_ms
+    }
+}
+
+/// Find the silences in a recording that last at least `min_silence_ms`,
+/// including any before the first and after the last word.
+pub fn find_silences(audio_path: &Path, config: &VadConfig) -> Result<Vec<SilenceSpan>> {
+    let (sample_rate, spans) = silent_frame_ranges(audio_path, config)?;
+    let to_ms = |frames: u64| frames * 1000 / sample_rate as u64;
+
+    Ok(spans.into_iter()
+        .map(|(start, end)| SilenceSpan { start_ms: to_ms(start), end_ms: to_ms(end) })
+        .filter(|span| span.duration_ms() >= config.min_silence_ms as u64)
+        .collect())
+}
+
+// Silent stretches as frame ranges, plus the file's sample rate
+fn silent_frame_ranges(audio_path: &Path, config: &VadConfig) -> Result<(u32, Vec<(u64, u64)>)> {
+    let mut samples = AudioReader::open(audio_path)
+        .map_err(|e| anyhow!("Failed to open audio file {:?}: {}", audio_path, e))?;
+    let info = samples.info();
+    let channels = info.channels.max(1) as usize;
+    let total_frames = info.frames;
+
+    let mut detector = VoiceActivityDetector::new(config, info.sample_rate);
+    let mut events = Vec::new();
+    let chunk_len = info.sample_rate as usize * channels;
+    let mut chunk = Vec::with_capacity(chunk_len);
+
+    loop {
+        chunk.clear();
+        for sample in samples.by_ref().take(chunk_len) {
+            chunk.push(sample?);
+        }
+        if chunk.is_empty() {
+            break;
+        }
+
+        let mono: Vec<f32> = chunk.chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        events.extend(detector.process(&mono));
+    }
+    events.extend(detector.finish());
+
+    let mut spans = Vec::new();
+    let mut silence_start = 0;
+    for event in events {
+        match event {
+            VadEvent::SpeechStart(at) => {
+                if at > silence_start {
+                    spans.push((silence_start, at));
+                }
+            }
+            VadEvent::SpeechEnd(at) => silence_start = at,
+        }
+    }
+    if total_frames > silence_start {
+        spans.push((silence_start, total_frames));
+    }
+
+    Ok((info.sample_rate, spans))
+}
+
+/// A piece of audio removed from a recording by [`trim_long_silences`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilenceCut {
+    /// Where the removed audio started, in the untrimmed recording.
+    pub at_ms: u64,
+    pub removed_ms: u64,
+}
+
+/// Shorten every silence longer than `max_silence_ms` to that length by
+/// cutting out its middle, rewriting the file in place.
+///
+/// Returns what was removed so timestamps can be moved with [`shift_transcript`].
+pub fn trim_long_silences(audio_path: &Path, config: &VadConfig) -> Result<Vec<SilenceCut>> {
+    let (sample_rate, spans) = silent_frame_ranges(audio_path, config)?;
+    let keep_each_side = config.max_silence_ms as u64 * sample_rate as u64 / 1000 / 2;
+
+    let cuts: Vec<(u64, u64)> = spans.into_iter()
+        .filter(|(start, end)| end - start > 2 * keep_each_side)
+        .map(|(start, end)| (start + keep_each_side, end - keep_each_side))
+        .collect();
+    if cuts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut reader = hound::WavReader::open(audio_path)?;
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as u64;
+    let temp_path = audio_path.with_extension("wav.tmp");
+    {
+        let mut writer = hound::WavWriter::create(&temp_path, spec)?;
+
+        // Cuts are in order, so only the next one can matter
+        let mut next_cut = 0;
+        let mut keep = |sample_index: usize| {
+            let frame = sample_index as u64 / channels;
+            while next_cut < cuts.len() && frame >= cuts[next_cut].1 {
+                next_cut += 1;
+            }
+            next_cut == cuts.len() || frame < cuts[next_cut].0
+        };
+
+        match spec.sample_format {
+            hound::SampleFormat::Float => {
+                for (i, sample) in reader.samples::<f32>().enumerate() {
+                    let sample = sample?;
+                    if keep(i) {
+                        writer.write_sample(sample)?;
+                    }
+                }
+            }
+            hound::SampleFormat::Int => {
+                for (i, sample) in reader.samples::<i32>().enumerate() {
+                    let sample = sample?;
+                    if keep(i) {
+                        writer.write_sample(sample)?;
+                    }
+                }
+            }
+        }
+        writer.finalize()?;
+    }
+    crate::durable::replace(&temp_path, audio_path)?;
+
+    let to_ms = |frames: u64| frames * 1000 / sample_rate as u64;
+    Ok(cuts.into_iter()
+        .map(|(start, end)| SilenceCut { at_ms: to_ms(start), removed_ms: to_ms(end) - to_ms(start) })
+        .collect())
+}
+
+/// Move transcript timestamps to where they are after `cuts` were removed from the audio.
+pub fn shift_transcript(lines: &mut [TranscriptLine], cuts: &[SilenceCut]) {
+    let shift = |t_ms: u64| {
+        let mut removed = 0;
+        for cut in cuts {
+            if t_ms >= cut.at_ms + cut.removed_ms {
+                removed += cut.removed_ms;
+            } else if t_ms > cut.at_ms {
+                // Inside removed audio: snap to the cut
+                removed += t_ms - cut.at_ms;
+            }
+        }
+        t_ms - removed
+    };
+
+    for line in lines {
+        line.t_ms = shift(line.t_ms);
+        line.end_ms = line.end_ms.map(shift);
+        for word in &mut line.words {
+            word.t_ms = shift(word.t_ms);
+            word.end_ms = shift(word.end_ms);
+        }
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum_squares: f32 = samples.iter().map(|&x| x * x).sum();
+    (sum_squares / samples.len() as f32).sqrt()
+}
@@ -0,0 +1,195 @@
+use anyhow::{Result, anyhow};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Where the sample data of a WAV file is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataChunk {
+    /// Offset of the chunk's size field.
+    pub size_offset: u64,
+    /// Offset of the first sample byte.
+    pub data_offset: u64,
+    /// Length of the sample data in bytes, as the header states it.
+    pub data_len: u64,
+    /// Bytes per frame, from the `fmt ` chunk.
+    pub block_align: u16,
+}
+
+/// Walk the RIFF chunks of a WAV file up to its `data` chunk.
+pub fn find_data_chunk<R: Read + Seek>(reader: &mut R) -> Result<DataChunk> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+        return Err(anyhow!("Not a WAV file"));
+    }
+
+    let mut offset = 12u64;
+    let mut block_align = None;
+    loop {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)
+            .map_err(|_| anyhow!("WAV file has no data chunk"))?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        match &header[..4] {
+            b"fmt " if len >= 14 => {
+                let mut format = [0u8; 14];
+                reader.read_exact(&mut format)?;
+                block_align = Some(u16::from_le_bytes([format[12], format[13]]).max(1));
+            }
+            b"data" => {
+                let block_align = block_align.ok_or_else(|| anyhow!("WAV file has no format before its data"))?;
+                return Ok(DataChunk { size_offset: offset + 4, data_offset: offset + 8, data_len: len, block_align });
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        offset += 8 + len + (len & 1);
+        reader.seek(SeekFrom::Start(offset))?;
+    }
+}
+
+/// Append the sample data of `segment_path` to `main_path` in place.
+///
+/// Only the new audio is copied and the two size fields in the main file's
+/// header are patched afterwards, so the cost doesn't grow with the length
+/// of the recording. Both files must have the same format, and the main
+/// file's data chunk must be its last chunk, which is how hound writes them.
+/// If this is interrupted, the header still describes the audio from before.
+pub fn append_data(main_path: &Path, segment_path: &Path) -> Result<()> {
+    let mut segment = File::open(segment_path)?;
+    let segment_chunk = find_data_chunk(&mut segment)?;
+    // Never read past the end, in case the segment's header overstates its data
+    let segment_len = segment_chunk.data_len
+        .min(segment.metadata()?.len().saturating_sub(segment_chunk.data_offset));
+
+    let mut main = OpenOptions::new().read(true).write(true).open(main_path)?;
+    let main_chunk = find_data_chunk(&mut main)?;
+    let data_end = main_chunk.data_offset + main_chunk.data_len;
+    let padded_end = data_end + (main_chunk.data_len & 1);
+    if padded_end < main.metadata()?.len() {
+        return Err(anyhow!("Audio file {:?} has chunks after its data, can't append in place", main_path));
+    }
+
+    let new_len = main_chunk.data_len + segment_len;
+    let file_len = main_chunk.data_offset + new_len + (new_len & 1);
+    if file_len - 8 > u32::MAX as u64 {
+        return Err(anyhow!("Audio file {:?} would grow beyond the 4GB WAV limit", main_path));
+    }
+
+    // New audio goes where the old data ended, over its padding byte if it had one
+    main.seek(SeekFrom::Start(data_end))?;
+    segment.seek(SeekFrom::Start(segment_chunk.data_offset))?;
+    {
+        let mut writer = BufWriter::new(&mut main);
+        let copied = io::copy(&mut (&mut segment).take(segment_len), &mut writer)?;
+        if copied != segment_len {
+            return Err(anyhow!("Segment {:?} ended early", segment_path));
+        }
+        writer.flush()?;
+    }
+    set_data_len(&mut main, &main_chunk, new_len)
+}
+
+/// Make the header of a WAV file whose writer never finished (e.g. the app
+/// died while recording) describe the audio that reached the disk. Returns
+/// the number of frames recovered.
+pub fn repair_header(path: &Path) -> Result<u64> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let chunk = find_data_chunk(&mut file)?;
+    let block_align = chunk.block_align as u64;
+
+    // Whole frames only, and no more than the header can describe
+    let available = file.metadata()?.len().saturating_sub(chunk.data_offset)
+        .min((u32::MAX as u64 - chunk.data_offset) & !1);
+    let data_len = available / block_align * block_align;
+    set_data_len(&mut file, &chunk, data_len)?;
+    Ok(data_len / block_align)
+}
+
+/// Cut anything past the data chunk a finished WAV file's header describes,
+/// such as audio from an append that was interrupted before the header was
+/// patched. Returns whether there was anything to cut.
+pub fn trim_to_header(path: &Path) -> Result<bool> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let chunk = find_data_chunk(&mut file)?;
+    let padded_end = chunk.data_offset + chunk.data_len + (chunk.data_len & 1);
+    if file.metadata()?.len() <= padded_end {
+        return Ok(false);
+    }
+    file.set_len(padded_end)?;
+    file.sync_data()?;
+    Ok(true)
+}
+
+/// Record how much audio `main_path` holds before `segment_path` is appended
+/// to it. The record stays until [`finish_append`], after the segment is
+/// deleted, so recovery can tell an append that still needs its segment from
+/// one whose header was already patched.
+pub fn mark_append(main_path: &Path, segment_path: &Path) -> Result<()> {
+    let mut main = File::open(main_path)?;
+    let chunk = find_data_chunk(&mut main)?;
+    let segment_name = segment_path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Invalid segment path {:?}", segment_path))?;
+
+    let marker_path = append_marker_path(main_path);
+    let mut marker = File::create(&marker_path)?;
+    writeln!(marker, "{}\n{}", segment_name, chunk.data_len)?;
+    marker.sync_all()?;
+    crate::durable::sync_parent(&marker_path)
+}
+
+/// Drop the record [`mark_append`] left, once its segment is gone for good.
+pub fn finish_append(main_path: &Path) -> Result<()> {
+    let marker_path = append_marker_path(main_path);
+    fs::remove_file(&marker_path)?;
+    crate::durable::sync_parent(&marker_path)
+}
+
+/// Cut `main_path` back to what it held before an append that never got to
+/// delete its segment, so the segment can be appended again exactly once.
+/// Returns whether anything was cut.
+pub fn undo_unfinished_append(main_path: &Path) -> Result<bool> {
+    let marker_path = append_marker_path(main_path);
+    let Ok(marker) = fs::read_to_string(&marker_path) else {
+        return Ok(false);
+    };
+
+    // A marker that didn't make it to the disk whole was written before the append started
+    let mut undone = false;
+    let mut fields = marker.lines();
+    if let (Some(segment_name), Some(Ok(data_len))) = (fields.next(), fields.next().map(str::parse::<u64>)) {
+        if main_path.with_file_name(segment_name).exists() && main_path.exists() {
+            let mut file = OpenOptions::new().read(true).write(true).open(main_path)?;
+            let chunk = find_data_chunk(&mut file)?;
+            set_data_len(&mut file, &chunk, data_len.min(u32::MAX as u64))?;
+            undone = true;
+        }
+    }
+    finish_append(main_path)?;
+    Ok(undone)
+}
+
+fn append_marker_path(main_path: &Path) -> PathBuf {
+    main_path.with_extension("wav.appending")
+}
+
+// Resize the data chunk to `data_len` bytes, which must all be in place
+// already, and patch both size fields in the header to match
+fn set_data_len(file: &mut File, chunk: &DataChunk, data_len: u64) -> Result<()> {
+    // Chunks are padded to an even length, the padding byte is zero
+    let file_len = chunk.data_offset + data_len + (data_len & 1);
+    file.set_len(file_len)?;
+
+    // The audio has to be on disk before the header claims it
+    file.sync_data()?;
+    file.seek(SeekFrom::Start(chunk.size_offset))?;
+    file.write_all(&(data_len as u32).to_le_bytes())?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((file_len - 8) as u32).to_le_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
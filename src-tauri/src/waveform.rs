@@ -0,0 +1,206 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use crate::codec::AudioReader;
+
+/// Peaks of a session's recording are cached next to it.
+pub const WAVEFORM_FILE: &str = "waveform.peaks";
+
+// Resolution of the cached peaks; anything the UI asks for is merged from these
+const PEAK_MS: u32 = 10;
+
+const MAGIC: &[u8; 4] = b"PKWF";
+const VERSION: u32 = 1;
+
+/// Smallest and largest sample of every `frames_per_peak` frames of a
+/// recording, across all of its channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    pub sample_rate: u32,
+    pub frames_per_peak: u32,
+    /// Length of the recording in samples per channel.
+    pub frames: u64,
+    pub peaks: Vec<(f32, f32)>,
+}
+
+/// Waveform ready to draw: `min[i]` and `max[i]` cover the i-th of
+/// `min.len()` equal slices of the recording.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaveformPeaks {
+    pub duration_ms: u64,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl Waveform {
+    /// Read the whole recording and collect its peaks.
+    pub fn compute(audio_path: &Path) -> Result<Self> {
+        let mut samples = AudioReader::open(audio_path)
+            .map_err(|e| anyhow!("Failed to open audio file {:?}: {}", audio_path, e))?;
+        let info = samples.info();
+        let channels = info.channels.max(1) as usize;
+        let frames_per_peak = (info.sample_rate * PEAK_MS / 1000).max(1);
+        let peak_len = frames_per_peak as usize * channels;
+
+        let mut peaks = Vec::with_capacity((info.frames / frames_per_peak as u64 + 1) as usize);
+        let mut frames = 0;
+        loop {
+            let mut peak = (f32::MAX, f32::MIN);
+            let mut read = 0;
+            for sample in samples.by_ref().take(peak_len) {
+                let sample = sample?;
+                peak = (peak.0.min(sample), peak.1.max(sample));
+                read += 1;
+            }
+            if read == 0 {
+                break;
+            }
+            frames += (read / channels) as u64;
+            peaks.push(peak);
+        }
+
+        Ok(Waveform { sample_rate: info.sample_rate, frames_per_peak, frames, peaks })
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.frames * 1000 / self.sample_rate.max(1) as u64
+    }
+
+    /// Merge the peaks down to at most `buckets` of them.
+    pub fn downsample(&self, buckets: usize) -> WaveformPeaks {
+        let count = self.peaks.len();
+        let buckets = buckets.clamp(1, count.max(1));
+        let mut min = Vec::with_capacity(buckets);
+        let mut max = Vec::with_capacity(buckets);
+
+        for bucket in 0..buckets.min(count) {
+            let range = &self.peaks[bucket * count / buckets..(bucket + 1) * count / buckets];
+            let (low, high) = range.iter().fold((f32::MAX, f32::MIN), |(low, high), &(peak_low, peak_high)| {
+                (low.min(peak_low), high.max(peak_high))
+            });
+            min.push(low);
+            max.push(high);
+        }
+
+        WaveformPeaks { duration_ms: self.duration_ms(), min, max }
+    }
+
+    // Peaks are stored as 16-bit pairs after a header naming the audio they
+    // were taken from, all little-endian
+    fn write(&self, path: &Path, source: SourceStamp) -> Result<()> {
+        let temp_path = path.with_extension("peaks.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&VERSION.to_le_bytes())?;
+            writer.write_all(&source.len.to_le_bytes())?;
+            writer.write_all(&source.modified_ns.to_le_bytes())?;
+            writer.write_all(&self.sample_rate.to_le_bytes())?;
+            writer.write_all(&self.frames_per_peak.to_le_bytes())?;
+            writer.write_all(&self.frames.to_le_bytes())?;
+            writer.write_all(&(self.peaks.len() as u64).to_le_bytes())?;
+            for &(low, high) in &self.peaks {
+                writer.write_all(&to_i16(low).to_le_bytes())?;
+                writer.write_all(&to_i16(high).to_le_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    // The cached peaks, unless they were taken from a different version of the audio
+    fn read(path: &Path, source: SourceStamp) -> Result<Option<Self>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+            return Ok(None);
+        }
+        let cached_source = SourceStamp { len: read_u64(&mut reader)?, modified_ns: read_u64(&mut reader)? };
+        if cached_source != source {
+            return Ok(None);
+        }
+
+        let sample_rate = read_u32(&mut reader)?;
+        let frames_per_peak = read_u32(&mut reader)?;
+        let frames = read_u64(&mut reader)?;
+        let count = read_u64(&mut reader)? as usize;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.len() != count * 4 {
+            return Err(anyhow!("Waveform file {:?} is truncated", path));
+        }
+        let peaks = data.chunks_exact(4)
+            .map(|pair| (from_i16([pair[0], pair[1]]), from_i16([pair[2], pair[3]])))
+            .collect();
+
+        Ok(Some(Waveform { sample_rate, frames_per_peak, frames, peaks }))
+    }
+}
+
+/// The peaks of a session's recording, from the cache in `session_dir` when it
+/// was made from this exact file and computed (and cached) otherwise.
+pub fn load(session_dir: &Path, audio_path: &Path) -> Result<Waveform> {
+    let cache_path = session_dir.join(WAVEFORM_FILE);
+    let source = SourceStamp::of(audio_path)?;
+
+    if cache_path.exists() {
+        match Waveform::read(&cache_path, source) {
+            Ok(Some(waveform)) => return Ok(waveform),
+            Ok(None) => println!("📈 Waveform of {:?} is out of date, recomputing", audio_path),
+            Err(e) => eprintln!("📈 Ignoring unreadable waveform file {:?}: {}", cache_path, e),
+        }
+    }
+
+    let waveform = Waveform::compute(audio_path)?;
+    // A failed write only costs recomputing next time
+    if let Err(e) = waveform.write(&cache_path, source) {
+        eprintln!("📈 Failed to cache waveform in {:?}: {}", cache_path, e);
+    }
+    Ok(waveform)
+}
+
+// Identifies the version of a recording peaks were taken from; recording
+// more, trimming or converting it all change one of these
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceStamp {
+    len: u64,
+    modified_ns: u64,
+}
+
+impl SourceStamp {
+    fn of(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified_ns = metadata.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(SourceStamp { len: metadata.len(), modified_ns })
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+fn from_i16(bytes: [u8; 2]) -> f32 {
+    i16::from_le_bytes(bytes) as f32 / i16::MAX as f32
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,305 @@
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState};
+use crate::models::TranscriptWord;
+use crate::speech::{SpeechEngine, SpeechSegment, SPEECH_SAMPLE_RATE};
+
+/// Sample rate whisper.cpp models expect their input at.
+pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+// Engines are fed at SPEECH_SAMPLE_RATE, which has to be what whisper takes
+const _: () = assert!(SPEECH_SAMPLE_RATE == WHISPER_SAMPLE_RATE);
+
+// Longest stretch of audio handed to the model in one go
+const MAX_WINDOW_MS: u64 = 15_000;
+
+// Models picked automatically from ~/.polka/models, in order of preference
+const PREFERRED_MODELS: &[&str] = &[
+    "ggml-base.en.bin",
+    "ggml-small.en.bin",
+    "ggml-base.bin",
+    "ggml-small.bin",
+    "ggml-tiny.en.bin",
+    "ggml-tiny.bin",
+];
+
+// Loaded models are shared between recordings, loading one takes a while
+static LOADED_MODELS: Lazy<Mutex<HashMap<PathBuf, Arc<WhisperContext>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn models_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
+    Ok(home.join(".polka").join("models"))
+}
+
+/// Locate a GGML Whisper model in `~/.polka/models`.
+pub fn find_model() -> Result<PathBuf> {
+    let dir = models_dir()?;
+
+    for name in PREFERRED_MODELS {
+        let path = dir.join(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+
+    // Fall back to any other GGML model the user dropped in
+    let mut candidates: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|_| anyhow!("No speech model directory at {:?}", dir))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            name.starts_with("ggml-") && name.ends_with(".bin")
+        })
+        .collect();
+    candidates.sort();
+
+    candidates.into_iter().next()
+        .ok_or_else(|| anyhow!("No Whisper model found in {:?}", dir))
+}
+
+fn load_context(path: &Path) -> Result<Arc<WhisperContext>> {
+    let mut models = LOADED_MODELS.lock().unwrap();
+    if let Some(context) = models.get(path) {
+        return Ok(Arc::clone(context));
+    }
+
+    println!("🎤 Loading Whisper model: {:?}", path);
+    let path_str = path.to_str().ok_or_else(|| anyhow!("Model path is not valid UTF-8: {:?}", path))?;
+    let context = WhisperContext::new_with_params(path_str, WhisperContextParameters::default())
+        .map_err(|e| anyhow!("Failed to load Whisper model {:?}: {}", path, e))?;
+
+    let context = Arc::new(context);
+    models.insert(path.to_path_buf(), Arc::clone(&context));
+    Ok(context)
+}
+
+/// Fully offline engine running a whisper.cpp model.
+///
+/// Whisper is not a streaming model, so incoming audio is buffered and
+/// transcribed when the utterance ends and the engine is flushed (or after
+/// `MAX_WINDOW_MS` at the latest).
+pub struct WhisperSpeechEngine {
+    context: Arc<WhisperContext>,
+    state: Option<WhisperState>,
+    language: &'static str,
+    // 16kHz samples not yet transcribed and where they start on the timeline
+    buffer: Vec<f32>,
+    buffer_start_sample: u64,
+    next_utterance_id: u64,
+}
+
+impl WhisperSpeechEngine {
+    pub fn from_default_model() -> Result<Self> {
+        Self::from_model(&find_model()?)
+    }
+
+    pub fn from_model(path: &Path) -> Result<Self> {
+        let context = load_context(path)?;
+
+        // English-only models reject any other language setting
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let language = if file_name.contains(".en.") { "en" } else { "auto" };
+
+        Ok(WhisperSpeechEngine {
+            context,
+            state: None,
+            language,
+            buffer: Vec::new(),
+            buffer_start_sample: 0,
+            next_utterance_id: 0,
+        })
+    }
+
+    fn buffered_ms(&self) -> u64 {
+        self.buffer.len() as u64 * 1000 / WHISPER_SAMPLE_RATE as u64
+    }
+
+    fn transcribe_buffer(&mut self) -> Result<Vec<SpeechSegment>> {
+        let window = std::mem::take(&mut self.buffer);
+        let offset_ms = self.buffer_start_sample * 1000 / WHISPER_SAMPLE_RATE as u64;
+        self.buffer_start_sample += window.len() as u64;
+
+        if window.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.transcribe(&window, offset_ms)
+    }
+
+    /// Run the model over 16kHz mono audio starting `offset_ms` into the recording.
+    pub fn transcribe(&mut self, pcm: &[f32], offset_ms: u64) -> Result<Vec<SpeechSegment>> {
+        if self.state.is_none() {
+            self.state = Some(self.context.create_state()
+                .map_err(|e| anyhow!("Failed to create Whisper state: {}", e))?);
+        }
+        let state = self.state.as_mut().unwrap();
+
+        // whisper.cpp refuses inputs shorter than a second; trailing zeros don't move timestamps
+        let min_samples = WHISPER_SAMPLE_RATE as usize;
+        let padded;
+        let pcm = if pcm.len() < min_samples {
+            padded = [pcm, &vec![0.0; min_samples - pcm.len()]].concat();
+            &padded[..]
+        } else {
+            pcm
+        };
+
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(Some(self.language));
+        params.set_n_threads(inference_threads());
+        params.set_no_context(true);
+        params.set_token_timestamps(true);
+        params.set_suppress_blank(true);
+        params.set_suppress_nst(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+
+        state.full(params, pcm)
+            .map_err(|e| anyhow!("Whisper inference failed: {}", e))?;
+
+        let segment_count = state.full_n_segments()
+            .map_err(|e| anyhow!("Failed to read Whisper segments: {}", e))?;
+
+        // Token ids from end-of-text upwards are control tokens, not text
+        let first_special_token = self.context.token_eot();
+
+        let mut segments = Vec::new();
+        for i in 0..segment_count {
+            let text = state.full_get_segment_text_lossy(i)
+                .map_err(|e| anyhow!("Failed to read Whisper segment text: {}", e))?;
+            let text = text.trim();
+            if text.is_empty() || is_non_speech_annotation(text) {
+                continue;
+            }
+
+            // Whisper reports times in 10ms units
+            let t0 = state.full_get_segment_t0(i).unwrap_or(0).max(0) as u64;
+            let t1 = state.full_get_segment_t1(i).unwrap_or(0).max(0) as u64;
+
+            let mut words: Vec<TranscriptWord> = Vec::new();
+            let mut word_probabilities: Vec<Vec<f32>> = Vec::new();
+            let token_count = state.full_n_tokens(i).unwrap_or(0);
+            for j in 0..token_count {
+                let token_id = state.full_get_token_id(i, j).unwrap_or(first_special_token);
+                if token_id >= first_special_token {
+                    continue;
+                }
+                let (Ok(token_text), Ok(data)) = (state.full_get_token_text_lossy(i, j), state.full_get_token_data(i, j)) else {
+                    continue;
+                };
+
+                let token_start = offset_ms + data.t0.max(0) as u64 * 10;
+                let token_end = offset_ms + data.t1.max(data.t0).max(0) as u64 * 10;
+
+                // Tokens starting with a space begin a new word, the rest continue the previous one
+                match words.last_mut() {
+                    Some(word) if !token_text.starts_with(' ') => {
+                        word.text.push_str(&token_text);
+                        word.end_ms = token_end;
+                        if let Some(probabilities) = word_probabilities.last_mut() {
+                            probabilities.push(data.p);
+                        }
+                    }
+                    _ => {
+                        let word_text = token_text.trim();
+                        if word_text.is_empty() {
+                            continue;
+                        }
+                        words.push(TranscriptWord {
+                            text: word_text.to_string(),
+                            t_ms: token_start,
+                            end_ms: token_end,
+                            confidence: None,
+                        });
+                        word_probabilities.push(vec![data.p]);
+                    }
+                }
+            }
+
+            for (word, probabilities) in words.iter_mut().zip(&word_probabilities) {
+                word.confidence = Some(mean(probabilities));
+            }
+            let all_probabilities: Vec<f32> = word_probabilities.concat();
+            let confidence = if all_probabilities.is_empty() { None } else { Some(mean(&all_probabilities)) };
+
+            // Whisper doesn't revise its output, so every segment is final
+            self.next_utterance_id += 1;
+            segments.push(SpeechSegment {
+                utterance_id: self.next_utterance_id,
+                is_final: true,
+                text: text.to_string(),
+                start_ms: offset_ms + t0 * 10,
+                end_ms: offset_ms + t1.max(t0) * 10,
+                confidence,
+                words,
+            });
+        }
+
+        Ok(segments)
+    }
+}
+
+impl SpeechEngine for WhisperSpeechEngine {
+    fn name(&self) -> &'static str {
+        "whisper"
+    }
+
+    fn start(&mut self, sample_rate: u32) -> Result<()> {
+        if sample_rate != WHISPER_SAMPLE_RATE {
+            return Err(anyhow!("Whisper needs {}Hz audio, got {}Hz", WHISPER_SAMPLE_RATE, sample_rate));
+        }
+
+        self.buffer.clear();
+        self.buffer_start_sample = 0;
+        self.next_utterance_id = 0;
+        Ok(())
+    }
+
+    fn feed(&mut self, samples: &[f32]) -> Result<Vec<SpeechSegment>> {
+        self.buffer.extend_from_slice(samples);
+
+        if self.buffered_ms() >= MAX_WINDOW_MS {
+            return self.transcribe_buffer();
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn flush(&mut self) -> Result<Vec<SpeechSegment>> {
+        self.transcribe_buffer()
+    }
+
+    fn skip(&mut self, samples: usize) {
+        // The buffer is empty after a flush, so only the clock needs to move on
+        self.buffer_start_sample += samples as u64;
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.buffer.clear();
+        self.state = None;
+        Ok(())
+    }
+}
+
+fn inference_threads() -> i32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get().min(8) as i32)
+        .unwrap_or(4)
+}
+
+// Whisper marks music, silence etc. as "[BLANK_AUDIO]", "(music)" and the like
+fn is_non_speech_annotation(text: &str) -> bool {
+    (text.starts_with('[') && text.ends_with(']')) || (text.starts_with('(') && text.ends_with(')'))
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len().max(1) as f32
+}
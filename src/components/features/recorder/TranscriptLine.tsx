@@ -6,17 +6,20 @@ import { formatTime } from '@/lib/utils';
 interface TranscriptLineProps {
   line: TranscriptLineData;
   isLatest?: boolean;
+  isActive?: boolean;
+  onClick?: (line: TranscriptLineData) => void;
 }
 
-export default function TranscriptLine({ line, isLatest = false }: TranscriptLineProps) {
+export default function TranscriptLine({ line, isLatest = false, isActive = false, onClick }: TranscriptLineProps) {
 
   return (
     <motion.div
       initial={{ opacity: 0, y: 10 }}
       animate={{ opacity: 1, y: 0 }}
+      onClick={onClick ? () => onClick(line) : undefined}
       className={`flex items-start gap-3 p-3 rounded-lg transition-colors ${
-        isLatest ? 'bg-blue-50 dark:bg-blue-950/20 border border-blue-200 dark:border-blue-800' : 'hover:bg-muted'
-      }`}
+        isLatest || isActive ? 'bg-blue-50 dark:bg-blue-950/20 border border-blue-200 dark:border-blue-800' : 'hover:bg-muted'
+      } ${onClick ? 'cursor-pointer' : ''}`}
     >
       <div className="flex items-center gap-2 text-sm text-muted-foreground min-w-[60px]">
         <Clock className="w-3 h-3" />
@@ -1,7 +1,9 @@
-import React, { useState } from 'react';
+import React, { useState, useEffect } from 'react';
 import { motion, AnimatePresence } from 'framer-motion';
 import { X } from 'lucide-react';
 import { Button, Input, Label, Card, CardContent, CardHeader, CardTitle } from '@/components';
+import { sessionsClient } from '@/lib/sessions';
+import { Course } from '@/types';
 
 interface CreateSessionModalProps {
   isOpen: boolean;
@@ -18,6 +20,15 @@ export const CreateSessionModal: React.FC<CreateSessionModalProps> = ({
 }) => {
   const [title, setTitle] = useState('');
   const [course, setCourse] = useState('');
+  const [courses, setCourses] = useState<Course[]>([]);
+
+  // Suggest existing courses so the same one isn't typed two ways
+  useEffect(() => {
+    if (!isOpen) return;
+    sessionsClient.listCourses()
+      .then(setCourses)
+      .catch((error) => console.error('Failed to load courses:', error));
+  }, [isOpen]);
 
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
@@ -88,8 +99,14 @@ export const CreateSessionModal: React.FC<CreateSessionModalProps> = ({
                       value={course}
                       onChange={(e) => setCourse(e.target.value)}
                       placeholder="Enter course name"
+                      list="course-options"
                       disabled={loading}
                     />
+                    <datalist id="course-options">
+                      {courses.map((c) => (
+                        <option key={c.id} value={c.name} />
+                      ))}
+                    </datalist>
                   </div>
                   
                   <div className="flex gap-3 pt-2">
@@ -1,6 +1,6 @@
 import React, { useState } from 'react';
 import { motion } from 'framer-motion';
-import { Clock, BookOpen, MoreVertical, Trash2, Check, Archive, Edit3 } from 'lucide-react';
+import { Clock, BookOpen, MoreVertical, Trash2, Check, Archive, Edit3, LifeBuoy } from 'lucide-react';
 import { Card, CardContent, CardHeader, CardTitle, Button, DropdownMenu, DropdownMenuContent, DropdownMenuItem, DropdownMenuSeparator, DropdownMenuSub, DropdownMenuSubContent, DropdownMenuSubTrigger, DropdownMenuTrigger } from '@/components';
 import DeleteConfirmationDialog from '@/components/features/common/DeleteConfirmationDialog';
 import { Session } from '@/types';
@@ -110,6 +110,15 @@ export const SessionCard: React.FC<SessionCardProps> = ({
                 </CardTitle>
               </div>
               <div className="flex items-center gap-2">
+                {session.recovered && (
+                  <span
+                    className="px-2 py-1 text-xs font-medium rounded-full flex items-center gap-1 bg-amber-100 text-amber-800"
+                    title="The app closed during this recording; the audio captured up to then was recovered"
+                  >
+                    <LifeBuoy className="w-3 h-3" />
+                    recovered
+                  </span>
+                )}
                 <span className={`px-2 py-1 text-xs font-medium rounded-full flex items-center gap-1 ${getStatusColor(session.status)}`}>
                   {getStatusIcon(session.status)}
                   {session.status}
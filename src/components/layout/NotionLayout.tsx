@@ -13,6 +13,8 @@ interface NotionLayoutProps {
   sessionTitle?: string;
   isFullscreen?: boolean;
   editorRef?: React.RefObject<HTMLDivElement | null>;
+  activeLineIndex?: number | null;
+  onLineClick?: (line: TranscriptLineData) => void;
 }
 
 export default function NotionLayout({
@@ -22,7 +24,9 @@ export default function NotionLayout({
   onNotesChange,
   sessionTitle = 'Notes',
   isFullscreen = false,
-  editorRef: externalEditorRef
+  editorRef: externalEditorRef,
+  activeLineIndex = null,
+  onLineClick
 }: NotionLayoutProps) {
   const [showTranscript, setShowTranscript] = useState(false);
   const internalEditorRef = useRef<HTMLDivElement>(null);
@@ -133,6 +137,8 @@ export default function NotionLayout({
                       <TranscriptLine
                         line={line}
                         isLatest={index === transcriptLines.length - 1 && isRecording}
+                        isActive={index === activeLineIndex}
+                        onClick={isRecording ? undefined : onLineClick}
                       />
                     </div>
                   ))}
@@ -1,9 +1,9 @@
 import { invoke } from '@tauri-apps/api/core';
-import { Session, CreateSessionRequest, UpdateSessionStatusRequest, TranscriptLine } from '@/types';
+import { Session, CreateSessionRequest, UpdateSessionStatusRequest, TranscriptLine, VadConfig, SilenceSpan, PlaybackState, WaveformPeaks, SearchHit, Course, CourseInput, CourseStats, Tag, SessionFilter } from '@/types';
 
 export const sessionsClient = {
-  async listSessions(): Promise<Session[]> {
-    return await invoke<Session[]>('cmd_list_sessions');
+  async listSessions(filter?: SessionFilter): Promise<Session[]> {
+    return await invoke<Session[]>('cmd_list_sessions', { filter });
   },
 
   async createSession(request: CreateSessionRequest): Promise<Session> {
@@ -14,37 +14,146 @@ export const sessionsClient = {
     await invoke('cmd_update_session_status', { ...request });
   },
 
+  // Pass null to file the session under no course
+  async setSessionCourse(id: string, courseId: string | null): Promise<void> {
+    await invoke('cmd_set_session_course', { id, courseId });
+  },
+
+  // Course operations
+  async listCourses(): Promise<Course[]> {
+    return await invoke<Course[]>('cmd_list_courses');
+  },
+
+  async createCourse(course: CourseInput): Promise<Course> {
+    return await invoke<Course>('cmd_create_course', { course });
+  },
+
+  async updateCourse(id: string, course: CourseInput): Promise<Course> {
+    return await invoke<Course>('cmd_update_course', { id, course });
+  },
+
+  // The course's sessions are kept, filed under no course
+  async deleteCourse(id: string): Promise<void> {
+    await invoke('cmd_delete_course', { id });
+  },
+
+  async listCourseStats(): Promise<CourseStats[]> {
+    return await invoke<CourseStats[]>('cmd_list_course_stats');
+  },
+
+  // Tag operations
+  async listTags(): Promise<Tag[]> {
+    return await invoke<Tag[]>('cmd_list_tags');
+  },
+
+  // Creates the tag the first time a name is used
+  async addSessionTag(id: string, name: string): Promise<Tag> {
+    return await invoke<Tag>('cmd_add_session_tag', { id, name });
+  },
+
+  async removeSessionTag(id: string, tagId: string): Promise<void> {
+    await invoke('cmd_remove_session_tag', { id, tagId });
+  },
+
+  // Renaming onto an existing tag merges the two; returns the tag that remains
+  async renameTag(id: string, name: string): Promise<Tag> {
+    return await invoke<Tag>('cmd_rename_tag', { id, name });
+  },
+
+  async mergeTags(fromId: string, intoId: string): Promise<void> {
+    await invoke('cmd_merge_tags', { fromId, intoId });
+  },
+
+  async deleteTag(id: string): Promise<void> {
+    await invoke('cmd_delete_tag', { id });
+  },
+
+  // Clears the "recovered" flag once the user has seen it
+  async dismissRecovery(id: string): Promise<void> {
+    await invoke('cmd_dismiss_recovery', { id });
+  },
+
   async deleteSession(id: string): Promise<void> {
     await invoke('cmd_delete_session', { id });
   },
 
   // Transcript operations
+  // Lines recognized during recording are saved by the backend; this is for lines added by hand.
   async appendTranscriptLine(id: string, t_ms: number, speaker: string, text: string): Promise<void> {
-    try {
-      await invoke('cmd_append_transcript_line', { id, tMs: t_ms, speaker, text });
-    } catch (error) {
-      console.error('Failed to save transcript line:', error);
-      
-      // Fallback to localStorage for development
-      const key = `transcript_${id}`;
-      const existing = JSON.parse(localStorage.getItem(key) || '[]');
-      existing.push({ t_ms, speaker, text });
-      localStorage.setItem(key, JSON.stringify(existing));
-    }
+    await invoke('cmd_append_transcript_line', { id, tMs: t_ms, speaker, text });
   },
 
   async readTranscript(id: string): Promise<TranscriptLine[]> {
-    try {
-      const result = await invoke<TranscriptLine[]>('cmd_read_transcript', { id });
-      return result;
-    } catch (error) {
-      console.error('Failed to read transcript:', error);
-      
-      // Fallback to localStorage for development
-      const key = `transcript_${id}`;
-      const existing = JSON.parse(localStorage.getItem(key) || '[]');
-      return existing;
-    }
+    return await invoke<TranscriptLine[]>('cmd_read_transcript', { id });
+  },
+
+  // Re-run speech recognition over a finished recording (progress arrives as events)
+  async transcribeSession(id: string): Promise<void> {
+    await invoke('cmd_transcribe_session', { id });
+  },
+
+  async cancelTranscription(id: string): Promise<void> {
+    await invoke('cmd_cancel_transcription', { id });
+  },
+
+  // Label transcript lines "Speaker 1", "Speaker 2", ... from the recording; returns the speaker count
+  async diarizeSession(id: string, numSpeakers?: number): Promise<number> {
+    return await invoke<number>('cmd_diarize_session', { id, numSpeakers });
+  },
+
+  // Returns how many lines were relabelled
+  async renameSpeaker(id: string, from: string, to: string): Promise<number> {
+    return await invoke<number>('cmd_rename_speaker', { id, from, to });
+  },
+
+  // Voice activity detection
+  async getVadConfig(id: string): Promise<VadConfig> {
+    return await invoke<VadConfig>('cmd_get_vad_config', { id });
+  },
+
+  async setVadConfig(id: string, config: VadConfig): Promise<void> {
+    await invoke('cmd_set_vad_config', { id, config });
+  },
+
+  async getSilenceSpans(id: string): Promise<SilenceSpan[]> {
+    return await invoke<SilenceSpan[]>('cmd_get_silence_spans', { id });
+  },
+
+  async getWaveform(id: string, buckets: number): Promise<WaveformPeaks> {
+    return await invoke<WaveformPeaks>('cmd_get_waveform', { id, buckets });
+  },
+
+  async search(query: string, limit?: number): Promise<SearchHit[]> {
+    return await invoke<SearchHit[]>('cmd_search', { query, limit });
+  },
+
+  // Playback operations
+  async playAudio(id: string): Promise<PlaybackState> {
+    return await invoke<PlaybackState>('cmd_play_audio', { id });
+  },
+
+  async playFrom(id: string, tMs: number): Promise<PlaybackState> {
+    return await invoke<PlaybackState>('cmd_play_from', { id, tMs });
+  },
+
+  async pauseAudio(): Promise<PlaybackState> {
+    return await invoke<PlaybackState>('cmd_pause_audio');
+  },
+
+  async resumeAudio(): Promise<PlaybackState> {
+    return await invoke<PlaybackState>('cmd_resume_audio');
+  },
+
+  async seekAudio(positionMs: number): Promise<PlaybackState> {
+    return await invoke<PlaybackState>('cmd_seek_audio', { positionMs });
+  },
+
+  async setPlaybackRate(rate: number): Promise<PlaybackState> {
+    return await invoke<PlaybackState>('cmd_set_playback_rate', { rate });
+  },
+
+  async stopAudio(): Promise<void> {
+    await invoke('cmd_stop_audio');
   },
 
   // Notes operations
@@ -0,0 +1,16 @@
+import { invoke } from '@tauri-apps/api/core';
+import { AppSettings, InputDeviceInfo } from '@/types';
+
+export const settingsClient = {
+  async getSettings(): Promise<AppSettings> {
+    return await invoke<AppSettings>('cmd_get_settings');
+  },
+
+  async saveSettings(settings: AppSettings): Promise<void> {
+    await invoke('cmd_save_settings', { settings });
+  },
+
+  async listInputDevices(): Promise<InputDeviceInfo[]> {
+    return await invoke<InputDeviceInfo[]>('cmd_list_input_devices');
+  }
+};
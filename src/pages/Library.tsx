@@ -10,7 +10,8 @@ import {
 } from 'lucide-react';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle, Button, Input, Select, SelectContent, SelectItem, SelectTrigger, SelectValue, ErrorMessage, useToast, ViewToggle, SessionList } from '@/components';
 import { useSessionsStore } from '@/store/sessions';
-import { Session } from '@/types/session';
+import { sessionsClient } from '@/lib/sessions';
+import { Session, Tag } from '@/types/session';
 import { PageTransition } from '@/components';
 import { useTabs } from '@/hooks/useTabs';
 
@@ -24,7 +25,10 @@ export default function Library() {
   const [sortBy, setSortBy] = useState<SortBy>('created_at');
   const [sortOrder, setSortOrder] = useState<SortOrder>('desc');
   const [statusFilter, setStatusFilter] = useState<StatusFilter>('all');
+  const [tagFilter, setTagFilter] = useState<string>('all');
   const [showFilters, setShowFilters] = useState(false);
+  // Sessions whose transcript or notes match the search
+  const [contentMatches, setContentMatches] = useState<Set<string>>(new Set());
   const { showError } = useToast();
   
   const { sessions, loading, error, load, delete: deleteSession, clearError } = useSessionsStore();
@@ -34,6 +38,31 @@ export default function Library() {
     load();
   }, [load]);
 
+  useEffect(() => {
+    const query = searchQuery.trim();
+    if (!query) {
+      setContentMatches(new Set());
+      return;
+    }
+
+    let cancelled = false;
+    const timeout = setTimeout(async () => {
+      try {
+        const hits = await sessionsClient.search(query, 200);
+        if (!cancelled) {
+          setContentMatches(new Set(hits.map(hit => hit.session_id)));
+        }
+      } catch (error) {
+        console.error('Failed to search sessions:', error);
+      }
+    }, 250);
+
+    return () => {
+      cancelled = true;
+      clearTimeout(timeout);
+    };
+  }, [searchQuery]);
+
   const filteredAndSortedSessions = useMemo(() => {
     let filtered = sessions;
 
@@ -42,7 +71,8 @@ export default function Library() {
       const query = searchQuery.toLowerCase();
       filtered = filtered.filter(session => 
         session.title.toLowerCase().includes(query) ||
-        session.course.toLowerCase().includes(query)
+        session.course.toLowerCase().includes(query) ||
+        contentMatches.has(session.id)
       );
     }
 
@@ -51,6 +81,11 @@ export default function Library() {
       filtered = filtered.filter(session => session.status.toLowerCase() === statusFilter);
     }
 
+    // Apply tag filter
+    if (tagFilter !== 'all') {
+      filtered = filtered.filter(session => session.tags.some(tag => tag.id === tagFilter));
+    }
+
     // Apply sorting
     filtered.sort((a, b) => {
       let aValue: any = a[sortBy];
@@ -70,7 +105,14 @@ export default function Library() {
     });
 
     return filtered;
-  }, [sessions, searchQuery, sortBy, sortOrder, statusFilter]);
+  }, [sessions, searchQuery, contentMatches, sortBy, sortOrder, statusFilter, tagFilter]);
+
+  // Every tag in use, for the tag filter
+  const tags = useMemo(() => {
+    const byId = new Map<string, Tag>();
+    sessions.forEach(session => session.tags.forEach(tag => byId.set(tag.id, tag)));
+    return [...byId.values()].sort((a, b) => a.name.localeCompare(b.name));
+  }, [sessions]);
 
   const handleSessionClick = (session: Session) => {
     openSessionTab(session.id, session.title);
@@ -101,9 +143,10 @@ export default function Library() {
     setSortBy('created_at');
     setSortOrder('desc');
     setStatusFilter('all');
+    setTagFilter('all');
   };
 
-  const hasActiveFilters = searchQuery.trim() || sortBy !== 'created_at' || sortOrder !== 'desc' || statusFilter !== 'all';
+  const hasActiveFilters = searchQuery.trim() || sortBy !== 'created_at' || sortOrder !== 'desc' || statusFilter !== 'all' || tagFilter !== 'all';
 
   return (
     <PageTransition>
@@ -215,6 +258,23 @@ export default function Library() {
                       </Select>
                     </div>
 
+                    {tags.length > 0 && (
+                      <div className="flex items-center gap-2">
+                        <label className="text-sm font-medium">Tag:</label>
+                        <Select value={tagFilter} onValueChange={setTagFilter}>
+                          <SelectTrigger className="w-36">
+                            <SelectValue />
+                          </SelectTrigger>
+                          <SelectContent>
+                            <SelectItem value="all">All</SelectItem>
+                            {tags.map(tag => (
+                              <SelectItem key={tag.id} value={tag.id}>{tag.name}</SelectItem>
+                            ))}
+                          </SelectContent>
+                        </Select>
+                      </div>
+                    )}
+
                     {hasActiveFilters && (
                       <Button
                         variant="ghost"
@@ -6,7 +6,7 @@ import { listen } from '@tauri-apps/api/event';
 import { ArrowLeft } from 'lucide-react';
 import { Button, ErrorBanner, PageTransition, VUMeter, NotionToolbar, NotionLayout, CatchUpSummaryModal } from '@/components';
 import { useSessionsStore } from '@/store/sessions';
-import { Session as SessionType, TranscriptLineData, convertTranscriptLine } from '@/types';
+import { Session as SessionType, AudioLevelEvent, InputWarning, InputWarningEvent, PlaybackState, TranscriptLineData, TranscriptLineEvent, convertTranscriptLine } from '@/types';
 import { sessionsClient } from '@/lib/sessions';
 import { useAutoSave } from '@/hooks';
 import { useFullscreen } from '@/contexts/FullscreenContext';
@@ -23,8 +23,10 @@ export default function Session() {
   const [showSummaryModal, setShowSummaryModal] = useState(false);
   const [audioLevel, setAudioLevel] = useState(0);
   const [recordingError, setRecordingError] = useState<string | null>(null);
+  const [inputWarnings, setInputWarnings] = useState<Partial<Record<InputWarning, string>>>({});
   const [isPlayingAudio, setIsPlayingAudio] = useState(false);
   const [playbackTimeRemaining, setPlaybackTimeRemaining] = useState(0);
+  const [activeLineIndex, setActiveLineIndex] = useState<number | null>(null);
   const { sessions, updateStatus, delete: deleteSession } = useSessionsStore();
   const { isFullscreen, toggleFullscreen } = useFullscreen();
   const recordingTimeRef = useRef(0);
@@ -85,7 +87,7 @@ export default function Session() {
 
     const setupAudioLevelListener = async () => {
       try {
-        unlisten = await listen<{ session_id: string; level: number }>('polka://audio-level', (event) => {
+        unlisten = await listen<AudioLevelEvent>('polka://audio-level', (event) => {
           if (event.payload.session_id === session?.id) {
             setAudioLevel(event.payload.level);
           }
@@ -109,6 +111,51 @@ export default function Session() {
     };
   }, [session?.id, isRecording, isPaused]);
 
+  // Surface a microphone that disappears mid-recording
+  useEffect(() => {
+    if (!session?.id || !isRecording) {
+      return;
+    }
+
+    const unlisten = listen<{ session_id: string; device: string; error: string }>('polka://audio-device-error', (event) => {
+      if (event.payload.session_id === session.id) {
+        setRecordingError(event.payload.error);
+        setAudioLevel(0);
+      }
+    });
+
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [session?.id, isRecording]);
+
+  // Warn about a muted or overdriven microphone while it can still be fixed
+  useEffect(() => {
+    if (!session?.id || !isRecording) {
+      setInputWarnings({});
+      return;
+    }
+
+    const unlisten = listen<InputWarningEvent>('polka://input-warning', (event) => {
+      if (event.payload.session_id === session.id) {
+        const { warning, active, message } = event.payload;
+        setInputWarnings(prev => {
+          const next = { ...prev };
+          if (active) {
+            next[warning] = message;
+          } else {
+            delete next[warning];
+          }
+          return next;
+        });
+      }
+    });
+
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [session?.id, isRecording]);
+
   const handleBack = () => {
     navigate('/app/home');
   };
@@ -175,7 +222,8 @@ export default function Session() {
       }
     } catch (error) {
       console.error('Error in toggleRecording:', error);
-      setRecordingError(error instanceof Error ? error.message : 'Recording failed');
+      // Backend commands reject with a plain string explaining what went wrong
+      setRecordingError(typeof error === 'string' ? error : error instanceof Error ? error.message : 'Recording failed');
       // Reset state on error
       setIsRecording(false);
       setIsPaused(false);
@@ -277,6 +325,15 @@ export default function Session() {
     }
   };
 
+  const applyPlaybackState = useCallback((state: PlaybackState) => {
+    const playing = state.is_playing && !state.ended;
+    setIsPlayingAudio(playing);
+    setPlaybackTimeRemaining(
+      playing ? Math.ceil((state.duration_ms - state.position_ms) / state.rate / 1000) : 0
+    );
+    setActiveLineIndex(playing ? state.active_line : null);
+  }, []);
+
   const handlePlayAudio = async () => {
     if (!session?.id) {
       setRecordingError('No session available');
@@ -285,43 +342,11 @@ export default function Session() {
 
     try {
       setRecordingError(null);
-      setIsPlayingAudio(true);
-      
-      console.log('🔊 Getting audio duration for session:', session.id);
-      // First get the audio duration
-      const duration = await invoke<number>('cmd_get_audio_duration', { id: session.id });
-      console.log('🔊 Audio duration:', duration, 'seconds');
-      
       console.log('🔊 Playing audio for session:', session.id);
-      // Then start playing the audio
-      await invoke('cmd_play_audio', { id: session.id });
-      
+      // Progress and the end of playback arrive as polka://playback-position events
+      const state = await sessionsClient.playAudio(session.id);
+      applyPlaybackState(state);
       console.log('🔊 Audio playback started successfully');
-      
-      // Set up countdown timer for remaining playback time
-      setPlaybackTimeRemaining(Math.ceil(duration));
-      
-      const countdownInterval = setInterval(() => {
-        setPlaybackTimeRemaining(prev => {
-          if (prev <= 1) {
-            clearInterval(countdownInterval);
-            setIsPlayingAudio(false);
-            console.log('🔊 Audio playback completed');
-            return 0;
-          }
-          return prev - 1;
-        });
-      }, 1000);
-      
-      // Fallback timeout in case countdown doesn't work perfectly
-      const durationMs = Math.ceil(duration * 1000) + 500; // Add 500ms buffer
-      setTimeout(() => {
-        clearInterval(countdownInterval);
-        setIsPlayingAudio(false);
-        setPlaybackTimeRemaining(0);
-        console.log('🔊 Audio playback completed (fallback)');
-      }, durationMs);
-      
     } catch (error) {
       console.error('Error playing audio:', error);
       setRecordingError(error instanceof Error ? error.message : 'Failed to play audio');
@@ -330,18 +355,33 @@ export default function Session() {
     }
   };
 
+  const handleTranscriptLineClick = async (line: TranscriptLineData) => {
+    if (!session?.id) return;
+
+    try {
+      setRecordingError(null);
+      const state = await sessionsClient.playFrom(session.id, line.startMs ?? line.timestamp * 1000);
+      applyPlaybackState(state);
+    } catch (error) {
+      console.error('Error playing audio from transcript line:', error);
+      setRecordingError(error instanceof Error ? error.message : 'Failed to play audio');
+    }
+  };
+
   const handleStopAudio = async () => {
     try {
       console.log('🔊 Stopping audio playback');
       await invoke('cmd_stop_audio');
       setIsPlayingAudio(false);
       setPlaybackTimeRemaining(0);
+      setActiveLineIndex(null);
       console.log('🔊 Audio playback stopped successfully');
     } catch (error) {
       console.error('Error stopping audio:', error);
       // Even if the backend stop fails, reset the UI state
       setIsPlayingAudio(false);
       setPlaybackTimeRemaining(0);
+      setActiveLineIndex(null);
     }
   };
 
@@ -353,41 +393,56 @@ export default function Session() {
   useEffect(() => {
     setIsPlayingAudio(false);
     setPlaybackTimeRemaining(0);
+    setActiveLineIndex(null);
   }, [session?.id]);
 
+  // Follow the native player while it plays this session
+  useEffect(() => {
+    if (!session?.id) return;
+
+    const unlisten = listen<PlaybackState>('polka://playback-position', (event) => {
+      if (event.payload.session_id === session.id) {
+        applyPlaybackState(event.payload);
+      }
+    });
+
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [session?.id, applyPlaybackState]);
+
   // Listen for real-time transcription events from Rust backend
   useEffect(() => {
     if (!session?.id) return;
 
-    const unlisten = listen<{ session_id: string; text: string; timestamp: number }>('polka://transcript-line', async (event) => {
+    const unlisten = listen<TranscriptLineEvent>('polka://transcript-line', (event) => {
       if (event.payload.session_id === session.id) {
-        console.log('🎤 Received transcript from backend:', event.payload.text);
-        
-        // Calculate recording time for consistent timestamps
-        const currentRecordingTime = recordingTimeRef.current;
-        
-        const newLine: TranscriptLineData = {
-          id: `line-${Date.now()}`,
-          timestamp: currentRecordingTime, // Use recording time for consistency
-          text: event.payload.text,
-          speaker: 'Speaker'
+        const { utterance_id, is_final, text, t_ms, end_ms, confidence, words, speaker } = event.payload;
+
+        const line: TranscriptLineData = {
+          id: `utterance-${utterance_id}`,
+          timestamp: Math.floor(t_ms / 1000),
+          text,
+          speaker,
+          endTimestamp: Math.floor(end_ms / 1000),
+          confidence: confidence ?? undefined,
+          words,
         };
-        
-        // Add to frontend state immediately
-        setTranscriptLines(prev => [...prev, newLine]);
 
-        // Save to backend
-        try {
-          console.log('💾 Saving real-time transcript to backend...');
-          await sessionsClient.appendTranscriptLine(
-            session.id,
-            currentRecordingTime * 1000, // Convert to ms
-            'Speaker',
-            event.payload.text
-          );
-          console.log('✅ Successfully saved real-time transcript to backend');
-        } catch (error) {
-          console.error('❌ Error saving real-time transcript:', error);
+        // Partial results for the same utterance replace each other until the final arrives.
+        // Final lines are saved to transcript.jsonl by the backend itself.
+        setTranscriptLines(prev => {
+          const existing = prev.findIndex(l => l.id === line.id);
+          if (existing === -1) {
+            return [...prev, line];
+          }
+          const next = [...prev];
+          next[existing] = { ...line, isBookmarked: prev[existing].isBookmarked };
+          return next;
+        });
+
+        if (is_final) {
+          console.log('🎤 Final transcript from backend:', text);
         }
       }
     });
@@ -462,6 +517,11 @@ export default function Session() {
           />
         )}
 
+        {/* Input Warnings - shown until the input recovers */}
+        {Object.entries(inputWarnings).map(([warning, message]) => (
+          <ErrorBanner key={warning} message={message} persistent />
+        ))}
+
         {/* Main Content - Notion Style */}
         <div className="flex-1 overflow-hidden">
           <NotionLayout
@@ -472,6 +532,8 @@ export default function Session() {
             sessionTitle={session?.title}
             isFullscreen={isFullscreen}
             editorRef={editorRef}
+            activeLineIndex={activeLineIndex}
+            onLineClick={handleTranscriptLineClick}
           />
         </div>
       </div>
@@ -12,13 +12,76 @@ import {
 } from 'lucide-react';
 import { Card, CardContent, CardDescription, CardHeader, CardTitle, Button, Switch, Select, SelectContent, SelectItem, SelectTrigger, SelectValue, Label, Separator } from '@/components';
 import { useTheme } from '@/lib/theme';
+import { settingsClient } from '@/lib/settings';
+import { AppSettings, InputDeviceInfo, RecordingFormat, StorageFormat } from '@/types';
 import { PageTransition } from '@/components';
 
 export default function Settings() {
   const { theme, toggleTheme } = useTheme();
   const [dataDir, setDataDir] = useState('~/.polka/data');
   const [consentReminder, setConsentReminder] = useState(true);
-  const [sampleRate, setSampleRate] = useState('44100');
+  const [settings, setSettings] = useState<AppSettings | null>(null);
+  const [inputDevices, setInputDevices] = useState<InputDeviceInfo[]>([]);
+
+  // Select items need a non-empty value, this one stands for "follow the system"
+  const SYSTEM_DEFAULT_DEVICE = '__system_default__';
+
+  React.useEffect(() => {
+    const loadRecordingSettings = async () => {
+      try {
+        const [savedSettings, devices] = await Promise.all([
+          settingsClient.getSettings(),
+          settingsClient.listInputDevices(),
+        ]);
+        setSettings(savedSettings);
+        setInputDevices(devices);
+      } catch (error) {
+        console.error('Failed to load recording settings:', error);
+      }
+    };
+
+    loadRecordingSettings();
+  }, []);
+
+  const handleInputDeviceChange = async (value: string) => {
+    if (!settings) return;
+
+    const updated = { ...settings, input_device: value === SYSTEM_DEFAULT_DEVICE ? null : value };
+    try {
+      await settingsClient.saveSettings(updated);
+      setSettings(updated);
+    } catch (error) {
+      console.error('Failed to save input device:', error);
+    }
+  };
+
+  const handleRecordingFormatChange = async (change: Partial<RecordingFormat>) => {
+    if (!settings) return;
+
+    const updated = { ...settings, recording_format: { ...settings.recording_format, ...change } };
+    try {
+      await settingsClient.saveSettings(updated);
+      setSettings(updated);
+    } catch (error) {
+      console.error('Failed to save recording format:', error);
+    }
+  };
+
+  const handleStorageFormatChange = async (value: string) => {
+    if (!settings) return;
+
+    const updated = { ...settings, storage_format: value as StorageFormat };
+    try {
+      await settingsClient.saveSettings(updated);
+      setSettings(updated);
+    } catch (error) {
+      console.error('Failed to save storage format:', error);
+    }
+  };
+
+  // A saved device that is currently unplugged stays selectable so it isn't silently dropped
+  const savedDeviceMissing = !!settings?.input_device &&
+    !inputDevices.some(device => device.name === settings.input_device);
 
   // Mock function to get data directory - would be replaced with actual Tauri command
   React.useEffect(() => {
@@ -38,12 +101,30 @@ export default function Settings() {
   };
 
   const sampleRateOptions = [
+    { value: '16000', label: '16 kHz' },
     { value: '44100', label: '44.1 kHz' },
     { value: '48000', label: '48 kHz' },
     { value: '96000', label: '96 kHz' },
     { value: '192000', label: '192 kHz' },
   ];
 
+  const sampleFormatOptions = [
+    { value: 'int16', label: '16-bit' },
+    { value: 'int24', label: '24-bit' },
+    { value: 'float32', label: '32-bit float' },
+  ];
+
+  const storageFormatOptions = [
+    { value: 'wav', label: 'WAV (uncompressed)' },
+    { value: 'flac', label: 'FLAC (lossless, about half the size)' },
+    { value: 'opus', label: 'Opus (compact, for speech)' },
+  ];
+
+  const channelOptions = [
+    { value: '1', label: 'Mono' },
+    { value: '2', label: 'Stereo' },
+  ];
+
   return (
     <PageTransition>
       <div className="max-w-4xl mx-auto p-6 space-y-8">
@@ -201,10 +282,48 @@ export default function Settings() {
                   </CardDescription>
                 </CardHeader>
                 <CardContent className="space-y-4">
+                  <div className="space-y-2">
+                    <Label className="text-base">Microphone</Label>
+                    <Select
+                      value={settings?.input_device ?? SYSTEM_DEFAULT_DEVICE}
+                      onValueChange={handleInputDeviceChange}
+                      disabled={!settings}
+                    >
+                      <SelectTrigger className="w-full">
+                        <SelectValue placeholder="Select microphone" />
+                      </SelectTrigger>
+                      <SelectContent>
+                        <SelectItem value={SYSTEM_DEFAULT_DEVICE}>System default</SelectItem>
+                        {inputDevices.map((device) => (
+                          <SelectItem key={device.name} value={device.name}>
+                            {device.name}{device.is_default ? ' (default)' : ''}
+                          </SelectItem>
+                        ))}
+                        {savedDeviceMissing && settings?.input_device && (
+                          <SelectItem value={settings.input_device}>
+                            {settings.input_device} (not connected)
+                          </SelectItem>
+                        )}
+                      </SelectContent>
+                    </Select>
+                    <div className="text-xs text-muted-foreground">
+                      Input device used for new recordings.
+                      {savedDeviceMissing && (
+                        <span className="text-amber-600 dark:text-amber-400 ml-1">
+                          The selected microphone is not connected; recording will fail until it is plugged back in.
+                        </span>
+                      )}
+                    </div>
+                  </div>
+                  <Separator />
                   <div className="space-y-2">
                     <Label className="text-base">Sample Rate</Label>
-                    <Select value={sampleRate} onValueChange={setSampleRate} disabled>
-                      <SelectTrigger className="w-full opacity-50">
+                    <Select
+                      value={settings ? String(settings.recording_format.sample_rate) : undefined}
+                      onValueChange={(value) => handleRecordingFormatChange({ sample_rate: Number(value) })}
+                      disabled={!settings}
+                    >
+                      <SelectTrigger className="w-full">
                         <SelectValue placeholder="Select sample rate" />
                       </SelectTrigger>
                       <SelectContent>
@@ -217,9 +336,72 @@ export default function Settings() {
                     </Select>
                     <div className="text-xs text-muted-foreground">
                       Audio sample rate for recordings. Higher rates provide better quality but larger files.
-                      <span className="text-amber-600 dark:text-amber-400 ml-1">
-                        (Currently disabled - coming soon)
-                      </span>
+                    </div>
+                  </div>
+                  <div className="grid grid-cols-2 gap-4">
+                    <div className="space-y-2">
+                      <Label className="text-base">Bit Depth</Label>
+                      <Select
+                        value={settings?.recording_format.sample_format}
+                        onValueChange={(value) => handleRecordingFormatChange({ sample_format: value as RecordingFormat['sample_format'] })}
+                        disabled={!settings}
+                      >
+                        <SelectTrigger className="w-full">
+                          <SelectValue placeholder="Select bit depth" />
+                        </SelectTrigger>
+                        <SelectContent>
+                          {sampleFormatOptions.map((option) => (
+                            <SelectItem key={option.value} value={option.value}>
+                              {option.label}
+                            </SelectItem>
+                          ))}
+                        </SelectContent>
+                      </Select>
+                    </div>
+                    <div className="space-y-2">
+                      <Label className="text-base">Channels</Label>
+                      <Select
+                        value={settings ? String(settings.recording_format.channels) : undefined}
+                        onValueChange={(value) => handleRecordingFormatChange({ channels: Number(value) })}
+                        disabled={!settings}
+                      >
+                        <SelectTrigger className="w-full">
+                          <SelectValue placeholder="Select channels" />
+                        </SelectTrigger>
+                        <SelectContent>
+                          {channelOptions.map((option) => (
+                            <SelectItem key={option.value} value={option.value}>
+                              {option.label}
+                            </SelectItem>
+                          ))}
+                        </SelectContent>
+                      </Select>
+                    </div>
+                  </div>
+                  <div className="text-xs text-muted-foreground">
+                    Applies to new recordings. A paused recording keeps the format it started with.
+                  </div>
+                  <Separator />
+                  <div className="space-y-2">
+                    <Label className="text-base">Storage Format</Label>
+                    <Select
+                      value={settings?.storage_format}
+                      onValueChange={handleStorageFormatChange}
+                      disabled={!settings}
+                    >
+                      <SelectTrigger className="w-full">
+                        <SelectValue placeholder="Select storage format" />
+                      </SelectTrigger>
+                      <SelectContent>
+                        {storageFormatOptions.map((option) => (
+                          <SelectItem key={option.value} value={option.value}>
+                            {option.label}
+                          </SelectItem>
+                        ))}
+                      </SelectContent>
+                    </Select>
+                    <div className="text-xs text-muted-foreground">
+                      Recordings are compressed when they are stopped. An hour of 16 kHz mono speech takes about 115 MB as WAV and 15 MB as Opus.
                     </div>
                   </div>
                 </CardContent>
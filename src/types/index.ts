@@ -1,3 +1,4 @@
 // Re-export all types from their respective modules
 export * from './session';
 export * from './transcript';
+export * from './settings';
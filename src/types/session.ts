@@ -1,20 +1,114 @@
 export interface Session {
   id: string;
   title: string;
+  // Name of the course the session is filed under, or '' for none
   course: string;
+  course_id: string | null;
   created_at: number;
   duration_ms: number;
   status: SessionStatus;
   notes_path: string | null;
   audio_path: string | null;
   transcript_path: string | null;
+  // Set when an interrupted recording was stitched back together on launch
+  recovered: boolean;
+  tags: Tag[];
+}
+
+export interface Tag {
+  id: string;
+  name: string;
+  created_at: number;
+}
+
+// Sessions carrying all of tags (tag ids), or any of them with any_tag
+export interface SessionFilter {
+  tags?: string[];
+  any_tag?: boolean;
+}
+
+// Emitted as polka://audio-level about 20 times a second while recording
+export interface AudioLevelEvent {
+  session_id: string;
+  // Smoothed 0-1 level for the VU meter
+  level: number;
+  rms_db: number;
+  true_peak_db: number;
+  clipping: boolean;
+}
+
+export type InputWarning = 'silence' | 'clipping';
+
+// Emitted as polka://input-warning when a warning starts (active) and when it clears
+export interface InputWarningEvent {
+  session_id: string;
+  warning: InputWarning;
+  active: boolean;
+  message: string;
+}
+
+// Emitted as polka://playback-position while a recording plays
+export interface PlaybackState {
+  session_id: string;
+  position_ms: number;
+  duration_ms: number;
+  rate: number;
+  is_playing: boolean;
+  ended: boolean;
+  // Index in the transcript of the line being heard
+  active_line: number | null;
+}
+
+// Min/max peaks of a recording; entry i covers the i-th of min.length equal slices
+export interface WaveformPeaks {
+  duration_ms: number;
+  min: number[];
+  max: number[];
+}
+
+export type SearchSource = 'transcript' | 'notes';
+
+// A match from cmd_search; highlights are [start, end) offsets into snippet
+export interface SearchHit {
+  session_id: string;
+  source: SearchSource;
+  snippet: string;
+  highlights: [number, number][];
+  t_ms: number | null;
+}
+
+export interface Course {
+  id: string;
+  name: string;
+  code: string | null;
+  term: string | null;
+  // '#rrggbb'
+  color: string | null;
+  instructor: string | null;
+  created_at: number;
+}
+
+export interface CourseInput {
+  name: string;
+  code?: string | null;
+  term?: string | null;
+  color?: string | null;
+  instructor?: string | null;
+}
+
+export interface CourseStats {
+  course_id: string;
+  session_count: number;
+  total_duration_ms: number;
 }
 
 export type SessionStatus = 'draft' | 'recording' | 'complete' | 'archived';
 
 export interface CreateSessionRequest {
   title: string;
+  // Joins the course of this name, creating it if there is none
   course: string;
+  courseId?: string;
 }
 
 export interface UpdateSessionStatusRequest {
@@ -26,4 +120,29 @@ export interface TranscriptLine {
   t_ms: number;
   speaker: string;
   text: string;
+  end_ms?: number;
+  confidence?: number;
+  words?: TranscriptWord[];
+}
+
+export interface TranscriptWord {
+  text: string;
+  t_ms: number;
+  end_ms: number;
+  confidence?: number;
+}
+
+// Per-session voice activity detection settings (stored as vad.json)
+export interface VadConfig {
+  threshold_db: number;
+  min_speech_ms: number;
+  min_silence_ms: number;
+  padding_ms: number;
+  trim_silence: boolean;
+  max_silence_ms: number;
+}
+
+export interface SilenceSpan {
+  start_ms: number;
+  end_ms: number;
 }
@@ -0,0 +1,24 @@
+// App-wide preferences (stored in ~/.polka/settings.json)
+export interface AppSettings {
+  input_device: string | null;
+  recording_format: RecordingFormat;
+  storage_format: StorageFormat;
+}
+
+// How finished recordings are kept: uncompressed, lossless or compact
+export type StorageFormat = 'wav' | 'flac' | 'opus';
+
+export type SampleFormat = 'int16' | 'int24' | 'float32';
+
+export interface RecordingFormat {
+  sample_rate: number;
+  sample_format: SampleFormat;
+  channels: number;
+}
+
+export interface InputDeviceInfo {
+  name: string;
+  is_default: boolean;
+  channels: number;
+  sample_rates: number[];
+}
@@ -1,11 +1,33 @@
-import { TranscriptLine as BackendTranscriptLine } from './session';
+import { TranscriptLine as BackendTranscriptLine, TranscriptWord } from './session';
 
 export interface TranscriptLineData {
   id: string;
   timestamp: number;
+  // Exact start for seeking playback; only set on lines loaded from the transcript file
+  startMs?: number;
   text: string;
   speaker?: string;
   isBookmarked?: boolean;
+  endTimestamp?: number;
+  confidence?: number;
+  words?: TranscriptWord[];
+}
+
+/**
+ * Payload of the `polka://transcript-line` event emitted during recording.
+ * Partial results share an `utterance_id` with the final result that replaces them.
+ */
+export interface TranscriptLineEvent {
+  session_id: string;
+  utterance_id: string;
+  is_final: boolean;
+  speaker: string;
+  text: string;
+  t_ms: number;
+  end_ms: number;
+  confidence: number | null;
+  words: TranscriptWord[];
+  timestamp: number;
 }
 
 /**
@@ -20,7 +42,11 @@ export const convertTranscriptLine = (
 ): TranscriptLineData => ({
   id: `line-${backendLine.t_ms}-${index}`,
   timestamp: Math.floor(backendLine.t_ms / 1000), // Convert ms to seconds
+  startMs: backendLine.t_ms,
   text: backendLine.text,
   speaker: backendLine.speaker,
   isBookmarked: false,
+  endTimestamp: backendLine.end_ms !== undefined ? Math.floor(backendLine.end_ms / 1000) : undefined,
+  confidence: backendLine.confidence,
+  words: backendLine.words,
 });